@@ -23,10 +23,15 @@
 // subblocks, measured in subtime.
 // * Round: a single interval duration. One aggregator could issue more than one
 // subblock in its round.
+// * Identity: identifies an aggregator. By default an identity is a lock script
+// hash, and the aggregator proves itself by including an input cell using the
+// lock. Alternatively an identity can be a secp256k1 public key hash, and the
+// aggregator signs the transaction in the witness of the PoA cell.
 
 // As always, we will need those headers to interact with CKB.
 #include "blake2b.h"
 #include "blockchain.h"
+#include "ckb_dlfcn.h"
 #include "ckb_syscalls.h"
 
 #define SCRIPT_BUFFER_SIZE 128
@@ -36,10 +41,72 @@
 #define CODE_SIZE (256 * 1024)
 #define PREFILLED_DATA_SIZE (1024 * 1024)
 #define IDENTITY_SIZE 32
+#define BLAKE160_SIZE 20
+#define SIGNATURE_SIZE 65
+#define MAX_SIGNATURES 255
+// Code hash and hash type of the signature library
+#define SIGNATURE_LIBRARY_SIZE 33
+// PoA data is 22 bytes long, optionally followed by one u32 counter of missed
+// rounds for each aggregator.
+#define POA_DATA_SIZE 22
+#define MISSED_ROUNDS_SIZE 4
+#define MAX_MISSED_ROUNDS 0xFFFFFFFF
+#define POA_DATA_BUFFER_SIZE (POA_DATA_SIZE + 255 * MISSED_ROUNDS_SIZE)
+// Versioned PoA setups start with a byte holding the version with its highest
+// bit set, see poa_setup.mol.
+#define POA_SETUP_VERSIONED_FLAG 0x80
+#define POA_SETUP_VERSION_1 1
+// Fields every PoASetupV1 table has, fields appended later are optional.
+#define POA_SETUP_V1_REQUIRED_FIELD_COUNT 7
+#define POA_SETUP_V1_FIELD_COUNT 13
+#define POA_SETUP_V1_PAUSED_FIELD 7
+#define POA_SETUP_V1_PROPOSAL_FIELD 12
+// A proposal holds the u64 activation subtime, followed by the proposed setup.
+#define PROPOSAL_HEADER_SIZE 8
 
 #define ERROR_TRANSACTION -1
 #define ERROR_ENCODING -2
 #define ERROR_DYNAMIC_LOADING -3
+// Each rejection of a PoA transaction has its own exit code, so a failing
+// transaction can be diagnosed without a debug build. Keep those in sync with
+// `PoaError` in the Rust library.
+#define ERROR_INVALID_ARGS -4
+#define ERROR_SETUP_TOO_SHORT -5
+#define ERROR_INVALID_IDENTITY_SIZE -6
+#define ERROR_INVALID_CHANGE_THRESHOLD -7
+#define ERROR_SETUP_LENGTH_MISMATCH -8
+#define ERROR_SETUP_CELL_TOO_LARGE -9
+#define ERROR_DUPLICATE_POA_CELL -10
+#define ERROR_POA_SETUP_CELL_MISSING -11
+#define ERROR_POA_DATA_CELL_MISSING -12
+#define ERROR_INVALID_POA_DATA -13
+#define ERROR_INVALID_AGGREGATOR_INDEX -14
+#define ERROR_INVALID_SINCE -15
+#define ERROR_SUBTIME_MISMATCH -16
+#define ERROR_INVALID_ROUND_INITIAL_SUBTIME -17
+#define ERROR_SUBTIME_DECREASED -18
+#define ERROR_AGGREGATOR_CHANGED -19
+#define ERROR_INVALID_SUBBLOCK_INDEX -20
+#define ERROR_ROUND_NOT_STARTED -21
+#define ERROR_OWNER_NOT_FOUND -22
+#define ERROR_NOT_ENOUGH_APPROVALS -23
+#define ERROR_NO_IDENTITY -24
+#define ERROR_THRESHOLD_BELOW_MINIMUM -25
+#define ERROR_POA_DATA_NOT_RESET -26
+#define ERROR_INVALID_WITNESS -27
+#define ERROR_INVALID_SIGNATURE -28
+#define ERROR_UNSUPPORTED_SETUP_VERSION -29
+#define ERROR_POA_PAUSED -30
+#define ERROR_INVALID_MISSED_ROUNDS -31
+#define ERROR_INVALID_WEIGHTS -32
+#define ERROR_INVALID_VOTING_WEIGHTS -33
+#define ERROR_PROPOSAL_REQUIRED -34
+#define ERROR_INVALID_PROPOSAL -35
+#define ERROR_PROPOSAL_NOT_ACTIVE -36
+
+// Unless specified in PoA lock args, a setup update cannot lower
+// aggregator_change_threshold to 0, which would freeze the setup for good.
+#define DEFAULT_MINIMUM_CHANGE_THRESHOLD 1
 
 #ifdef ENABLE_DEBUG_MODE
 #define DEBUG(s) ckb_debug(s)
@@ -58,18 +125,234 @@ typedef struct {
   uint32_t round_intervals;
   uint32_t subblocks_per_round;
   const uint8_t *identities;
+  int signature_identities;
+  const uint8_t *signature_library;
+  // Set by governance to halt subblock production, setup updates are still
+  // allowed so as to resume.
+  int paused;
+  // One weight for each aggregator, or NULL when all aggregators have weight
+  // 1. An aggregator with weight w owns w consecutive slots: its rounds last
+  // w * round_intervals, and hold up to w * subblocks_per_round subblocks.
+  const uint8_t *weights;
+  // One little endian u32 voting weight for each aggregator, or NULL when
+  // setup updates use aggregator_change_threshold as a head count. When
+  // present, a setup update needs approvals whose voting weights add up to
+  // voting_threshold.
+  const uint8_t *voting_weights;
+  uint32_t voting_threshold;
+  // When not 0, setup changes are time locked: they are first proposed, and
+  // can only be committed proposal_delay after the proposal.
+  uint32_t proposal_delay;
+  // Pending proposal, or NULL when there is none.
+  const uint8_t *proposal;
+  size_t proposal_size;
 } PoASetup;
 
-int parse_poa_setup(const uint8_t *source_data, size_t source_length,
-                    PoASetup *output) {
-  if (source_length < 12) {
-    DEBUG("PoA data have invalid length!");
+// Aggregator index wraps around like in the steps calculation.
+uint64_t aggregator_weight(const PoASetup *poa_setup, uint64_t index) {
+  if (poa_setup->weights == NULL) {
+    return 1;
+  }
+  return (uint64_t)poa_setup->weights[index % poa_setup->aggregator_number];
+}
+
+// The molecule table follows the version byte, hence numbers in it are not
+// aligned, and are read byte by byte.
+uint32_t unpack_unaligned_u32(const uint8_t *src) {
+  return (uint32_t)src[0] | ((uint32_t)src[1] << 8) |
+         ((uint32_t)src[2] << 16) | ((uint32_t)src[3] << 24);
+}
+
+uint64_t unpack_unaligned_u64(const uint8_t *src) {
+  return (uint64_t)unpack_unaligned_u32(src) |
+         ((uint64_t)unpack_unaligned_u32(&src[4]) << 32);
+}
+
+// Verifies the PoASetupV1 molecule table, and slices its fields. Optional
+// fields missing from the table are left empty, extra fields appended in later
+// versions are accepted and ignored.
+int parse_poa_setup_v1_fields(const mol_seg_t *table,
+                              mol_seg_t fields[POA_SETUP_V1_FIELD_COUNT]) {
+  if (table->size < MOL_NUM_T_SIZE * 2) {
     return ERROR_ENCODING;
   }
-  output->_source_data = source_data;
-  output->_source_length = source_length;
+  mol_num_t total_size = unpack_unaligned_u32(table->ptr);
+  mol_num_t header_size = unpack_unaligned_u32(&table->ptr[MOL_NUM_T_SIZE]);
+  if (total_size != table->size || header_size % MOL_NUM_T_SIZE != 0 ||
+      header_size > total_size ||
+      header_size < MOL_NUM_T_SIZE * (POA_SETUP_V1_REQUIRED_FIELD_COUNT + 1)) {
+    return ERROR_ENCODING;
+  }
+  mol_num_t field_count = header_size / MOL_NUM_T_SIZE - 1;
+  mol_num_t start = header_size;
+  for (mol_num_t i = 0; i < field_count; i++) {
+    mol_num_t end = total_size;
+    if (i + 1 < field_count) {
+      end = unpack_unaligned_u32(&table->ptr[MOL_NUM_T_SIZE * (i + 2)]);
+    }
+    if (unpack_unaligned_u32(&table->ptr[MOL_NUM_T_SIZE * (i + 1)]) != start ||
+        end < start || end > total_size) {
+      return ERROR_ENCODING;
+    }
+    if (i < POA_SETUP_V1_FIELD_COUNT) {
+      fields[i].ptr = &table->ptr[start];
+      fields[i].size = end - start;
+    }
+    start = end;
+  }
+  for (mol_num_t i = field_count; i < POA_SETUP_V1_FIELD_COUNT; i++) {
+    fields[i].ptr = NULL;
+    fields[i].size = 0;
+  }
+  if (fields[0].size != 1 || fields[0].ptr[0] > 1 || fields[1].size != 1 ||
+      fields[2].size != 1 || fields[3].size != 4 || fields[4].size != 4 ||
+      fields[5].size < MOL_NUM_T_SIZE ||
+      unpack_unaligned_u32(fields[5].ptr) != fields[5].size - MOL_NUM_T_SIZE ||
+      (fields[6].size != 0 && fields[6].size != SIGNATURE_LIBRARY_SIZE) ||
+      (fields[7].size != 0 && (fields[7].size != 1 || fields[7].ptr[0] > 1)) ||
+      (fields[8].size != 0 &&
+       (fields[8].size < MOL_NUM_T_SIZE ||
+        unpack_unaligned_u32(fields[8].ptr) !=
+            fields[8].size - MOL_NUM_T_SIZE)) ||
+      (fields[9].size != 0 &&
+       (fields[9].size < MOL_NUM_T_SIZE ||
+        (uint64_t)unpack_unaligned_u32(fields[9].ptr) * 4 !=
+            fields[9].size - MOL_NUM_T_SIZE)) ||
+      (fields[10].size != 0 && fields[10].size != 4) ||
+      (fields[11].size != 0 && fields[11].size != 4) ||
+      (fields[12].size != 0 &&
+       (fields[12].size < MOL_NUM_T_SIZE ||
+        unpack_unaligned_u32(fields[12].ptr) !=
+            fields[12].size - MOL_NUM_T_SIZE ||
+        (fields[12].size > MOL_NUM_T_SIZE &&
+         fields[12].size < MOL_NUM_T_SIZE + PROPOSAL_HEADER_SIZE)))) {
+    return ERROR_ENCODING;
+  }
+  return CKB_SUCCESS;
+}
+
+int parse_poa_setup_v1(const uint8_t *source_data, size_t source_length,
+                       PoASetup *output) {
+  if ((source_data[0] & ~POA_SETUP_VERSIONED_FLAG) != POA_SETUP_VERSION_1) {
+    DEBUG("Unsupported PoA setup version!");
+    return ERROR_UNSUPPORTED_SETUP_VERSION;
+  }
+  mol_seg_t table;
+  table.ptr = (uint8_t *)&source_data[1];
+  table.size = source_length - 1;
+  mol_seg_t fields[POA_SETUP_V1_FIELD_COUNT];
+  int ret = parse_poa_setup_v1_fields(&table, fields);
+  if (ret != CKB_SUCCESS) {
+    DEBUG("Invalid PoA setup encoding!");
+    return ret;
+  }
+  mol_seg_t identities_seg;
+  identities_seg.ptr = &fields[5].ptr[MOL_NUM_T_SIZE];
+  identities_seg.size = fields[5].size - MOL_NUM_T_SIZE;
+
+  output->round_interval_uses_seconds = fields[0].ptr[0] == 1;
+  output->identity_size = fields[1].ptr[0];
+  output->aggregator_change_threshold = fields[2].ptr[0];
+  output->round_intervals = unpack_unaligned_u32(fields[3].ptr);
+  output->subblocks_per_round = unpack_unaligned_u32(fields[4].ptr);
+  output->identities = identities_seg.ptr;
+  output->signature_identities = fields[6].size != 0;
+  output->signature_library =
+      output->signature_identities ? fields[6].ptr : NULL;
+  output->paused = fields[7].size == 1 && fields[7].ptr[0] == 1;
+  output->weights = NULL;
+  output->voting_weights = NULL;
+  output->voting_threshold =
+      fields[10].size == 4 ? unpack_unaligned_u32(fields[10].ptr) : 0;
+  output->proposal_delay =
+      fields[11].size == 4 ? unpack_unaligned_u32(fields[11].ptr) : 0;
+  output->proposal = NULL;
+  output->proposal_size = 0;
+  if (fields[12].size > MOL_NUM_T_SIZE) {
+    output->proposal = &fields[12].ptr[MOL_NUM_T_SIZE];
+    output->proposal_size = fields[12].size - MOL_NUM_T_SIZE;
+  }
 
+  if (output->identity_size > IDENTITY_SIZE ||
+      (output->signature_identities &&
+       output->identity_size > BLAKE160_SIZE)) {
+    DEBUG("Invalid identity size!");
+    return ERROR_INVALID_IDENTITY_SIZE;
+  }
+  // Aggregator number is derived from the length of identities
+  size_t aggregator_number = 0;
+  if (output->identity_size == 0) {
+    if (identities_seg.size != 0) {
+      DEBUG("PoA setup have invalid length!");
+      return ERROR_SETUP_LENGTH_MISMATCH;
+    }
+  } else {
+    aggregator_number = identities_seg.size / output->identity_size;
+    if (identities_seg.size % output->identity_size != 0 ||
+        aggregator_number > 255) {
+      DEBUG("PoA setup have invalid length!");
+      return ERROR_SETUP_LENGTH_MISMATCH;
+    }
+  }
+  output->aggregator_number = (uint8_t)aggregator_number;
+  if (output->aggregator_change_threshold > output->aggregator_number) {
+    DEBUG("Invalid aggregator change threshold!");
+    return ERROR_INVALID_CHANGE_THRESHOLD;
+  }
+  // Empty weights mean all aggregators have weight 1
+  if (fields[8].size > MOL_NUM_T_SIZE) {
+    if (fields[8].size - MOL_NUM_T_SIZE != aggregator_number) {
+      DEBUG("Invalid number of weights!");
+      return ERROR_INVALID_WEIGHTS;
+    }
+    output->weights = &fields[8].ptr[MOL_NUM_T_SIZE];
+    for (size_t i = 0; i < aggregator_number; i++) {
+      if (output->weights[i] == 0) {
+        DEBUG("Weight cannot be 0!");
+        return ERROR_INVALID_WEIGHTS;
+      }
+    }
+  }
+  // Empty voting weights keep the head count of aggregator_change_threshold,
+  // otherwise the voting threshold must be reachable, and cannot be 0.
+  if (fields[9].size > MOL_NUM_T_SIZE) {
+    if (unpack_unaligned_u32(fields[9].ptr) != aggregator_number) {
+      DEBUG("Invalid number of voting weights!");
+      return ERROR_INVALID_VOTING_WEIGHTS;
+    }
+    output->voting_weights = &fields[9].ptr[MOL_NUM_T_SIZE];
+    uint64_t total_voting_weight = 0;
+    for (size_t i = 0; i < aggregator_number; i++) {
+      total_voting_weight +=
+          (uint64_t)unpack_unaligned_u32(&output->voting_weights[i * 4]);
+    }
+    if (output->voting_threshold == 0 ||
+        (uint64_t)output->voting_threshold > total_voting_weight) {
+      DEBUG("Invalid voting threshold!");
+      return ERROR_INVALID_VOTING_WEIGHTS;
+    }
+  } else if (output->voting_threshold != 0) {
+    DEBUG("Voting threshold requires voting weights!");
+    return ERROR_INVALID_VOTING_WEIGHTS;
+  }
+  return CKB_SUCCESS;
+}
+
+int parse_poa_setup_v0(const uint8_t *source_data, size_t source_length,
+                       PoASetup *output) {
+  if (source_length < 12) {
+    DEBUG("PoA setup is too short!");
+    return ERROR_SETUP_TOO_SHORT;
+  }
   output->round_interval_uses_seconds = (source_data[0] & 1) == 1;
+  output->signature_identities = (source_data[0] & 2) == 2;
+  output->paused = (source_data[0] & 4) == 4;
+  output->weights = NULL;
+  output->voting_weights = NULL;
+  output->voting_threshold = 0;
+  output->proposal_delay = 0;
+  output->proposal = NULL;
+  output->proposal_size = 0;
   output->identity_size = source_data[1];
   output->aggregator_number = source_data[2];
   output->aggregator_change_threshold = source_data[3];
@@ -77,35 +360,253 @@ int parse_poa_setup(const uint8_t *source_data, size_t source_length,
   output->subblocks_per_round = *((uint32_t *)(&source_data[8]));
   output->identities = &source_data[12];
 
-  if (output->identity_size > IDENTITY_SIZE) {
+  if (output->identity_size > IDENTITY_SIZE ||
+      (output->signature_identities &&
+       output->identity_size > BLAKE160_SIZE)) {
     DEBUG("Invalid identity size!");
-    return ERROR_ENCODING;
+    return ERROR_INVALID_IDENTITY_SIZE;
   }
   if (output->aggregator_change_threshold > output->aggregator_number) {
     DEBUG("Invalid aggregator change threshold!");
-    return ERROR_ENCODING;
+    return ERROR_INVALID_CHANGE_THRESHOLD;
   }
-  if (source_length !=
-      12 + (size_t)output->identity_size * (size_t)output->aggregator_number) {
-    DEBUG("PoA data have invalid length!");
-    return ERROR_ENCODING;
+  // Signature library follows identities
+  size_t identities_length =
+      (size_t)output->identity_size * (size_t)output->aggregator_number;
+  size_t library_length =
+      output->signature_identities ? SIGNATURE_LIBRARY_SIZE : 0;
+  if (source_length != 12 + identities_length + library_length) {
+    DEBUG("PoA setup have invalid length!");
+    return ERROR_SETUP_LENGTH_MISMATCH;
   }
+  output->signature_library =
+      output->signature_identities ? &source_data[12 + identities_length]
+                                   : NULL;
   return CKB_SUCCESS;
 }
 
-int validate_consensus_signing(const uint8_t *identity_buffer,
+int parse_poa_setup(const uint8_t *source_data, size_t source_length,
+                    PoASetup *output) {
+  output->_source_data = source_data;
+  output->_source_length = source_length;
+  if (source_length > 0 && (source_data[0] & POA_SETUP_VERSIONED_FLAG) != 0) {
+    return parse_poa_setup_v1(source_data, source_length, output);
+  }
+  return parse_poa_setup_v0(source_data, source_length, output);
+}
+
+// Signers of a PoA transaction, either identified by lock hashes of input
+// cells, or by public key hashes recovered from signatures in the PoA cell
+// witness.
+typedef struct {
+  int use_signatures;
+  size_t count;
+  uint8_t pubkey_hashes[MAX_SIGNATURES * BLAKE160_SIZE];
+} Signers;
+
+typedef int (*LoadPrefilledDataFn)(void *data, size_t *len);
+typedef int (*ValidateSignatureFn)(void *prefilled_data,
+                                   const uint8_t *signature_buffer,
+                                   size_t signature_size,
+                                   const uint8_t *message_buffer,
+                                   size_t message_size, uint8_t *output,
+                                   size_t *output_len);
+
+static uint8_t code_buffer[CODE_SIZE] __attribute__((aligned(RISCV_PGSIZE)));
+static uint8_t prefilled_data_buffer[PREFILLED_DATA_SIZE];
+static uint8_t witness_buffer[SIGNATURE_WITNESS_BUFFER_SIZE];
+static uint8_t signatures_buffer[MAX_SIGNATURES * SIGNATURE_SIZE];
+
+int load_and_hash_witness(blake2b_state *ctx, size_t index, size_t source) {
+  uint8_t temp[ONE_BATCH_SIZE];
+  uint64_t len = ONE_BATCH_SIZE;
+  int ret = ckb_load_witness(temp, &len, 0, index, source);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  blake2b_update(ctx, (char *)&len, sizeof(uint64_t));
+  uint64_t offset = (len > ONE_BATCH_SIZE) ? ONE_BATCH_SIZE : len;
+  blake2b_update(ctx, temp, offset);
+  while (offset < len) {
+    uint64_t current_len = ONE_BATCH_SIZE;
+    ret = ckb_load_witness(temp, &current_len, offset, index, source);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    uint64_t current_read =
+        (current_len > ONE_BATCH_SIZE) ? ONE_BATCH_SIZE : current_len;
+    blake2b_update(ctx, temp, current_read);
+    offset += current_read;
+  }
+  return CKB_SUCCESS;
+}
+
+// The signing message is calculated the same way as the default secp256k1
+// lock: transaction hash, the PoA cell witness with its lock field
+// zero-filled, then all witnesses not covered by any input.
+int calculate_signing_message(uint8_t *witness, uint64_t witness_len,
+                              uint8_t message[32]) {
+  uint8_t tx_hash[32];
+  uint64_t len = 32;
+  int ret = ckb_load_tx_hash(tx_hash, &len, 0);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  blake2b_state ctx;
+  blake2b_init(&ctx, 32);
+  blake2b_update(&ctx, tx_hash, 32);
+  blake2b_update(&ctx, (char *)&witness_len, sizeof(uint64_t));
+  blake2b_update(&ctx, witness, witness_len);
+
+  size_t input_count = 0;
+  while (1) {
+    len = 0;
+    ret = ckb_load_input_by_field(NULL, &len, 0, input_count, CKB_SOURCE_INPUT,
+                                  CKB_INPUT_FIELD_SINCE);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      break;
+    }
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    input_count++;
+  }
+  size_t current = input_count;
+  while (1) {
+    ret = load_and_hash_witness(&ctx, current, CKB_SOURCE_INPUT);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      break;
+    }
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    current++;
+  }
+  blake2b_final(&ctx, message, 32);
+  return CKB_SUCCESS;
+}
+
+// When identities are public key hashes, the lock field of the PoA cell
+// witness holds one or more 65-byte recoverable signatures of the signing
+// message. Signatures are validated by the library referenced in PoA setup,
+// which is loaded from cell deps.
+int load_signers(const PoASetup *poa_setup, Signers *signers) {
+  signers->use_signatures = poa_setup->signature_identities;
+  signers->count = 0;
+  if (!signers->use_signatures) {
+    return CKB_SUCCESS;
+  }
+
+  uint64_t witness_len = SIGNATURE_WITNESS_BUFFER_SIZE;
+  int ret = ckb_load_witness(witness_buffer, &witness_len, 0, 0,
+                             CKB_SOURCE_GROUP_INPUT);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("PoA cell witness is missing!");
+    return ERROR_INVALID_WITNESS;
+  }
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (witness_len > SIGNATURE_WITNESS_BUFFER_SIZE) {
+    DEBUG("PoA cell witness is too large!");
+    return ERROR_INVALID_WITNESS;
+  }
+  mol_seg_t witness_seg;
+  witness_seg.ptr = witness_buffer;
+  witness_seg.size = witness_len;
+  if (MolReader_WitnessArgs_verify(&witness_seg, false) != MOL_OK) {
+    DEBUG("Invalid PoA cell witness!");
+    return ERROR_INVALID_WITNESS;
+  }
+  mol_seg_t lock_seg = MolReader_WitnessArgs_get_lock(&witness_seg);
+  if (MolReader_BytesOpt_is_none(&lock_seg)) {
+    DEBUG("PoA cell witness has no signature!");
+    return ERROR_INVALID_WITNESS;
+  }
+  mol_seg_t lock_bytes_seg = MolReader_Bytes_raw_bytes(&lock_seg);
+  if (lock_bytes_seg.size == 0 || lock_bytes_seg.size % SIGNATURE_SIZE != 0 ||
+      lock_bytes_seg.size / SIGNATURE_SIZE > MAX_SIGNATURES) {
+    DEBUG("Invalid signatures in PoA cell witness!");
+    return ERROR_INVALID_WITNESS;
+  }
+  size_t signature_count = lock_bytes_seg.size / SIGNATURE_SIZE;
+  memcpy(signatures_buffer, lock_bytes_seg.ptr, lock_bytes_seg.size);
+  memset(lock_bytes_seg.ptr, 0, lock_bytes_seg.size);
+  uint8_t message[32];
+  ret = calculate_signing_message(witness_buffer, witness_len, message);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+
+  void *handle = NULL;
+  size_t consumed_size = 0;
+  ret = ckb_dlopen2(poa_setup->signature_library,
+                    poa_setup->signature_library[32], code_buffer, CODE_SIZE,
+                    &handle, &consumed_size);
+  if (ret != CKB_SUCCESS) {
+    DEBUG("Error loading signature library!");
+    return ERROR_DYNAMIC_LOADING;
+  }
+  LoadPrefilledDataFn load_prefilled_data =
+      (LoadPrefilledDataFn)ckb_dlsym(handle, "load_prefilled_data");
+  ValidateSignatureFn validate_signature =
+      (ValidateSignatureFn)ckb_dlsym(handle, "validate_signature");
+  if (load_prefilled_data == NULL || validate_signature == NULL) {
+    DEBUG("Error loading signature functions!");
+    return ERROR_DYNAMIC_LOADING;
+  }
+  size_t prefilled_data_len = PREFILLED_DATA_SIZE;
+  ret = load_prefilled_data(prefilled_data_buffer, &prefilled_data_len);
+  if (ret != CKB_SUCCESS) {
+    DEBUG("Error loading prefilled data!");
+    return ERROR_DYNAMIC_LOADING;
+  }
+  for (size_t i = 0; i < signature_count; i++) {
+    size_t output_len = BLAKE160_SIZE;
+    ret = validate_signature(
+        prefilled_data_buffer, &signatures_buffer[i * SIGNATURE_SIZE],
+        SIGNATURE_SIZE, message, 32,
+        &signers->pubkey_hashes[i * BLAKE160_SIZE], &output_len);
+    if (ret != CKB_SUCCESS || output_len != BLAKE160_SIZE) {
+      DEBUG("Invalid signature!");
+      return ERROR_INVALID_SIGNATURE;
+    }
+  }
+  signers->count = signature_count;
+  return CKB_SUCCESS;
+}
+
+int load_signer(const Signers *signers, size_t index, uint8_t hash[32]) {
+  if (!signers->use_signatures) {
+    uint64_t len = 32;
+    return ckb_load_cell_by_field(hash, &len, 0, index, CKB_SOURCE_INPUT,
+                                  CKB_CELL_FIELD_LOCK_HASH);
+  }
+  if (index >= signers->count) {
+    return CKB_INDEX_OUT_OF_BOUND;
+  }
+  memset(hash, 0, 32);
+  memcpy(hash, &signers->pubkey_hashes[index * BLAKE160_SIZE], BLAKE160_SIZE);
+  return CKB_SUCCESS;
+}
+
+// Approvals are counted per distinct identity, or when voting weights are
+// present, the voting weights of distinct identities are summed up.
+int validate_consensus_signing(const Signers *signers,
+                               const uint8_t *identity_buffer,
                                size_t identity_size, uint8_t identity_count,
-                               uint8_t aggregator_change_threshold) {
+                               uint8_t aggregator_change_threshold,
+                               const uint8_t *voting_weights,
+                               uint32_t voting_threshold) {
   uint64_t mask[4];
   mask[0] = mask[1] = mask[2] = mask[3] = 0;
   uint8_t found = 0;
+  uint64_t approved_voting_weight = 0;
   size_t current = 0;
   while (current < SIZE_MAX) {
-    uint64_t len = 32;
     uint8_t hash[32];
 
-    int ret = ckb_load_cell_by_field(hash, &len, 0, current, CKB_SOURCE_INPUT,
-                                     CKB_CELL_FIELD_LOCK_HASH);
+    int ret = load_signer(signers, current, hash);
     if (ret == CKB_INDEX_OUT_OF_BOUND) {
       break;
     }
@@ -125,25 +626,30 @@ int validate_consensus_signing(const uint8_t *identity_buffer,
     if (found_identity < identity_count) {
       // New match found
       found++;
-      if (found == aggregator_change_threshold) {
+      if (voting_weights != NULL) {
+        approved_voting_weight += (uint64_t)unpack_unaligned_u32(
+            &voting_weights[(size_t)found_identity * 4]);
+        if (approved_voting_weight >= (uint64_t)voting_threshold) {
+          return CKB_SUCCESS;
+        }
+      } else if (found == aggregator_change_threshold) {
         return CKB_SUCCESS;
       }
-      mask[found_identity / 64] |= 1 << (found_identity % 64);
+      mask[found_identity / 64] |= ((uint64_t)1) << (found_identity % 64);
     }
     current++;
   }
   DEBUG("Not enough matching identities found!");
-  return ERROR_ENCODING;
+  return ERROR_NOT_ENOUGH_APPROVALS;
 }
 
-int validate_single_signing(const uint8_t *identity, size_t identity_size) {
+int validate_single_signing(const Signers *signers, const uint8_t *identity,
+                            size_t identity_size) {
   size_t current = 0;
   while (current < SIZE_MAX) {
-    uint64_t len = 32;
     uint8_t hash[32];
 
-    int ret = ckb_load_cell_by_field(hash, &len, 0, current, CKB_SOURCE_INPUT,
-                                     CKB_CELL_FIELD_LOCK_HASH);
+    int ret = load_signer(signers, current, hash);
     if (ret == CKB_INDEX_OUT_OF_BOUND) {
       break;
     }
@@ -156,7 +662,7 @@ int validate_single_signing(const uint8_t *identity, size_t identity_size) {
     current++;
   }
   DEBUG("No matching identity found!");
-  return ERROR_ENCODING;
+  return ERROR_OWNER_NOT_FOUND;
 }
 
 static const uint8_t type_id_script_prefix[53] = {
@@ -166,27 +672,54 @@ static const uint8_t type_id_script_prefix[53] = {
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x54, 0x59, 0x50,
     0x45, 0x5f, 0x49, 0x44, 0x01, 0x20, 0x00, 0x00, 0x00};
 
-int look_for_poa_cell(const uint8_t *type_id, size_t source, size_t *index) {
+// PoA setup cell and PoA data cell are located via type ID args. The PoA data
+// cell could also use the PoA data type script in poa_data.c, in which case it
+// is located via its type script hash. Neither can be forged: type ID args
+// are derived from the creating transaction, and a type script hash commits
+// to the whole script, whose uniqueness is enforced by poa_data.c.
+int match_poa_cell(const uint8_t *type_id, size_t source, size_t index,
+                   int match_type_hash, int *matched) {
+  uint64_t len = 85;
+  uint8_t script[85];
+  int ret = ckb_load_cell_by_field(script, &len, 0, index, source,
+                                   CKB_CELL_FIELD_TYPE);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  *matched = len == 85 && memcmp(type_id_script_prefix, script, 53) == 0 &&
+             memcmp(type_id, &script[53], 32) == 0;
+  if ((!*matched) && match_type_hash) {
+    uint8_t hash[32];
+    len = 32;
+    ret = ckb_load_cell_by_field(hash, &len, 0, index, source,
+                                 CKB_CELL_FIELD_TYPE_HASH);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    *matched = memcmp(type_id, hash, 32) == 0;
+  }
+  return CKB_SUCCESS;
+}
+
+int look_for_poa_cell(const uint8_t *type_id, size_t source,
+                      int match_type_hash, size_t *index) {
   size_t current = 0;
   size_t found_index = SIZE_MAX;
   int running = 1;
   while ((running == 1) && (current < SIZE_MAX)) {
-    uint64_t len = 85;
-    uint8_t script[85];
-
-    int ret = ckb_load_cell_by_field(script, &len, 0, current, source,
-                                     CKB_CELL_FIELD_TYPE);
+    int matched = 0;
+    int ret =
+        match_poa_cell(type_id, source, current, match_type_hash, &matched);
     switch (ret) {
       case CKB_ITEM_MISSING:
         break;
       case CKB_SUCCESS:
-        if (len == 85 && memcmp(type_id_script_prefix, script, 53) == 0 &&
-            memcmp(type_id, &script[53], 32) == 0) {
+        if (matched) {
           // Found a match;
           if (found_index != SIZE_MAX) {
             // More than one PoA cell exists
             DEBUG("Duplicate PoA cell!");
-            return ERROR_ENCODING;
+            return ERROR_DUPLICATE_POA_CELL;
           }
           found_index = current;
         }
@@ -204,6 +737,248 @@ int look_for_poa_cell(const uint8_t *type_id, size_t source, size_t *index) {
   return CKB_SUCCESS;
 }
 
+// Checks that PoA data either tracks no missed rounds, or tracks them for all
+// aggregators.
+int validate_poa_data_length(uint64_t len, const PoASetup *poa_setup) {
+  if (len != POA_DATA_SIZE &&
+      len != POA_DATA_SIZE + (uint64_t)poa_setup->aggregator_number *
+                                 MISSED_ROUNDS_SIZE) {
+    return ERROR_INVALID_POA_DATA;
+  }
+  return CKB_SUCCESS;
+}
+
+// When a new round starts, aggregators between the last aggregator and the
+// new one in the rotation have each missed one round, and every full rotation
+// skipped on top makes all aggregators miss one more. Counters saturate
+// instead of wrapping around.
+int validate_missed_rounds(const uint8_t *last_missed_rounds,
+                           const uint8_t *current_missed_rounds,
+                           uint64_t aggregator_number,
+                           uint64_t last_aggregator_index, uint64_t steps,
+                           uint64_t skipped_rotations) {
+  for (uint64_t i = 0; i < aggregator_number; i++) {
+    uint32_t last =
+        unpack_unaligned_u32(&last_missed_rounds[i * MISSED_ROUNDS_SIZE]);
+    uint32_t current =
+        unpack_unaligned_u32(&current_missed_rounds[i * MISSED_ROUNDS_SIZE]);
+    uint64_t offset =
+        (i + aggregator_number - last_aggregator_index) % aggregator_number;
+    uint64_t expected = (uint64_t)last + skipped_rotations;
+    if (offset != 0 && offset < steps) {
+      expected += 1;
+    }
+    if (expected > MAX_MISSED_ROUNDS) {
+      expected = MAX_MISSED_ROUNDS;
+    }
+    if ((uint64_t)current != expected) {
+      return ERROR_INVALID_MISSED_ROUNDS;
+    }
+  }
+  return CKB_SUCCESS;
+}
+
+int same_identities(const PoASetup *a, const PoASetup *b) {
+  return a->signature_identities == b->signature_identities &&
+         a->identity_size == b->identity_size &&
+         a->aggregator_number == b->aggregator_number &&
+         memcmp(a->identities, b->identities,
+                (size_t)a->identity_size * (size_t)a->aggregator_number) == 0;
+}
+
+int is_poa_setup_v1(const PoASetup *poa_setup) {
+  return poa_setup->_source_length > 0 &&
+         (poa_setup->_source_data[0] & POA_SETUP_VERSIONED_FLAG) != 0;
+}
+
+// Checks that 2 v1 setups only differ in the skipped field.
+int same_setup_except(const PoASetup *a, const PoASetup *b,
+                      size_t skipped_field) {
+  if (!is_poa_setup_v1(a) || !is_poa_setup_v1(b)) {
+    return 0;
+  }
+  mol_seg_t a_table, b_table;
+  a_table.ptr = (uint8_t *)&a->_source_data[1];
+  a_table.size = a->_source_length - 1;
+  b_table.ptr = (uint8_t *)&b->_source_data[1];
+  b_table.size = b->_source_length - 1;
+  mol_seg_t a_fields[POA_SETUP_V1_FIELD_COUNT];
+  mol_seg_t b_fields[POA_SETUP_V1_FIELD_COUNT];
+  if (parse_poa_setup_v1_fields(&a_table, a_fields) != CKB_SUCCESS ||
+      parse_poa_setup_v1_fields(&b_table, b_fields) != CKB_SUCCESS) {
+    return 0;
+  }
+  for (size_t i = 0; i < POA_SETUP_V1_FIELD_COUNT; i++) {
+    if (i == skipped_field) {
+      continue;
+    }
+    if (a_fields[i].size != b_fields[i].size ||
+        (a_fields[i].size > 0 &&
+         memcmp(a_fields[i].ptr, b_fields[i].ptr, a_fields[i].size) != 0)) {
+      return 0;
+    }
+  }
+  return 1;
+}
+
+// A proposal is committed no earlier than its activation subtime, via an
+// absolute since on the PoA cell, and no earlier than proposal_delay after
+// it was proposed, via a relative since on the PoA setup cell, which is
+// recreated by the proposal.
+int validate_proposal_since(const PoASetup *poa_setup,
+                            uint64_t activation_subtime,
+                            size_t input_poa_setup_cell_index) {
+  uint64_t since = 0;
+  uint64_t len = 8;
+  int ret = ckb_load_input_by_field(((uint8_t *)&since), &len, 0, 0,
+                                    CKB_SOURCE_GROUP_INPUT,
+                                    CKB_INPUT_FIELD_SINCE);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (len != 8) {
+    DEBUG("Invalid loading since!");
+    return ERROR_INVALID_SINCE;
+  }
+  uint64_t absolute_flags = poa_setup->round_interval_uses_seconds ? 0x40 : 0;
+  if (since >> 56 != absolute_flags ||
+      (since & 0x00FFFFFFFFFFFFFF) < activation_subtime) {
+    DEBUG("Proposal has not reached its activation subtime!");
+    return ERROR_PROPOSAL_NOT_ACTIVE;
+  }
+
+  since = 0;
+  len = 8;
+  ret = ckb_load_input_by_field(((uint8_t *)&since), &len, 0,
+                                input_poa_setup_cell_index, CKB_SOURCE_INPUT,
+                                CKB_INPUT_FIELD_SINCE);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (len != 8) {
+    DEBUG("Invalid loading since!");
+    return ERROR_INVALID_SINCE;
+  }
+  uint64_t relative_flags = poa_setup->round_interval_uses_seconds ? 0xC0 : 0x80;
+  if (since >> 56 != relative_flags ||
+      (since & 0x00FFFFFFFFFFFFFF) < (uint64_t)poa_setup->proposal_delay) {
+    DEBUG("Proposal delay has not passed!");
+    return ERROR_PROPOSAL_NOT_ACTIVE;
+  }
+  return CKB_SUCCESS;
+}
+
+// Setup changes are either applied directly, or when the current setup has a
+// proposal delay, go through 3 kinds of updates:
+// * Propose: the new setup only adds a proposal to the current setup.
+// * Cancel: the new setup only removes the pending proposal.
+// * Commit: the new setup is the pending proposal, after the delay.
+// Only pausing or resuming is still applied directly under a delay.
+int validate_setup_proposal(const PoASetup *poa_setup,
+                            const PoASetup *new_poa_setup,
+                            size_t input_poa_setup_cell_index) {
+  if (poa_setup->proposal != NULL) {
+    if (new_poa_setup->proposal == NULL &&
+        same_setup_except(poa_setup, new_poa_setup,
+                          POA_SETUP_V1_PROPOSAL_FIELD)) {
+      return CKB_SUCCESS;
+    }
+    if (poa_setup->proposal_size - PROPOSAL_HEADER_SIZE !=
+            new_poa_setup->_source_length ||
+        memcmp(&poa_setup->proposal[PROPOSAL_HEADER_SIZE],
+               new_poa_setup->_source_data,
+               new_poa_setup->_source_length) != 0) {
+      DEBUG("New setup is neither the proposal nor a cancellation!");
+      return ERROR_INVALID_PROPOSAL;
+    }
+    return validate_proposal_since(
+        poa_setup, unpack_unaligned_u64(poa_setup->proposal),
+        input_poa_setup_cell_index);
+  }
+  if (new_poa_setup->proposal != NULL) {
+    if (poa_setup->proposal_delay == 0 ||
+        !same_setup_except(poa_setup, new_poa_setup,
+                           POA_SETUP_V1_PROPOSAL_FIELD)) {
+      DEBUG("Invalid setup proposal!");
+      return ERROR_INVALID_PROPOSAL;
+    }
+    // Proposed setup must be valid, and cannot hold a proposal itself
+    PoASetup proposed_setup;
+    int ret = parse_poa_setup(
+        &new_poa_setup->proposal[PROPOSAL_HEADER_SIZE],
+        new_poa_setup->proposal_size - PROPOSAL_HEADER_SIZE, &proposed_setup);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    if (proposed_setup.proposal != NULL) {
+      DEBUG("Proposed setup cannot hold a proposal!");
+      return ERROR_INVALID_PROPOSAL;
+    }
+    return CKB_SUCCESS;
+  }
+  // Pausing or resuming cannot wait for the delay, e.g. when an aggregator
+  // key is compromised.
+  if (poa_setup->proposal_delay != 0 &&
+      !same_setup_except(poa_setup, new_poa_setup,
+                         POA_SETUP_V1_PAUSED_FIELD)) {
+    DEBUG("Setup changes must be proposed first!");
+    return ERROR_PROPOSAL_REQUIRED;
+  }
+  return CKB_SUCCESS;
+}
+
+// Subtimes in PoA data cannot be compared across different kinds of round
+// intervals, and aggregator index and missed rounds in PoA data refer to the
+// old aggregator list. When either changes, the PoA data cell must be
+// rewritten to a fresh round in the same transaction.
+int validate_poa_data_reset(const uint8_t *type_id,
+                            const PoASetup *new_poa_setup) {
+  size_t index = SIZE_MAX;
+  int ret = look_for_poa_cell(type_id, CKB_SOURCE_INPUT, 1, &index);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Input PoA data cell is missing!");
+    return ERROR_POA_DATA_CELL_MISSING;
+  }
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  ret = look_for_poa_cell(type_id, CKB_SOURCE_OUTPUT, 1, &index);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Output PoA data cell is missing!");
+    return ERROR_POA_DATA_CELL_MISSING;
+  }
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  uint8_t buffer[POA_DATA_BUFFER_SIZE];
+  uint64_t len = POA_DATA_BUFFER_SIZE;
+  ret = ckb_load_cell_data(buffer, &len, 0, index, CKB_SOURCE_OUTPUT);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (validate_poa_data_length(len, new_poa_setup) != CKB_SUCCESS) {
+    DEBUG("Invalid output poa data cell!");
+    return ERROR_INVALID_POA_DATA;
+  }
+  uint64_t round_initial_subtime = *((uint64_t *)buffer);
+  uint64_t subblock_subtime = *((uint64_t *)(&buffer[8]));
+  uint32_t subblock_index = *((uint32_t *)(&buffer[16]));
+  uint16_t aggregator_index = *((uint16_t *)(&buffer[20]));
+  if (round_initial_subtime != subblock_subtime || subblock_index != 0 ||
+      aggregator_index >= new_poa_setup->aggregator_number) {
+    DEBUG("PoA data is not reset!");
+    return ERROR_POA_DATA_NOT_RESET;
+  }
+  // Missed rounds refer to the old aggregator list, and start over.
+  for (uint64_t i = POA_DATA_SIZE; i < len; i++) {
+    if (buffer[i] != 0) {
+      DEBUG("Missed rounds are not reset!");
+      return ERROR_POA_DATA_NOT_RESET;
+    }
+  }
+  return CKB_SUCCESS;
+}
+
 int main() {
   // One CKB transaction can only have one cell using current lock.
   uint64_t len = 0;
@@ -237,13 +1012,19 @@ int main() {
   mol_seg_t args_seg = MolReader_Script_get_args(&script_seg);
   mol_seg_t args_bytes_seg = MolReader_Bytes_raw_bytes(&args_seg);
 
-  if (args_bytes_seg.size != 64) {
-    DEBUG("Script args must be 64 bytes long!");
-    return ERROR_ENCODING;
+  // An optional 65th byte in args sets the minimum
+  // aggregator_change_threshold allowed in setup updates.
+  if (args_bytes_seg.size != 64 && args_bytes_seg.size != 65) {
+    DEBUG("Script args must be 64 or 65 bytes long!");
+    return ERROR_INVALID_ARGS;
+  }
+  uint8_t minimum_change_threshold = DEFAULT_MINIMUM_CHANGE_THRESHOLD;
+  if (args_bytes_seg.size == 65) {
+    minimum_change_threshold = args_bytes_seg.ptr[64];
   }
 
   size_t dep_poa_setup_cell_index = SIZE_MAX;
-  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_CELL_DEP,
+  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_CELL_DEP, 0,
                           &dep_poa_setup_cell_index);
   if (ret != CKB_INDEX_OUT_OF_BOUND && ret != CKB_SUCCESS) {
     return ret;
@@ -259,50 +1040,65 @@ int main() {
     }
     if (len > POA_BUFFER_SIZE) {
       DEBUG("Dep PoA cell is too large!");
-      return ERROR_ENCODING;
+      return ERROR_SETUP_CELL_TOO_LARGE;
     }
     PoASetup poa_setup;
     ret = parse_poa_setup(dep_poa_setup_buffer, len, &poa_setup);
     if (ret != CKB_SUCCESS) {
       return ret;
     }
+    if (poa_setup.paused) {
+      DEBUG("Subblock production is paused!");
+      return ERROR_POA_PAUSED;
+    }
 
     size_t input_poa_data_cell_index = SIZE_MAX;
-    ret = look_for_poa_cell(&args_bytes_seg.ptr[32], CKB_SOURCE_INPUT,
+    ret = look_for_poa_cell(&args_bytes_seg.ptr[32], CKB_SOURCE_INPUT, 1,
                             &input_poa_data_cell_index);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      DEBUG("Input PoA data cell is missing!");
+      return ERROR_POA_DATA_CELL_MISSING;
+    }
     if (ret != CKB_SUCCESS) {
       return ret;
     }
-    uint8_t input_poa_data_buffer[22];
-    len = 22;
-    ret = ckb_load_cell_data(input_poa_data_buffer, &len, 0,
+    uint8_t input_poa_data_buffer[POA_DATA_BUFFER_SIZE];
+    uint64_t input_poa_data_len = POA_DATA_BUFFER_SIZE;
+    ret = ckb_load_cell_data(input_poa_data_buffer, &input_poa_data_len, 0,
                              input_poa_data_cell_index, CKB_SOURCE_INPUT);
     if (ret != CKB_SUCCESS) {
       return ret;
     }
-    if (len != 22) {
+    if (validate_poa_data_length(input_poa_data_len, &poa_setup) !=
+        CKB_SUCCESS) {
       DEBUG("Invalid input poa data cell!");
-      return ERROR_ENCODING;
+      return ERROR_INVALID_POA_DATA;
     }
     const uint8_t *last_subblock_info = input_poa_data_buffer;
 
     size_t output_poa_data_cell_index = SIZE_MAX;
-    ret = look_for_poa_cell(&args_bytes_seg.ptr[32], CKB_SOURCE_OUTPUT,
+    ret = look_for_poa_cell(&args_bytes_seg.ptr[32], CKB_SOURCE_OUTPUT, 1,
                             &output_poa_data_cell_index);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      DEBUG("Output PoA data cell is missing!");
+      return ERROR_POA_DATA_CELL_MISSING;
+    }
     if (ret != CKB_SUCCESS) {
       return ret;
     }
-    uint8_t output_poa_data_buffer[22];
-    len = 22;
+    uint8_t output_poa_data_buffer[POA_DATA_BUFFER_SIZE];
+    len = POA_DATA_BUFFER_SIZE;
     ret = ckb_load_cell_data(output_poa_data_buffer, &len, 0,
                              output_poa_data_cell_index, CKB_SOURCE_OUTPUT);
     if (ret != CKB_SUCCESS) {
       return ret;
     }
-    if (len != 22) {
+    if (len != input_poa_data_len) {
       DEBUG("Invalid output poa data cell!");
-      return ERROR_ENCODING;
+      return ERROR_INVALID_POA_DATA;
     }
+    // Missed rounds are only tracked when PoA data is long enough
+    int track_missed_rounds = input_poa_data_len > POA_DATA_SIZE;
     const uint8_t *current_subblock_info = output_poa_data_buffer;
 
     // Check that current aggregator is indeed due to issuing new block.
@@ -321,7 +1117,7 @@ int main() {
         *((uint16_t *)(&current_subblock_info[20]));
     if (current_aggregator_index >= poa_setup.aggregator_number) {
       DEBUG("Invalid aggregator index!");
-      return ERROR_ENCODING;
+      return ERROR_INVALID_AGGREGATOR_INDEX;
     }
 
     // Since is used to ensure aggregators wait till the correct time.
@@ -335,23 +1131,23 @@ int main() {
     }
     if (len != 8) {
       DEBUG("Invalid loading since!");
-      return ERROR_ENCODING;
+      return ERROR_INVALID_SINCE;
     }
     if (poa_setup.round_interval_uses_seconds) {
       if (since >> 56 != 0x40) {
         DEBUG("PoA requires absolute timestamp since!");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_SINCE;
       }
     } else {
       if (since >> 56 != 0) {
         DEBUG("PoA requires absolute block number since!");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_SINCE;
       }
     }
     since &= 0x00FFFFFFFFFFFFFF;
     if (current_subblock_subtime != since) {
       DEBUG("Invalid current time!");
-      return ERROR_ENCODING;
+      return ERROR_SUBTIME_MISMATCH;
     }
 
     // There are 2 supporting modes:
@@ -359,34 +1155,45 @@ int main() {
     // round_intervals and subblocks_per_round requirement is met.
     // 2. When the round_intervals duration has passed, the next aggregator
     // should now be able to issue more blocks.
-    if (since < last_round_initial_subtime + poa_setup.round_intervals) {
+    uint64_t last_weight =
+        aggregator_weight(&poa_setup, (uint64_t)last_aggregator_index);
+    if (since < last_round_initial_subtime +
+                    last_weight * (uint64_t)poa_setup.round_intervals) {
       // Current aggregator is issuing blocks
       if (current_round_initial_subtime != last_round_initial_subtime) {
         DEBUG("Invalid current round first timestamp!");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_ROUND_INITIAL_SUBTIME;
       }
       // Timestamp must be non-decreasing
       if (current_subblock_subtime < last_subblock_subtime) {
         DEBUG("Invalid current timestamp!");
-        return ERROR_ENCODING;
+        return ERROR_SUBTIME_DECREASED;
       }
       if (current_aggregator_index != last_aggregator_index) {
         DEBUG("Invalid aggregator!");
-        return ERROR_ENCODING;
+        return ERROR_AGGREGATOR_CHANGED;
       }
       if ((current_subblock_index != last_block_index + 1) ||
-          (current_subblock_index >= poa_setup.subblocks_per_round)) {
+          ((uint64_t)current_subblock_index >=
+           last_weight * (uint64_t)poa_setup.subblocks_per_round)) {
         DEBUG("Invalid block index");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_SUBBLOCK_INDEX;
+      }
+      if (track_missed_rounds &&
+          memcmp(&last_subblock_info[POA_DATA_SIZE],
+                 &current_subblock_info[POA_DATA_SIZE],
+                 input_poa_data_len - POA_DATA_SIZE) != 0) {
+        DEBUG("Missed rounds cannot change in a round!");
+        return ERROR_INVALID_MISSED_ROUNDS;
       }
     } else {
       if (current_round_initial_subtime != current_subblock_subtime) {
         DEBUG("Invalid current round first timestamp!");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_ROUND_INITIAL_SUBTIME;
       }
       if (current_subblock_index != 0) {
         DEBUG("Invalid block index");
-        return ERROR_ENCODING;
+        return ERROR_INVALID_SUBBLOCK_INDEX;
       }
       // Next aggregator in place
       uint64_t steps = (((uint64_t)current_aggregator_index +
@@ -396,22 +1203,61 @@ int main() {
       if (steps == 0) {
         steps = (uint64_t)poa_setup.aggregator_number;
       }
-      uint64_t duration = steps * ((uint64_t)poa_setup.round_intervals);
+      // Rounds of the last aggregator and all skipped aggregators must have
+      // passed.
+      uint64_t duration = 0;
+      for (uint64_t i = 0; i < steps; i++) {
+        duration += aggregator_weight(&poa_setup,
+                                      (uint64_t)last_aggregator_index + i) *
+                    ((uint64_t)poa_setup.round_intervals);
+      }
       if (since < duration + last_round_initial_subtime) {
         DEBUG("Invalid time!");
-        return ERROR_ENCODING;
+        return ERROR_ROUND_NOT_STARTED;
+      }
+      if (track_missed_rounds) {
+        // Full rotations passing after the handoff
+        uint64_t rotation = 0;
+        for (uint64_t i = 0; i < (uint64_t)poa_setup.aggregator_number; i++) {
+          rotation += aggregator_weight(&poa_setup, i) *
+                      ((uint64_t)poa_setup.round_intervals);
+        }
+        uint64_t skipped_rotations = 0;
+        if (rotation > 0) {
+          skipped_rotations =
+              (since - last_round_initial_subtime - duration) / rotation;
+        }
+        ret = validate_missed_rounds(&last_subblock_info[POA_DATA_SIZE],
+                                     &current_subblock_info[POA_DATA_SIZE],
+                                     (uint64_t)poa_setup.aggregator_number,
+                                     (uint64_t)last_aggregator_index, steps,
+                                     skipped_rotations);
+        if (ret != CKB_SUCCESS) {
+          DEBUG("Invalid missed rounds!");
+          return ret;
+        }
       }
     }
 
+    Signers signers;
+    ret = load_signers(&poa_setup, &signers);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
     return validate_single_signing(
+        &signers,
         &poa_setup.identities[(size_t)current_aggregator_index *
                               (size_t)poa_setup.identity_size],
         poa_setup.identity_size);
   }
   // PoA consensus mode
   size_t input_poa_setup_cell_index = SIZE_MAX;
-  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_INPUT,
+  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_INPUT, 0,
                           &input_poa_setup_cell_index);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Input PoA setup cell is missing!");
+    return ERROR_POA_SETUP_CELL_MISSING;
+  }
   if (ret != CKB_SUCCESS) {
     return ret;
   }
@@ -424,7 +1270,7 @@ int main() {
   }
   if (input_poa_setup_len > POA_BUFFER_SIZE) {
     DEBUG("Input PoA cell is too large!");
-    return ERROR_ENCODING;
+    return ERROR_SETUP_CELL_TOO_LARGE;
   }
   PoASetup poa_setup;
   ret =
@@ -434,8 +1280,12 @@ int main() {
   }
 
   size_t output_poa_setup_cell_index = SIZE_MAX;
-  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_OUTPUT,
+  ret = look_for_poa_cell(args_bytes_seg.ptr, CKB_SOURCE_OUTPUT, 0,
                           &output_poa_setup_cell_index);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Output PoA setup cell is missing!");
+    return ERROR_POA_SETUP_CELL_MISSING;
+  }
   if (ret != CKB_SUCCESS) {
     return ret;
   }
@@ -448,7 +1298,7 @@ int main() {
   }
   if (output_poa_setup_len > POA_BUFFER_SIZE) {
     DEBUG("Output PoA cell is too large!");
-    return ERROR_ENCODING;
+    return ERROR_SETUP_CELL_TOO_LARGE;
   }
   PoASetup new_poa_setup;
   ret = parse_poa_setup(output_poa_setup_buffer, output_poa_setup_len,
@@ -457,7 +1307,37 @@ int main() {
     return ret;
   }
 
+  ret = validate_setup_proposal(&poa_setup, &new_poa_setup,
+                                input_poa_setup_cell_index);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+
+  // Governance rules on the new setup
+  if (new_poa_setup.aggregator_number == 0) {
+    DEBUG("New PoA setup has no identity!");
+    return ERROR_NO_IDENTITY;
+  }
+  if (new_poa_setup.aggregator_change_threshold < minimum_change_threshold) {
+    DEBUG("New aggregator change threshold is below minimum!");
+    return ERROR_THRESHOLD_BELOW_MINIMUM;
+  }
+  if ((new_poa_setup.round_interval_uses_seconds !=
+       poa_setup.round_interval_uses_seconds) ||
+      (!same_identities(&poa_setup, &new_poa_setup))) {
+    ret = validate_poa_data_reset(&args_bytes_seg.ptr[32], &new_poa_setup);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+  }
+
+  Signers signers;
+  ret = load_signers(&poa_setup, &signers);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
   return validate_consensus_signing(
-      poa_setup.identities, poa_setup.identity_size,
-      poa_setup.aggregator_number, poa_setup.aggregator_change_threshold);
+      &signers, poa_setup.identities, poa_setup.identity_size,
+      poa_setup.aggregator_number, poa_setup.aggregator_change_threshold,
+      poa_setup.voting_weights, poa_setup.voting_threshold);
 }
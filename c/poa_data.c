@@ -0,0 +1,401 @@
+// # PoA Data Type Script
+//
+// Optional type script for the PoA data cell, replacing the plain type ID
+// script. It keeps the uniqueness guarantee of type ID, and validates PoA data
+// transitions on its own, so the PoA data cell cannot be corrupted even by a
+// transaction that skips the PoA lock.
+//
+// Script args contain 32-byte type ID args, calculated the same way as type
+// ID, followed by the 32-byte code hash and 1-byte hash type of the PoA lock.
+// When this script is used, the second half of PoA lock args holds the type
+// script hash of the PoA data cell instead of type ID args.
+//
+// PoA data is 22 bytes long:
+//
+// * round_initial_subtime: u64
+// * subblock_subtime: u64
+// * subblock_index: u32
+// * aggregator_index: u16
+//
+// It can be followed by one u32 counter of missed rounds for each aggregator,
+// which the PoA lock updates when a new round starts.
+//
+// A transition is valid when PoA data stays unchanged, when a new subblock is
+// added to the current round by the same aggregator, or when a new round
+// starts at its first subblock. Subtimes never decrease, and missed rounds
+// only change when a new round starts. The only exception is a reset by a
+// setup update, which starts over at its first subblock with all missed
+// rounds at 0, since switching round intervals between seconds and blocks
+// changes the unit of subtimes.
+//
+// Only the PoA lock knows the PoA setup, so every change to PoA data also
+// requires the PoA cell among the inputs. The PoA lock then checks subblocks
+// against the round limits and the aggregator rotation, or checks that PoA
+// data is reset for a new setup.
+// Missed rounds, when tracked, must either follow the rotation or be reset to
+// 0, and a reset is the only way to change the number of counters.
+#include "blake2b.h"
+#include "blockchain.h"
+#include "ckb_syscalls.h"
+
+#define SCRIPT_BUFFER_SIZE 128
+#define INPUT_BUFFER_SIZE 128
+#define TYPE_ID_SIZE 32
+#define CODE_HASH_SIZE 32
+#define ARGS_SIZE (TYPE_ID_SIZE + CODE_HASH_SIZE + 1)
+#define POA_LOCK_ARGS_SIZE 64
+#define POA_DATA_SIZE 22
+#define MISSED_ROUNDS_SIZE 4
+#define POA_DATA_BUFFER_SIZE (POA_DATA_SIZE + 255 * MISSED_ROUNDS_SIZE)
+#define MAX_MISSED_ROUNDS 0xFFFFFFFF
+
+#ifdef ENABLE_DEBUG_MODE
+#define DEBUG(s) ckb_debug(s)
+#else
+#define DEBUG(s)
+#endif /* ENABLE_DEBUG_MODE */
+
+#define ERROR_TRANSACTION -1
+#define ERROR_ENCODING -2
+#define ERROR_INVALID_ARGS -3
+#define ERROR_INVALID_TYPE_ID -4
+#define ERROR_INVALID_POA_DATA -5
+#define ERROR_SUBTIME_DECREASED -6
+#define ERROR_INVALID_ROUND -7
+#define ERROR_INVALID_MISSED_ROUNDS -8
+#define ERROR_POA_CELL_MISSING -9
+
+typedef struct {
+  uint64_t round_initial_subtime;
+  uint64_t subblock_subtime;
+  uint32_t subblock_index;
+  uint16_t aggregator_index;
+  uint8_t missed_rounds[POA_DATA_BUFFER_SIZE - POA_DATA_SIZE];
+  size_t missed_rounds_length;
+} PoAData;
+
+int load_poa_data(size_t source, PoAData *output) {
+  uint8_t buffer[POA_DATA_BUFFER_SIZE];
+  uint64_t len = POA_DATA_BUFFER_SIZE;
+  int ret = ckb_load_cell_data(buffer, &len, 0, 0, source);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (len < POA_DATA_SIZE || len > POA_DATA_BUFFER_SIZE ||
+      (len - POA_DATA_SIZE) % MISSED_ROUNDS_SIZE != 0) {
+    DEBUG("Invalid PoA data length!");
+    return ERROR_INVALID_POA_DATA;
+  }
+  output->missed_rounds_length = len - POA_DATA_SIZE;
+  memcpy(output->missed_rounds, &buffer[POA_DATA_SIZE],
+         output->missed_rounds_length);
+  output->round_initial_subtime = *((uint64_t *)buffer);
+  output->subblock_subtime = *((uint64_t *)(&buffer[8]));
+  output->subblock_index = *((uint32_t *)(&buffer[16]));
+  output->aggregator_index = *((uint16_t *)(&buffer[20]));
+  if (output->round_initial_subtime > output->subblock_subtime) {
+    DEBUG("Round starts after current subblock!");
+    return ERROR_INVALID_ROUND;
+  }
+  if (output->subblock_index == 0 &&
+      output->round_initial_subtime != output->subblock_subtime) {
+    DEBUG("First subblock of a round must be issued at round start!");
+    return ERROR_INVALID_ROUND;
+  }
+  return CKB_SUCCESS;
+}
+
+uint32_t missed_rounds_at(const PoAData *data, uint64_t index) {
+  uint32_t counter;
+  memcpy(&counter, &data->missed_rounds[index * MISSED_ROUNDS_SIZE],
+         MISSED_ROUNDS_SIZE);
+  return counter;
+}
+
+int missed_rounds_reset(const PoAData *data) {
+  uint64_t aggregator_number = data->missed_rounds_length / MISSED_ROUNDS_SIZE;
+  for (uint64_t i = 0; i < aggregator_number; i++) {
+    if (missed_rounds_at(data, i) != 0) {
+      return 0;
+    }
+  }
+  return 1;
+}
+
+// Whether aggregator `index` lies between the last aggregator and the new one,
+// `steps` rounds later in the rotation.
+uint64_t skipped_in_rotation(uint64_t index, uint64_t last_aggregator_index,
+                             uint64_t aggregator_number, uint64_t steps) {
+  uint64_t offset = (index + aggregator_number -
+                     last_aggregator_index % aggregator_number) %
+                    aggregator_number;
+  return offset != 0 && offset < steps;
+}
+
+// Like `validate_missed_rounds` in `c/poa.c`, aggregators between the last
+// aggregator and the new one in the rotation have each missed one round, and
+// every full rotation skipped on top makes all aggregators miss one more. Only
+// the PoA lock knows round lengths, here all counters must agree on the number
+// of skipped rotations, taken from the first new counter that has not
+// saturated.
+// All counters can also be reset to 0 along with the setup.
+int validate_new_round_missed_rounds(const PoAData *last,
+                                     const PoAData *current) {
+  uint64_t aggregator_number =
+      current->missed_rounds_length / MISSED_ROUNDS_SIZE;
+  if (aggregator_number > 0 &&
+      (uint64_t)current->aggregator_index >= aggregator_number) {
+    DEBUG("Aggregator index exceeds missed rounds counters!");
+    return ERROR_INVALID_ROUND;
+  }
+  if (missed_rounds_reset(current)) {
+    return CKB_SUCCESS;
+  }
+  if (current->missed_rounds_length != last->missed_rounds_length) {
+    DEBUG("Missed rounds counters can only be resequenced by a reset!");
+    return ERROR_INVALID_MISSED_ROUNDS;
+  }
+  uint64_t last_aggregator_index = (uint64_t)last->aggregator_index;
+  uint64_t steps = ((uint64_t)current->aggregator_index + aggregator_number -
+                    last_aggregator_index % aggregator_number) %
+                   aggregator_number;
+  if (steps == 0) {
+    steps = aggregator_number;
+  }
+  uint64_t skipped_rotations = 0;
+  for (uint64_t i = 0; i < aggregator_number; i++) {
+    uint64_t counter = missed_rounds_at(last, i);
+    uint64_t current_counter = missed_rounds_at(current, i);
+    uint64_t skipped = skipped_in_rotation(i, last_aggregator_index,
+                                           aggregator_number, steps);
+    if (current_counter != MAX_MISSED_ROUNDS) {
+      if (current_counter < counter + skipped) {
+        DEBUG("Missed rounds cannot decrease in a new round!");
+        return ERROR_INVALID_MISSED_ROUNDS;
+      }
+      skipped_rotations = current_counter - counter - skipped;
+      break;
+    }
+  }
+  for (uint64_t i = 0; i < aggregator_number; i++) {
+    uint64_t expected =
+        (uint64_t)missed_rounds_at(last, i) + skipped_rotations +
+        skipped_in_rotation(i, last_aggregator_index, aggregator_number, steps);
+    if (expected > MAX_MISSED_ROUNDS) {
+      expected = MAX_MISSED_ROUNDS;
+    }
+    if ((uint64_t)missed_rounds_at(current, i) != expected) {
+      DEBUG("Invalid missed rounds in a new round!");
+      return ERROR_INVALID_MISSED_ROUNDS;
+    }
+  }
+  return CKB_SUCCESS;
+}
+
+// Looks for an input cell using the PoA lock from script args, whose args
+// refer to current script hash.
+int look_for_poa_cell(const uint8_t *poa_lock) {
+  uint8_t current_script_hash[32];
+  uint64_t len = 32;
+  int ret = ckb_load_script_hash(current_script_hash, &len, 0);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  size_t index = 0;
+  while (1) {
+    uint8_t lock[SCRIPT_BUFFER_SIZE];
+    len = SCRIPT_BUFFER_SIZE;
+    ret = ckb_load_cell_by_field(lock, &len, 0, index, CKB_SOURCE_INPUT,
+                                 CKB_CELL_FIELD_LOCK);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      DEBUG("PoA cell is missing!");
+      return ERROR_POA_CELL_MISSING;
+    }
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+    index++;
+    // PoA lock scripts always fit in the buffer
+    if (len > SCRIPT_BUFFER_SIZE) {
+      continue;
+    }
+    mol_seg_t lock_seg;
+    lock_seg.ptr = lock;
+    lock_seg.size = len;
+    if (MolReader_Script_verify(&lock_seg, false) != MOL_OK) {
+      return ERROR_ENCODING;
+    }
+    mol_seg_t code_hash_seg = MolReader_Script_get_code_hash(&lock_seg);
+    mol_seg_t hash_type_seg = MolReader_Script_get_hash_type(&lock_seg);
+    mol_seg_t args_seg = MolReader_Script_get_args(&lock_seg);
+    mol_seg_t args_bytes_seg = MolReader_Bytes_raw_bytes(&args_seg);
+    if (memcmp(code_hash_seg.ptr, poa_lock, CODE_HASH_SIZE) == 0 &&
+        *hash_type_seg.ptr == poa_lock[CODE_HASH_SIZE] &&
+        (args_bytes_seg.size == POA_LOCK_ARGS_SIZE ||
+         args_bytes_seg.size == POA_LOCK_ARGS_SIZE + 1) &&
+        memcmp(&args_bytes_seg.ptr[32], current_script_hash, 32) == 0) {
+      return CKB_SUCCESS;
+    }
+  }
+}
+
+int validate_transition(const PoAData *last, const PoAData *current,
+                        const uint8_t *poa_lock) {
+  // A reset still requires the PoA cell, whose lock checks it against the new
+  // setup.
+  int reset = current->subblock_index == 0 && missed_rounds_reset(current);
+  if (!reset && (current->subblock_subtime < last->subblock_subtime ||
+                 current->round_initial_subtime <
+                     last->round_initial_subtime)) {
+    DEBUG("Subtime cannot decrease!");
+    return ERROR_SUBTIME_DECREASED;
+  }
+  if (current->round_initial_subtime == last->round_initial_subtime &&
+      current->subblock_subtime == last->subblock_subtime &&
+      current->subblock_index == last->subblock_index &&
+      current->aggregator_index == last->aggregator_index &&
+      current->missed_rounds_length == last->missed_rounds_length &&
+      memcmp(current->missed_rounds, last->missed_rounds,
+             current->missed_rounds_length) == 0) {
+    return CKB_SUCCESS;
+  }
+  if (current->subblock_index == 0) {
+    // New round, load_poa_data has checked the round starts at current
+    // subblock.
+    int ret = validate_new_round_missed_rounds(last, current);
+    if (ret != CKB_SUCCESS) {
+      return ret;
+    }
+  } else {
+    if (current->missed_rounds_length != last->missed_rounds_length ||
+        memcmp(current->missed_rounds, last->missed_rounds,
+               current->missed_rounds_length) != 0) {
+      DEBUG("Missed rounds cannot change in a round!");
+      return ERROR_INVALID_MISSED_ROUNDS;
+    }
+    if (current->round_initial_subtime != last->round_initial_subtime ||
+        current->aggregator_index != last->aggregator_index ||
+        (uint64_t)current->subblock_index !=
+            (uint64_t)last->subblock_index + 1) {
+      DEBUG("Invalid subblock in current round!");
+      return ERROR_INVALID_ROUND;
+    }
+  }
+  // Subblock limits and subtimes are only known to the PoA lock
+  return look_for_poa_cell(poa_lock);
+}
+
+// Follows type ID: args must be the hash of the first input, and the index of
+// the created cell in outputs.
+int validate_type_id_creation(const uint8_t *type_id) {
+  uint8_t current_script_hash[32];
+  uint64_t len = 32;
+  int ret = ckb_load_script_hash(current_script_hash, &len, 0);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  size_t output_index = 0;
+  while (1) {
+    uint8_t hash[32];
+    len = 32;
+    ret = ckb_load_cell_by_field(hash, &len, 0, output_index,
+                                 CKB_SOURCE_OUTPUT, CKB_CELL_FIELD_TYPE_HASH);
+    if (ret == CKB_SUCCESS && memcmp(hash, current_script_hash, 32) == 0) {
+      break;
+    }
+    if (ret != CKB_SUCCESS && ret != CKB_ITEM_MISSING) {
+      return ret;
+    }
+    output_index++;
+  }
+
+  uint8_t first_input[INPUT_BUFFER_SIZE];
+  len = INPUT_BUFFER_SIZE;
+  ret = ckb_load_input(first_input, &len, 0, 0, CKB_SOURCE_INPUT);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  if (len > INPUT_BUFFER_SIZE) {
+    return ERROR_ENCODING;
+  }
+  uint64_t index = output_index;
+  uint8_t expected[32];
+  blake2b_state ctx;
+  blake2b_init(&ctx, 32);
+  blake2b_update(&ctx, first_input, len);
+  blake2b_update(&ctx, (uint8_t *)&index, sizeof(uint64_t));
+  blake2b_final(&ctx, expected, 32);
+  if (memcmp(expected, type_id, TYPE_ID_SIZE) != 0) {
+    DEBUG("Invalid type ID!");
+    return ERROR_INVALID_TYPE_ID;
+  }
+  return CKB_SUCCESS;
+}
+
+int main() {
+  // Like type ID, one CKB transaction can only have at most one input and one
+  // output cell using current script.
+  uint64_t len = 0;
+  int ret = ckb_load_cell(NULL, &len, 0, 1, CKB_SOURCE_GROUP_INPUT);
+  if (ret != CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Transaction has more than one input PoA data cell!");
+    return ERROR_TRANSACTION;
+  }
+  len = 0;
+  ret = ckb_load_cell(NULL, &len, 0, 1, CKB_SOURCE_GROUP_OUTPUT);
+  if (ret != CKB_INDEX_OUT_OF_BOUND) {
+    DEBUG("Transaction has more than one output PoA data cell!");
+    return ERROR_TRANSACTION;
+  }
+
+  uint8_t script[SCRIPT_BUFFER_SIZE];
+  len = SCRIPT_BUFFER_SIZE;
+  ret = ckb_checked_load_script(script, &len, 0);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  mol_seg_t script_seg;
+  script_seg.ptr = (uint8_t *)script;
+  script_seg.size = len;
+  if (MolReader_Script_verify(&script_seg, false) != MOL_OK) {
+    DEBUG("molecule verification failure!");
+    return ERROR_ENCODING;
+  }
+  mol_seg_t args_seg = MolReader_Script_get_args(&script_seg);
+  mol_seg_t args_bytes_seg = MolReader_Bytes_raw_bytes(&args_seg);
+  if (args_bytes_seg.size != ARGS_SIZE) {
+    DEBUG("Script args must be 65 bytes long!");
+    return ERROR_INVALID_ARGS;
+  }
+
+  len = 0;
+  ret = ckb_load_cell(NULL, &len, 0, 0, CKB_SOURCE_GROUP_OUTPUT);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    // Destroying the PoA data cell is left to its lock.
+    return CKB_SUCCESS;
+  }
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  PoAData current;
+  ret = load_poa_data(CKB_SOURCE_GROUP_OUTPUT, &current);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+
+  len = 0;
+  ret = ckb_load_cell(NULL, &len, 0, 0, CKB_SOURCE_GROUP_INPUT);
+  if (ret == CKB_INDEX_OUT_OF_BOUND) {
+    return validate_type_id_creation(args_bytes_seg.ptr);
+  }
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  PoAData last;
+  ret = load_poa_data(CKB_SOURCE_GROUP_INPUT, &last);
+  if (ret != CKB_SUCCESS) {
+    return ret;
+  }
+  return validate_transition(&last, &current,
+                             &args_bytes_seg.ptr[TYPE_ID_SIZE]);
+}
@@ -16,10 +16,14 @@
 // locks of PoA Setup cell and PoA Data cell
 //
 // Since the 3 cells are created in a single transaction, this flow works.
+//
+// Args could also contain several 32-byte lock hashes, in which case an input
+// cell using any of the locks unlocks the cell.
 #include "blockchain.h"
 #include "ckb_syscalls.h"
 
-#define SCRIPT_BUFFER_SIZE 128
+#define SCRIPT_BUFFER_SIZE 32768
+#define OWNER_HASH_SIZE 32
 
 #ifdef ENABLE_DEBUG_MODE
 #define DEBUG(s) ckb_debug(s)
@@ -28,6 +32,7 @@
 #endif /* ENABLE_DEBUG_MODE */
 
 #define ERROR_TRANSACTION -1
+#define ERROR_OWNER_NOT_FOUND -2
 
 int main() {
   // Load current script so as to extract PoA cell information
@@ -47,10 +52,11 @@ int main() {
   mol_seg_t args_seg = MolReader_Script_get_args(&script_seg);
   mol_seg_t args_bytes_seg = MolReader_Bytes_raw_bytes(&args_seg);
 
-  if (args_bytes_seg.size != 32) {
-    DEBUG("Script args must be 32 bytes long!");
+  if (args_bytes_seg.size == 0 || args_bytes_seg.size % OWNER_HASH_SIZE != 0) {
+    DEBUG("Script args must be a non-empty list of 32-byte lock hashes!");
     return ERROR_TRANSACTION;
   }
+  size_t owner_count = args_bytes_seg.size / OWNER_HASH_SIZE;
 
   size_t current = 0;
   while (current < SIZE_MAX) {
@@ -59,6 +65,9 @@ int main() {
 
     ret = ckb_load_cell_by_field(hash, &len, 0, current, CKB_SOURCE_INPUT,
                                  CKB_CELL_FIELD_LOCK_HASH);
+    if (ret == CKB_INDEX_OUT_OF_BOUND) {
+      break;
+    }
     if (ret != CKB_SUCCESS) {
       return ret;
     }
@@ -66,10 +75,14 @@ int main() {
       DEBUG("Invalid script length!");
       return ERROR_TRANSACTION;
     }
-    if (memcmp(hash, args_bytes_seg.ptr, 32) == 0) {
-      break;
+    for (size_t i = 0; i < owner_count; i++) {
+      if (memcmp(hash, &args_bytes_seg.ptr[i * OWNER_HASH_SIZE],
+                 OWNER_HASH_SIZE) == 0) {
+        return CKB_SUCCESS;
+      }
     }
     current++;
   }
-  return 0;
+  DEBUG("Owner cell is not found!");
+  return ERROR_OWNER_NOT_FOUND;
 }
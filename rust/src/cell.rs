@@ -0,0 +1,56 @@
+use ckb_tool::ckb_types::{bytes::Bytes, packed::*, prelude::*};
+
+/// A live cell on CKB, together with its location.
+#[derive(Clone, Debug)]
+pub struct CellInfo {
+    pub out_point: OutPoint,
+    pub output: CellOutput,
+    pub data: Bytes,
+}
+
+impl CellInfo {
+    pub fn new(out_point: OutPoint, output: CellOutput, data: Bytes) -> Self {
+        CellInfo {
+            out_point,
+            output,
+            data,
+        }
+    }
+
+    pub fn input(&self, since: u64) -> CellInput {
+        CellInput::new_builder()
+            .previous_output(self.out_point.clone())
+            .since(since.pack())
+            .build()
+    }
+
+    pub fn code_dep(&self) -> CellDep {
+        CellDep::new_builder()
+            .out_point(self.out_point.clone())
+            .build()
+    }
+
+    pub fn lock_hash(&self) -> Bytes {
+        self.output.lock().calc_script_hash().as_bytes()
+    }
+
+    /// Args of the type script, or an empty slice when the cell has no type
+    /// script.
+    pub fn type_args(&self) -> Bytes {
+        self.output
+            .type_()
+            .to_opt()
+            .map(|script| script.args().raw_data())
+            .unwrap_or_default()
+    }
+
+    /// Hash of the type script, or an empty slice when the cell has no type
+    /// script.
+    pub fn type_hash(&self) -> Bytes {
+        self.output
+            .type_()
+            .to_opt()
+            .map(|script| script.calc_script_hash().as_bytes())
+            .unwrap_or_default()
+    }
+}
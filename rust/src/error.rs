@@ -0,0 +1,397 @@
+use ckb_tool::ckb_types::bytes::Bytes;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// PoA setup is shorter than the fixed header.
+    SetupTooShort(usize),
+    /// Identity size exceeds the 32 bytes supported by the PoA lock.
+    InvalidIdentitySize(u8),
+    /// More approvals are required than there are aggregators.
+    InvalidChangeThreshold {
+        threshold: u8,
+        aggregator_number: u8,
+    },
+    /// Setup length does not match header and identities.
+    SetupLengthMismatch { expected: usize, actual: usize },
+    /// PoA setup uses an encoding version this library does not know.
+    UnsupportedSetupVersion(u8),
+    /// Versioned PoA setup is not a valid molecule structure.
+    InvalidSetupEncoding,
+    /// PoA setup has weights, but not for each aggregator.
+    InvalidWeights { expected: usize, actual: usize },
+    /// Aggregator at the contained index has weight 0.
+    ZeroWeight(usize),
+    /// PoA setup version cannot hold aggregator weights.
+    WeightsUnsupported(u8),
+    /// PoA setup has voting weights, but not for each aggregator.
+    InvalidVotingWeights { expected: usize, actual: usize },
+    /// Voting threshold is 0 or above the total voting weight, or set without
+    /// voting weights.
+    InvalidVotingThreshold {
+        threshold: u32,
+        total_voting_weight: u64,
+    },
+    /// PoA setup version cannot hold voting weights.
+    VotingWeightsUnsupported(u8),
+    /// PoA setup version cannot hold a proposal delay or a proposal.
+    ProposalUnsupported(u8),
+    /// Proposed setup holds a proposal itself.
+    NestedProposal,
+    /// PoA setup holds more aggregators than the 255 allowed.
+    TooManyIdentities(usize),
+    /// One identity is shorter than the configured identity size.
+    IdentityTooShort { index: usize, length: usize },
+    /// Versioned PoA setup has the contained number of identities, but
+    /// identity size 0, so none of them could be serialized.
+    EmptyIdentitySize(usize),
+    /// PoA data cell is neither 22 bytes long, nor followed by missed rounds
+    /// counters.
+    InvalidDataLength(usize),
+    /// PoA data tracks missed rounds for a different number of aggregators
+    /// than the setup, or does not track them at all.
+    InvalidMissedRounds { expected: usize, actual: usize },
+    /// Number of aggregators is 0, or above the 255 supported by the PoA
+    /// lock.
+    InvalidAggregatorNumber(usize),
+    /// Current aggregator cannot be located in the PoA setup.
+    IdentityNotFound,
+    /// Subtime cannot be encoded in an absolute since value.
+    InvalidSubtime(u64),
+    /// Current aggregator's round has not started, contains the subtime at
+    /// which it starts.
+    RoundNotStarted(u64),
+    /// PoA lock args do not reference the provided PoA setup and data cells.
+    PoACellMismatch,
+    /// No input cell uses the owner lock of current aggregator.
+    OwnerCellMissing,
+    /// Not enough aggregators approve a PoA setup update, contains the
+    /// identities that have not approved.
+    NotEnoughApprovals { required: u8, missing: Vec<Bytes> },
+    /// Voting weights of the aggregators approving a PoA setup update do not
+    /// reach the voting threshold, contains the identities that have not
+    /// approved.
+    NotEnoughVotingWeight {
+        required: u32,
+        approved: u64,
+        missing: Vec<Bytes>,
+    },
+    /// Cell capacity cannot be represented in shannons.
+    CapacityOverflow,
+    /// New PoA setup has no identity.
+    NoIdentity,
+    /// New aggregator change threshold is below the minimum in PoA lock args.
+    ThresholdBelowMinimum { threshold: u8, minimum: u8 },
+    /// Setup update requires PoA data cell to be reset to a fresh round.
+    PoADataResetRequired,
+    /// Current setup has a proposal delay, setup changes must be proposed
+    /// first.
+    ProposalRequired,
+    /// Current setup has no proposal delay, setup changes are applied
+    /// directly.
+    NoProposalDelay,
+    /// Current setup already has a pending proposal.
+    ProposalPending,
+    /// Current setup has no pending proposal to commit or cancel.
+    NoProposal,
+    /// New setup neither adds a proposal, cancels it nor commits it.
+    InvalidProposal,
+    /// Existing PoA cell witness is not a valid WitnessArgs structure.
+    InvalidWitness,
+    /// Private key cannot be used for signing.
+    InvalidPrivateKey,
+    /// PoA setup is paused, no subblock can be issued until it is resumed.
+    Paused,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SetupTooShort(length) => write!(f, "PoA setup is too short: {}", length),
+            Error::InvalidIdentitySize(size) => write!(f, "Invalid identity size: {}", size),
+            Error::InvalidChangeThreshold {
+                threshold,
+                aggregator_number,
+            } => write!(
+                f,
+                "Invalid aggregator change threshold {} for {} aggregators",
+                threshold, aggregator_number
+            ),
+            Error::SetupLengthMismatch { expected, actual } => write!(
+                f,
+                "PoA setup has invalid length {}, expected: {}",
+                actual, expected
+            ),
+            Error::UnsupportedSetupVersion(version) => {
+                write!(f, "Unsupported PoA setup version: {}", version)
+            }
+            Error::InvalidSetupEncoding => write!(f, "Invalid PoA setup encoding"),
+            Error::InvalidWeights { expected, actual } => write!(
+                f,
+                "PoA setup has {} weights, expected: {}",
+                actual, expected
+            ),
+            Error::ZeroWeight(index) => write!(f, "Aggregator {} has weight 0", index),
+            Error::WeightsUnsupported(version) => {
+                write!(f, "PoA setup version {} does not support weights", version)
+            }
+            Error::InvalidVotingWeights { expected, actual } => write!(
+                f,
+                "PoA setup has {} voting weights, expected: {}",
+                actual, expected
+            ),
+            Error::InvalidVotingThreshold {
+                threshold,
+                total_voting_weight,
+            } => write!(
+                f,
+                "Invalid voting threshold {}, total voting weight: {}",
+                threshold, total_voting_weight
+            ),
+            Error::VotingWeightsUnsupported(version) => write!(
+                f,
+                "PoA setup version {} does not support voting weights",
+                version
+            ),
+            Error::ProposalUnsupported(version) => write!(
+                f,
+                "PoA setup version {} does not support proposals",
+                version
+            ),
+            Error::NestedProposal => write!(f, "Proposed setup cannot hold a proposal"),
+            Error::TooManyIdentities(count) => write!(f, "Too many identities: {}", count),
+            Error::IdentityTooShort { index, length } => {
+                write!(f, "Identity {} is too short: {}", index, length)
+            }
+            Error::EmptyIdentitySize(aggregator_number) => write!(
+                f,
+                "PoA setup has {} identities, but identity size 0",
+                aggregator_number
+            ),
+            Error::InvalidDataLength(length) => {
+                write!(f, "PoA data has invalid length: {}", length)
+            }
+            Error::InvalidMissedRounds { expected, actual } => write!(
+                f,
+                "PoA data tracks missed rounds of {} aggregators, expected: {}",
+                actual, expected
+            ),
+            Error::InvalidAggregatorNumber(number) => {
+                write!(f, "Invalid number of aggregators: {}", number)
+            }
+            Error::IdentityNotFound => write!(f, "Specified identity cannot be located!"),
+            Error::InvalidSubtime(subtime) => write!(f, "Invalid subtime: {}", subtime),
+            Error::RoundNotStarted(start) => write!(f, "Round has not started till {}", start),
+            Error::PoACellMismatch => write!(f, "PoA cell does not match state cells"),
+            Error::OwnerCellMissing => write!(f, "Cannot find any owner cell!"),
+            Error::NotEnoughApprovals { required, missing } => write!(
+                f,
+                "Not enough approvals, required: {}, missing identities: {:?}",
+                required, missing
+            ),
+            Error::NotEnoughVotingWeight {
+                required,
+                approved,
+                missing,
+            } => write!(
+                f,
+                "Not enough voting weight, required: {}, approved: {}, missing identities: {:?}",
+                required, approved, missing
+            ),
+            Error::CapacityOverflow => write!(f, "Capacity overflow"),
+            Error::NoIdentity => write!(f, "PoA setup has no identity"),
+            Error::ThresholdBelowMinimum { threshold, minimum } => write!(
+                f,
+                "Aggregator change threshold {} is below minimum: {}",
+                threshold, minimum
+            ),
+            Error::ProposalRequired => write!(f, "Setup changes must be proposed first"),
+            Error::NoProposalDelay => write!(f, "PoA setup has no proposal delay"),
+            Error::ProposalPending => write!(f, "PoA setup already has a pending proposal"),
+            Error::NoProposal => write!(f, "PoA setup has no pending proposal"),
+            Error::InvalidProposal => write!(f, "Invalid setup proposal"),
+            Error::PoADataResetRequired => write!(f, "PoA data must be reset"),
+            Error::InvalidWitness => write!(f, "Invalid PoA cell witness"),
+            Error::InvalidPrivateKey => write!(f, "Invalid private key"),
+            Error::Paused => write!(f, "Subblock production is paused"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Exit codes of the PoA lock in `c/poa.c`, so a rejected transaction can be
+/// diagnosed from the script error alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i8)]
+pub enum PoaError {
+    /// ERROR_TRANSACTION: more than one cell in the transaction uses the PoA lock.
+    Transaction = -1,
+    /// ERROR_ENCODING: current script or a versioned PoA setup is not valid molecule.
+    Encoding = -2,
+    /// ERROR_DYNAMIC_LOADING: signature library or its functions fail to load.
+    DynamicLoading = -3,
+    /// ERROR_INVALID_ARGS: PoA lock args are neither 64 nor 65 bytes long.
+    InvalidArgs = -4,
+    /// ERROR_SETUP_TOO_SHORT: PoA setup is shorter than its fixed fields.
+    SetupTooShort = -5,
+    /// ERROR_INVALID_IDENTITY_SIZE: identity size exceeds what identities allow.
+    InvalidIdentitySize = -6,
+    /// ERROR_INVALID_CHANGE_THRESHOLD: threshold exceeds the aggregator number.
+    InvalidChangeThreshold = -7,
+    /// ERROR_SETUP_LENGTH_MISMATCH: identities do not split into identity size.
+    SetupLengthMismatch = -8,
+    /// ERROR_SETUP_CELL_TOO_LARGE: PoA setup cell exceeds the PoA lock buffer.
+    SetupCellTooLarge = -9,
+    /// ERROR_DUPLICATE_POA_CELL: more than one cell uses a type ID from args.
+    DuplicatePoACell = -10,
+    /// ERROR_POA_SETUP_CELL_MISSING: PoA setup cell is missing from inputs or outputs.
+    PoASetupCellMissing = -11,
+    /// ERROR_POA_DATA_CELL_MISSING: PoA data cell is missing from inputs or outputs.
+    PoADataCellMissing = -12,
+    /// ERROR_INVALID_POA_DATA: PoA data is neither 22 bytes nor followed by all counters.
+    InvalidPoAData = -13,
+    /// ERROR_INVALID_AGGREGATOR_INDEX: new aggregator index is out of range.
+    InvalidAggregatorIndex = -14,
+    /// ERROR_INVALID_SINCE: PoA cell input lacks an absolute since of the right kind.
+    InvalidSince = -15,
+    /// ERROR_SUBTIME_MISMATCH: since differs from the new subblock subtime.
+    SubtimeMismatch = -16,
+    /// ERROR_INVALID_ROUND_INITIAL_SUBTIME: round initial subtime is off.
+    InvalidRoundInitialSubtime = -17,
+    /// ERROR_SUBTIME_DECREASED: subblock subtime precedes the last one in a round.
+    SubtimeDecreased = -18,
+    /// ERROR_AGGREGATOR_CHANGED: another aggregator issues a subblock in a round.
+    AggregatorChanged = -19,
+    /// ERROR_INVALID_SUBBLOCK_INDEX: subblock index does not follow the last one.
+    InvalidSubblockIndex = -20,
+    /// ERROR_ROUND_NOT_STARTED: new aggregator's round has not started yet.
+    RoundNotStarted = -21,
+    /// ERROR_OWNER_NOT_FOUND: no input uses the owner lock of the new aggregator.
+    OwnerNotFound = -22,
+    /// ERROR_NOT_ENOUGH_APPROVALS: too few aggregators approve a setup update.
+    NotEnoughApprovals = -23,
+    /// ERROR_NO_IDENTITY: new PoA setup has no identity.
+    NoIdentity = -24,
+    /// ERROR_THRESHOLD_BELOW_MINIMUM: new threshold is below the args minimum.
+    ThresholdBelowMinimum = -25,
+    /// ERROR_POA_DATA_NOT_RESET: setup update does not reset PoA data.
+    PoADataNotReset = -26,
+    /// ERROR_INVALID_WITNESS: PoA cell witness does not hold signatures.
+    InvalidWitness = -27,
+    /// ERROR_INVALID_SIGNATURE: signature library rejects a signature.
+    InvalidSignature = -28,
+    /// ERROR_UNSUPPORTED_SETUP_VERSION: PoA setup version is unknown.
+    UnsupportedSetupVersion = -29,
+    /// ERROR_POA_PAUSED: PoA setup is paused, only setup updates are accepted.
+    Paused = -30,
+    /// ERROR_INVALID_MISSED_ROUNDS: missed rounds miss skipped aggregators.
+    InvalidMissedRounds = -31,
+    /// ERROR_INVALID_WEIGHTS: weights lack a non-zero one per aggregator.
+    InvalidWeights = -32,
+    /// ERROR_INVALID_VOTING_WEIGHTS: voting weights or threshold are invalid.
+    InvalidVotingWeights = -33,
+    /// ERROR_PROPOSAL_REQUIRED: setup is changed directly under a proposal delay.
+    ProposalRequired = -34,
+    /// ERROR_INVALID_PROPOSAL: update neither proposes, cancels nor commits.
+    InvalidProposal = -35,
+    /// ERROR_PROPOSAL_NOT_ACTIVE: proposal is committed before it is active.
+    ProposalNotActive = -36,
+}
+
+impl PoaError {
+    pub fn code(self) -> i8 {
+        self as i8
+    }
+
+    /// Maps a PoA lock exit code back to the error, returns `None` for
+    /// success and syscall errors.
+    pub fn from_code(code: i8) -> Option<Self> {
+        let error = match code {
+            -1 => PoaError::Transaction,
+            -2 => PoaError::Encoding,
+            -3 => PoaError::DynamicLoading,
+            -4 => PoaError::InvalidArgs,
+            -5 => PoaError::SetupTooShort,
+            -6 => PoaError::InvalidIdentitySize,
+            -7 => PoaError::InvalidChangeThreshold,
+            -8 => PoaError::SetupLengthMismatch,
+            -9 => PoaError::SetupCellTooLarge,
+            -10 => PoaError::DuplicatePoACell,
+            -11 => PoaError::PoASetupCellMissing,
+            -12 => PoaError::PoADataCellMissing,
+            -13 => PoaError::InvalidPoAData,
+            -14 => PoaError::InvalidAggregatorIndex,
+            -15 => PoaError::InvalidSince,
+            -16 => PoaError::SubtimeMismatch,
+            -17 => PoaError::InvalidRoundInitialSubtime,
+            -18 => PoaError::SubtimeDecreased,
+            -19 => PoaError::AggregatorChanged,
+            -20 => PoaError::InvalidSubblockIndex,
+            -21 => PoaError::RoundNotStarted,
+            -22 => PoaError::OwnerNotFound,
+            -23 => PoaError::NotEnoughApprovals,
+            -24 => PoaError::NoIdentity,
+            -25 => PoaError::ThresholdBelowMinimum,
+            -26 => PoaError::PoADataNotReset,
+            -27 => PoaError::InvalidWitness,
+            -28 => PoaError::InvalidSignature,
+            -29 => PoaError::UnsupportedSetupVersion,
+            -30 => PoaError::Paused,
+            -31 => PoaError::InvalidMissedRounds,
+            -32 => PoaError::InvalidWeights,
+            -33 => PoaError::InvalidVotingWeights,
+            -34 => PoaError::ProposalRequired,
+            -35 => PoaError::InvalidProposal,
+            -36 => PoaError::ProposalNotActive,
+            _ => return None,
+        };
+        Some(error)
+    }
+}
+
+impl fmt::Display for PoaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            PoaError::Transaction => "Transaction has more than one cell using PoA lock",
+            PoaError::Encoding => "Invalid script encoding",
+            PoaError::DynamicLoading => "Dynamic loading failure",
+            PoaError::InvalidArgs => "Script args must be 64 or 65 bytes long",
+            PoaError::SetupTooShort => "PoA setup is too short",
+            PoaError::InvalidIdentitySize => "Invalid identity size",
+            PoaError::InvalidChangeThreshold => "Invalid aggregator change threshold",
+            PoaError::SetupLengthMismatch => "PoA setup has invalid length",
+            PoaError::SetupCellTooLarge => "PoA setup cell is too large",
+            PoaError::DuplicatePoACell => "Duplicate PoA cell",
+            PoaError::PoASetupCellMissing => "PoA setup cell is missing",
+            PoaError::PoADataCellMissing => "PoA data cell is missing",
+            PoaError::InvalidPoAData => "Invalid PoA data cell",
+            PoaError::InvalidAggregatorIndex => "Invalid aggregator index",
+            PoaError::InvalidSince => "Invalid since",
+            PoaError::SubtimeMismatch => "Since does not match subblock subtime",
+            PoaError::InvalidRoundInitialSubtime => "Invalid round initial subtime",
+            PoaError::SubtimeDecreased => "Subblock subtime decreased",
+            PoaError::AggregatorChanged => "Aggregator changed within a round",
+            PoaError::InvalidSubblockIndex => "Invalid subblock index",
+            PoaError::RoundNotStarted => "Round has not started",
+            PoaError::OwnerNotFound => "No matching identity found",
+            PoaError::NotEnoughApprovals => "Not enough matching identities found",
+            PoaError::NoIdentity => "New PoA setup has no identity",
+            PoaError::ThresholdBelowMinimum => "New aggregator change threshold is below minimum",
+            PoaError::PoADataNotReset => "PoA data is not reset",
+            PoaError::InvalidWitness => "Invalid PoA cell witness",
+            PoaError::InvalidSignature => "Invalid signature",
+            PoaError::UnsupportedSetupVersion => "Unsupported PoA setup version",
+            PoaError::Paused => "Subblock production is paused",
+            PoaError::InvalidMissedRounds => "Invalid missed rounds",
+            PoaError::InvalidWeights => "Invalid aggregator weights",
+            PoaError::InvalidVotingWeights => "Invalid voting weights",
+            PoaError::ProposalRequired => "Setup changes must be proposed first",
+            PoaError::InvalidProposal => "Invalid setup proposal",
+            PoaError::ProposalNotActive => "Proposal is not active yet",
+        };
+        write!(f, "{} ({})", message, self.code())
+    }
+}
+
+impl std::error::Error for PoaError {}
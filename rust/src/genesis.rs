@@ -0,0 +1,315 @@
+use crate::cell::CellInfo;
+use crate::error::Error;
+use crate::poa::{serialize_poa_data, serialize_poa_setup, PoAData, PoASetup, POA_LOCK_ARGS_SIZE};
+use blake2b_ref::Blake2bBuilder;
+use ckb_tool::ckb_types::{
+    bytes::{Bytes, BytesMut},
+    core::{Capacity, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+
+pub const TYPE_ID_CODE_HASH: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x54, 0x59, 0x50,
+    0x45, 0x5f, 0x49, 0x44,
+];
+
+pub const POA_CELL_INDEX: usize = 0;
+pub const POA_SETUP_CELL_INDEX: usize = 1;
+pub const POA_DATA_CELL_INDEX: usize = 2;
+
+/// Calculates type ID args for the cell at `output_index`, in a transaction
+/// whose first input is `first_input`.
+pub fn type_id_args(first_input: &CellInput, output_index: u64) -> Bytes {
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut hash = vec![0u8; 32];
+    blake2b.finalize(&mut hash[..]);
+    Bytes::from(hash)
+}
+
+pub fn type_id_script(args: Bytes) -> Script {
+    Script::new_builder()
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(args.pack())
+        .build()
+}
+
+/// Builds args of the PoA data type script in `c/poa_data.c`: type ID args
+/// followed by code hash and hash type of the PoA lock, so the type script can
+/// require the PoA cell whenever PoA data changes.
+pub fn poa_data_type_args(type_id_args: &[u8], poa_lock: &Script) -> Bytes {
+    let mut buffer = BytesMut::with_capacity(65);
+    buffer.extend_from_slice(type_id_args);
+    buffer.extend_from_slice(poa_lock.code_hash().as_slice());
+    buffer.extend_from_slice(poa_lock.hash_type().as_slice());
+    buffer.freeze()
+}
+
+/// Everything created by the genesis transaction of a PoA deployment.
+#[derive(Clone, Debug)]
+pub struct Genesis {
+    pub poa_setup_type_id_args: Bytes,
+    pub poa_data_type_id_args: Bytes,
+    pub poa_lock_args: Bytes,
+    pub state_lock_args: Bytes,
+    pub poa_data: PoAData,
+    pub tx: TransactionView,
+}
+
+impl Genesis {
+    fn cell(&self, index: usize) -> CellInfo {
+        CellInfo::new(
+            OutPoint::new(self.tx.hash(), index as u32),
+            self.tx.outputs().get(index).expect("output"),
+            self.tx
+                .outputs_data()
+                .get(index)
+                .expect("output data")
+                .raw_data(),
+        )
+    }
+
+    pub fn poa_cell(&self) -> CellInfo {
+        self.cell(POA_CELL_INDEX)
+    }
+
+    pub fn poa_setup_cell(&self) -> CellInfo {
+        self.cell(POA_SETUP_CELL_INDEX)
+    }
+
+    pub fn poa_data_cell(&self) -> CellInfo {
+        self.cell(POA_DATA_CELL_INDEX)
+    }
+}
+
+/// Assembles the transaction bootstrapping a PoA deployment, following the
+/// steps described in `c/state.c`: the PoA cell, PoA setup cell and PoA data
+/// cell are created together, the type IDs of the 2 state cells fill in the
+/// PoA lock args, and the PoA lock hash fills in the state lock args.
+///
+/// The first 3 outputs are the PoA cell, PoA setup cell and PoA data cell.
+/// Unless specified otherwise, each cell is created with exactly the
+/// capacity it occupies, any change should be added via `output`.
+pub struct GenesisTxBuilder {
+    funding: CellInfo,
+    setup: PoASetup,
+    poa_lock: Script,
+    state_lock: Script,
+    poa_cell_data: Bytes,
+    poa_data: PoAData,
+    poa_data_type: Option<Script>,
+    minimum_change_threshold: Option<u8>,
+    capacities: [Option<u64>; 3],
+    inputs: Vec<CellInfo>,
+    outputs: Vec<(CellOutput, Bytes)>,
+    cell_deps: Vec<CellDep>,
+}
+
+impl GenesisTxBuilder {
+    /// `poa_lock` and `state_lock` provide code hash and hash type of the
+    /// PoA lock and state lock, their args are filled in by the builder.
+    pub fn new(funding: CellInfo, setup: PoASetup, poa_lock: Script, state_lock: Script) -> Self {
+        GenesisTxBuilder {
+            funding,
+            setup,
+            poa_lock,
+            state_lock,
+            poa_cell_data: Bytes::new(),
+            poa_data: PoAData::default(),
+            poa_data_type: None,
+            minimum_change_threshold: None,
+            capacities: [None; 3],
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            cell_deps: Vec::new(),
+        }
+    }
+
+    pub fn poa_cell_data(mut self, data: Bytes) -> Self {
+        self.poa_cell_data = data;
+        self
+    }
+
+    /// Sets the subtime at which the first round starts. Aggregator 0 owns
+    /// the first round, and has already issued subblock 0 in it.
+    pub fn initial_subtime(mut self, subtime: u64) -> Self {
+        self.poa_data = PoAData {
+            round_initial_subtime: subtime,
+            subblock_subtime: subtime,
+            subblock_index: 0,
+            aggregator_index: 0,
+            missed_rounds: self.poa_data.missed_rounds,
+        };
+        self
+    }
+
+    /// Makes the PoA data cell track missed rounds of each aggregator, all
+    /// starting at 0.
+    pub fn track_missed_rounds(mut self) -> Self {
+        self.poa_data.missed_rounds = vec![0; self.setup.identities.len()];
+        self
+    }
+
+    pub fn poa_data(mut self, poa_data: PoAData) -> Self {
+        self.poa_data = poa_data;
+        self
+    }
+
+    /// Guards the PoA data cell with the PoA data type script in
+    /// `c/poa_data.c` instead of a plain type ID script. `script` provides
+    /// code hash and hash type, its args are filled in via `poa_data_type_args`,
+    /// and PoA lock args refer to the PoA data cell via its type script hash.
+    pub fn poa_data_type(mut self, script: Script) -> Self {
+        self.poa_data_type = Some(script);
+        self
+    }
+
+    /// Sets the minimum aggregator change threshold accepted in setup
+    /// updates, which is kept in PoA lock args.
+    pub fn minimum_change_threshold(mut self, threshold: u8) -> Self {
+        self.minimum_change_threshold = Some(threshold);
+        self
+    }
+
+    pub fn poa_cell_capacity(mut self, capacity: u64) -> Self {
+        self.capacities[POA_CELL_INDEX] = Some(capacity);
+        self
+    }
+
+    pub fn poa_setup_cell_capacity(mut self, capacity: u64) -> Self {
+        self.capacities[POA_SETUP_CELL_INDEX] = Some(capacity);
+        self
+    }
+
+    pub fn poa_data_cell_capacity(mut self, capacity: u64) -> Self {
+        self.capacities[POA_DATA_CELL_INDEX] = Some(capacity);
+        self
+    }
+
+    /// Adds an extra funding cell.
+    pub fn input(mut self, cell: CellInfo) -> Self {
+        self.inputs.push(cell);
+        self
+    }
+
+    pub fn output(mut self, output: CellOutput, data: Bytes) -> Self {
+        self.outputs.push((output, data));
+        self
+    }
+
+    pub fn cell_dep(mut self, cell_dep: CellDep) -> Self {
+        self.cell_deps.push(cell_dep);
+        self
+    }
+
+    fn fill_capacity(
+        &self,
+        index: usize,
+        output: CellOutput,
+        data: &Bytes,
+    ) -> Result<CellOutput, Error> {
+        let capacity = match self.capacities[index] {
+            Some(capacity) => capacity,
+            None => Capacity::bytes(data.len())
+                .and_then(|data_capacity| output.occupied_capacity(data_capacity))
+                .map_err(|_| Error::CapacityOverflow)?
+                .as_u64(),
+        };
+        Ok(output.as_builder().capacity(capacity.pack()).build())
+    }
+
+    pub fn build(&self) -> Result<Genesis, Error> {
+        let setup_data = serialize_poa_setup(&self.setup)?;
+        let first_input = self.funding.input(0);
+        let poa_setup_type_id_args = type_id_args(&first_input, POA_SETUP_CELL_INDEX as u64);
+        let poa_data_type_id_args = type_id_args(&first_input, POA_DATA_CELL_INDEX as u64);
+        let poa_data_type = match &self.poa_data_type {
+            Some(script) => script
+                .clone()
+                .as_builder()
+                .args(poa_data_type_args(&poa_data_type_id_args, &self.poa_lock).pack())
+                .build(),
+            None => type_id_script(poa_data_type_id_args.clone()),
+        };
+        let poa_lock_args = {
+            let mut buffer = BytesMut::with_capacity(POA_LOCK_ARGS_SIZE + 1);
+            buffer.extend_from_slice(&poa_setup_type_id_args);
+            if self.poa_data_type.is_some() {
+                buffer.extend_from_slice(poa_data_type.calc_script_hash().as_slice());
+            } else {
+                buffer.extend_from_slice(&poa_data_type_id_args);
+            }
+            if let Some(threshold) = self.minimum_change_threshold {
+                buffer.extend_from_slice(&[threshold]);
+            }
+            buffer.freeze()
+        };
+        let poa_lock = self
+            .poa_lock
+            .clone()
+            .as_builder()
+            .args(poa_lock_args.pack())
+            .build();
+        let state_lock_args = poa_lock.calc_script_hash().as_bytes();
+        let state_lock = self
+            .state_lock
+            .clone()
+            .as_builder()
+            .args(state_lock_args.pack())
+            .build();
+        let poa_data = serialize_poa_data(&self.poa_data);
+
+        let poa_output = self.fill_capacity(
+            POA_CELL_INDEX,
+            CellOutput::new_builder().lock(poa_lock).build(),
+            &self.poa_cell_data,
+        )?;
+        let poa_setup_output = self.fill_capacity(
+            POA_SETUP_CELL_INDEX,
+            CellOutput::new_builder()
+                .lock(state_lock.clone())
+                .type_(
+                    ScriptOpt::new_builder()
+                        .set(Some(type_id_script(poa_setup_type_id_args.clone())))
+                        .build(),
+                )
+                .build(),
+            &setup_data,
+        )?;
+        let poa_data_output = self.fill_capacity(
+            POA_DATA_CELL_INDEX,
+            CellOutput::new_builder()
+                .lock(state_lock)
+                .type_(ScriptOpt::new_builder().set(Some(poa_data_type)).build())
+                .build(),
+            &poa_data,
+        )?;
+
+        let tx = TransactionBuilder::default()
+            .input(first_input)
+            .inputs(self.inputs.iter().map(|cell| cell.input(0)))
+            .output(poa_output)
+            .output_data(self.poa_cell_data.pack())
+            .output(poa_setup_output)
+            .output_data(setup_data.pack())
+            .output(poa_data_output)
+            .output_data(poa_data.pack())
+            .outputs(self.outputs.iter().map(|(output, _)| output.clone()))
+            .outputs_data(self.outputs.iter().map(|(_, data)| data.pack()))
+            .cell_deps(self.cell_deps.clone())
+            .build();
+        Ok(Genesis {
+            poa_setup_type_id_args,
+            poa_data_type_id_args,
+            poa_lock_args,
+            state_lock_args,
+            poa_data: self.poa_data.clone(),
+            tx,
+        })
+    }
+}
@@ -0,0 +1,551 @@
+use crate::cell::CellInfo;
+use crate::error::Error;
+use crate::poa::{
+    is_poa_data_cell, is_poa_lock_args, is_poa_setup_v1, parse_poa_setup, poa_setup_v1_fields,
+    replace_setup_proposal, serialize_poa_data, serialize_poa_setup, PoAData, PoASetup,
+    SetupProposal, POA_LOCK_ARGS_SIZE, POA_SETUP_V1_PAUSED_FIELD, POA_SETUP_V1_PROPOSAL_FIELD,
+};
+use crate::subblock::{subtime_to_since, SINCE_TIMESTAMP_FLAG};
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use std::iter;
+
+/// Marks the identities approved by the given input lock hashes, or public
+/// key hashes of signers when identities are public key hashes, following
+/// the same matching rules as `validate_consensus_signing` in `c/poa.c`: each
+/// input approves at most one identity, and each identity is only counted
+/// once.
+pub fn approved_identities(setup: &PoASetup, lock_hashes: &[Bytes]) -> Vec<bool> {
+    let size = setup.identity_size as usize;
+    let mut approved = vec![false; setup.identities.len()];
+    for lock_hash in lock_hashes {
+        if lock_hash.len() < size {
+            continue;
+        }
+        let found = (0..setup.identities.len()).find(|i| {
+            let identity = &setup.identities[*i];
+            !approved[*i] && identity.len() >= size && identity[..size] == lock_hash[..size]
+        });
+        if let Some(index) = found {
+            approved[index] = true;
+        }
+    }
+    approved
+}
+
+/// Sum of the voting weights of approved identities, see
+/// `approved_identities`. Without voting weights, each approval counts as 1.
+pub fn approved_voting_weight(setup: &PoASetup, approved: &[bool]) -> u64 {
+    approved
+        .iter()
+        .enumerate()
+        .filter(|(_, approved)| **approved)
+        .map(|(index, _)| setup.voting_weights.get(index).map_or(1, |w| *w as u64))
+        .sum()
+}
+
+/// Checks whether approved identities can update the setup, like
+/// `validate_consensus_signing` in `c/poa.c`: their voting weights must
+/// reach `voting_threshold`, or without voting weights, at least
+/// `aggregator_change_threshold` identities must approve. A threshold of 0
+/// is never reached, rendering the setup immutable.
+pub fn has_enough_approvals(setup: &PoASetup, approved: &[bool]) -> bool {
+    let (required, approved) = if setup.voting_weights.is_empty() {
+        (
+            setup.aggregator_change_threshold as u64,
+            approved.iter().filter(|approved| **approved).count() as u64,
+        )
+    } else {
+        (
+            setup.voting_threshold as u64,
+            approved_voting_weight(setup, approved),
+        )
+    };
+    required > 0 && approved >= required
+}
+
+/// Minimum aggregator change threshold accepted in setup updates, unless PoA
+/// lock args specify a different one.
+pub const DEFAULT_MINIMUM_CHANGE_THRESHOLD: u8 = 1;
+
+/// Reads the minimum aggregator change threshold from PoA lock args.
+pub fn minimum_change_threshold(poa_lock_args: &[u8]) -> u8 {
+    poa_lock_args
+        .get(POA_LOCK_ARGS_SIZE)
+        .cloned()
+        .unwrap_or(DEFAULT_MINIMUM_CHANGE_THRESHOLD)
+}
+
+/// Checks whether PoA data starts a fresh round valid in the new setup, with
+/// missed rounds, if tracked, starting over from 0.
+pub fn is_poa_data_reset(new_setup: &PoASetup, poa_data: &PoAData) -> bool {
+    poa_data.round_initial_subtime == poa_data.subblock_subtime
+        && poa_data.subblock_index == 0
+        && (poa_data.aggregator_index as usize) < new_setup.identities.len()
+        && poa_data.check_missed_rounds(new_setup).is_ok()
+        && poa_data.missed_rounds.iter().all(|counter| *counter == 0)
+}
+
+/// Pairs the identity of each aggregator with the number of rounds it has
+/// missed, so governance can spot aggregators that are chronically offline.
+pub fn missed_rounds(setup: &PoASetup, poa_data: &PoAData) -> Result<Vec<(Bytes, u32)>, Error> {
+    poa_data.check_missed_rounds(setup)?;
+    if poa_data.missed_rounds.is_empty() {
+        return Err(Error::InvalidMissedRounds {
+            expected: setup.identities.len(),
+            actual: 0,
+        });
+    }
+    Ok(setup
+        .identities
+        .iter()
+        .map(|identity| identity.slice(0..setup.identity_size as usize))
+        .zip(poa_data.missed_rounds.iter().cloned())
+        .collect())
+}
+
+/// Identities of aggregators that have missed more than `max_missed_rounds`
+/// rounds, candidates for eviction via a setup update.
+pub fn offline_aggregators(
+    setup: &PoASetup,
+    poa_data: &PoAData,
+    max_missed_rounds: u32,
+) -> Result<Vec<Bytes>, Error> {
+    Ok(missed_rounds(setup, poa_data)?
+        .into_iter()
+        .filter(|(_, missed_rounds)| *missed_rounds > max_missed_rounds)
+        .map(|(identity, _)| identity)
+        .collect())
+}
+
+/// Checks whether 2 setups have different aggregator lists, as seen by the
+/// PoA lock.
+pub fn identities_changed(setup: &PoASetup, new_setup: &PoASetup) -> bool {
+    let size = setup.identity_size as usize;
+    setup.identity_size != new_setup.identity_size
+        || setup.identities.len() != new_setup.identities.len()
+        || setup
+            .identities
+            .iter()
+            .zip(new_setup.identities.iter())
+            .any(|(a, b)| a.get(..size) != b.get(..size))
+}
+
+/// Rewrites PoA data for a new aggregator list, as a fresh round starting at
+/// the subtime of the last subblock. The round stays with the last aggregator
+/// if it is kept in the new setup, otherwise it passes to the first following
+/// aggregator that is kept. Missed rounds, if tracked, start over from 0 for
+/// the new aggregator list.
+pub fn migrate_poa_data(setup: &PoASetup, new_setup: &PoASetup, poa_data: &PoAData) -> PoAData {
+    let size = setup.identity_size as usize;
+    let count = setup.identities.len();
+    let aggregator_index = (0..count)
+        .map(|step| (poa_data.aggregator_index as usize + step) % count)
+        .filter_map(|index| setup.identities[index].get(..size))
+        .find_map(|identity| new_setup.find_aggregator(identity))
+        .unwrap_or(0);
+    PoAData {
+        round_initial_subtime: poa_data.subblock_subtime,
+        subblock_subtime: poa_data.subblock_subtime,
+        subblock_index: 0,
+        aggregator_index: aggregator_index as u16,
+        missed_rounds: if poa_data.missed_rounds.is_empty() {
+            Vec::new()
+        } else {
+            vec![0; new_setup.identities.len()]
+        },
+    }
+}
+
+/// Checks a setup update against the governance rules of the PoA lock: the
+/// new setup keeps at least one identity, its aggregator change threshold
+/// does not fall below `minimum_change_threshold`, and switching the kind of
+/// round intervals or changing the aggregator list comes with `poa_data`
+/// rewritten to a fresh round.
+pub fn validate_setup_update(
+    setup: &PoASetup,
+    new_setup: &PoASetup,
+    minimum_change_threshold: u8,
+    poa_data: Option<&PoAData>,
+) -> Result<(), Error> {
+    if new_setup.identities.is_empty() {
+        return Err(Error::NoIdentity);
+    }
+    if new_setup.aggregator_change_threshold < minimum_change_threshold {
+        return Err(Error::ThresholdBelowMinimum {
+            threshold: new_setup.aggregator_change_threshold,
+            minimum: minimum_change_threshold,
+        });
+    }
+    if new_setup.round_interval_uses_seconds != setup.round_interval_uses_seconds
+        || identities_changed(setup, new_setup)
+    {
+        match poa_data {
+            Some(poa_data) if is_poa_data_reset(new_setup, poa_data) => (),
+            _ => return Err(Error::PoADataResetRequired),
+        }
+    }
+    Ok(())
+}
+
+/// Set in relative `since` values, which are measured from the time the
+/// input cell is committed.
+pub const SINCE_RELATIVE_FLAG: u64 = 0x8000_0000_0000_0000;
+
+/// Builds the relative `since` value required on the PoA setup cell by the
+/// PoA lock to commit a proposal, which makes sure the proposal delay has
+/// passed since the proposal.
+pub fn proposal_delay_since(setup: &PoASetup) -> u64 {
+    let since = SINCE_RELATIVE_FLAG | setup.proposal_delay as u64;
+    if setup.round_interval_uses_seconds {
+        since | SINCE_TIMESTAMP_FLAG
+    } else {
+        since
+    }
+}
+
+// Like `same_setup_except` in `c/poa.c`, raw field bytes are compared, so an
+// omitted optional field differs from its default value.
+fn same_setup_except(setup_data: &[u8], new_setup_data: &[u8], skipped_field: usize) -> bool {
+    if !is_poa_setup_v1(setup_data) || !is_poa_setup_v1(new_setup_data) {
+        return false;
+    }
+    match (
+        poa_setup_v1_fields(setup_data),
+        poa_setup_v1_fields(new_setup_data),
+    ) {
+        (Ok(fields), Ok(new_fields)) => fields
+            .iter()
+            .zip(new_fields.iter())
+            .enumerate()
+            .all(|(i, (field, new_field))| i == skipped_field || field == new_field),
+        _ => false,
+    }
+}
+
+/// Checks a setup update against the time lock rules of the PoA lock. When
+/// current setup has a proposal delay, a setup update either adds a proposal
+/// without changing anything else, cancels the pending proposal, or commits
+/// it. Only pausing or resuming PoA is applied directly under a delay.
+/// Otherwise the setup is changed directly. The activation subtime and
+/// proposal delay of a commit are checked on chain via `since` values.
+///
+/// Both setups are passed serialized, since the PoA lock compares them byte
+/// for byte.
+pub fn validate_setup_proposal(setup_data: &[u8], new_setup_data: &[u8]) -> Result<(), Error> {
+    let setup = parse_poa_setup(setup_data)?;
+    let new_setup = parse_poa_setup(new_setup_data)?;
+    match (&setup.proposal, &new_setup.proposal) {
+        (Some(_), None)
+            if same_setup_except(setup_data, new_setup_data, POA_SETUP_V1_PROPOSAL_FIELD) =>
+        {
+            Ok(())
+        }
+        (Some(proposal), _) if proposal.setup == new_setup_data => Ok(()),
+        (Some(_), _) => Err(Error::InvalidProposal),
+        (None, Some(_)) if setup.proposal_delay == 0 => Err(Error::NoProposalDelay),
+        (None, Some(proposal)) => {
+            if !same_setup_except(setup_data, new_setup_data, POA_SETUP_V1_PROPOSAL_FIELD) {
+                return Err(Error::InvalidProposal);
+            }
+            if proposal.parse_setup()?.proposal.is_some() {
+                return Err(Error::NestedProposal);
+            }
+            Ok(())
+        }
+        // Pausing or resuming is applied directly even with a proposal delay
+        (None, None)
+            if setup.proposal_delay != 0
+                && !same_setup_except(setup_data, new_setup_data, POA_SETUP_V1_PAUSED_FIELD) =>
+        {
+            Err(Error::ProposalRequired)
+        }
+        (None, None) => Ok(()),
+    }
+}
+
+enum SetupUpdateKind {
+    Direct(PoASetup),
+    Propose {
+        setup: PoASetup,
+        activation_subtime: u64,
+    },
+    Commit,
+    Cancel,
+}
+
+/// Assembles a transaction updating the PoA setup, which needs approvals from
+/// at least `aggregator_change_threshold` distinct aggregators in current
+/// setup, or when current setup has voting weights, approvals adding up to
+/// `voting_threshold`. An aggregator approves an update by providing an input cell using
+/// its owner lock, or when identities are public key hashes, by signing the
+/// transaction with `sign_poa_tx` after registering itself via `signer`.
+///
+/// When current setup has a proposal delay, setup changes are time locked:
+/// they are submitted via `propose`, and applied via `commit` once the delay
+/// has passed, or dropped via `cancel`. Each step needs the same approvals.
+///
+/// The generated transaction has the PoA cell and PoA setup cell as its
+/// first 2 inputs and outputs, followed by the PoA data cell when it is
+/// rewritten. Notice the PoA setup cell must not be included
+/// in cell deps, otherwise the PoA lock would validate the transaction as a
+/// new subblock.
+pub struct SetupUpdateTxBuilder {
+    poa_cell: CellInfo,
+    poa_setup_cell: CellInfo,
+    kind: SetupUpdateKind,
+    poa_data: Option<(CellInfo, PoAData)>,
+    approvals: Vec<CellInfo>,
+    signers: Vec<Bytes>,
+    poa_output: Option<(CellOutput, Bytes)>,
+    inputs: Vec<CellInfo>,
+    outputs: Vec<(CellOutput, Bytes)>,
+    cell_deps: Vec<CellDep>,
+}
+
+impl SetupUpdateTxBuilder {
+    pub fn new(poa_cell: CellInfo, poa_setup_cell: CellInfo, new_setup: PoASetup) -> Self {
+        Self::with_kind(poa_cell, poa_setup_cell, SetupUpdateKind::Direct(new_setup))
+    }
+
+    /// Proposes `new_setup` in a time-locked setup, which can be committed
+    /// no earlier than `activation_subtime`, and the proposal delay after the
+    /// proposal.
+    pub fn propose(
+        poa_cell: CellInfo,
+        poa_setup_cell: CellInfo,
+        new_setup: PoASetup,
+        activation_subtime: u64,
+    ) -> Self {
+        Self::with_kind(
+            poa_cell,
+            poa_setup_cell,
+            SetupUpdateKind::Propose {
+                setup: new_setup,
+                activation_subtime,
+            },
+        )
+    }
+
+    /// Commits the pending proposal. The transaction sets `since` values
+    /// on the PoA cell and PoA setup cell, so it is only accepted on chain
+    /// once the activation subtime and proposal delay have passed.
+    pub fn commit(poa_cell: CellInfo, poa_setup_cell: CellInfo) -> Self {
+        Self::with_kind(poa_cell, poa_setup_cell, SetupUpdateKind::Commit)
+    }
+
+    /// Cancels the pending proposal.
+    pub fn cancel(poa_cell: CellInfo, poa_setup_cell: CellInfo) -> Self {
+        Self::with_kind(poa_cell, poa_setup_cell, SetupUpdateKind::Cancel)
+    }
+
+    fn with_kind(poa_cell: CellInfo, poa_setup_cell: CellInfo, kind: SetupUpdateKind) -> Self {
+        SetupUpdateTxBuilder {
+            poa_cell,
+            poa_setup_cell,
+            kind,
+            poa_data: None,
+            approvals: Vec::new(),
+            signers: Vec::new(),
+            poa_output: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            cell_deps: Vec::new(),
+        }
+    }
+
+    /// Rewrites the PoA data cell, which is required when switching the kind
+    /// of round intervals or changing the aggregator list. See
+    /// `migrate_poa_data` for the latter.
+    pub fn poa_data_cell(mut self, cell: CellInfo, poa_data: PoAData) -> Self {
+        self.poa_data = Some((cell, poa_data));
+        self
+    }
+
+    /// Adds an owner cell of an approving aggregator.
+    pub fn approval(mut self, cell: CellInfo) -> Self {
+        self.approvals.push(cell);
+        self
+    }
+
+    /// Adds the public key hash of an approving aggregator, which signs the
+    /// transaction once built.
+    pub fn signer(mut self, pubkey_hash: Bytes) -> Self {
+        self.signers.push(pubkey_hash);
+        self
+    }
+
+    /// Sets the new PoA cell. By default the PoA cell is recreated unchanged.
+    pub fn poa_output(mut self, output: CellOutput, data: Bytes) -> Self {
+        self.poa_output = Some((output, data));
+        self
+    }
+
+    pub fn input(mut self, cell: CellInfo) -> Self {
+        self.inputs.push(cell);
+        self
+    }
+
+    pub fn output(mut self, output: CellOutput, data: Bytes) -> Self {
+        self.outputs.push((output, data));
+        self
+    }
+
+    pub fn cell_dep(mut self, cell_dep: CellDep) -> Self {
+        self.cell_deps.push(cell_dep);
+        self
+    }
+
+    pub fn poa_setup(&self) -> Result<PoASetup, Error> {
+        parse_poa_setup(&self.poa_setup_cell.data)
+    }
+
+    /// Serialized setup written to the PoA setup cell by the update. Proposals
+    /// are added to or removed from current setup in place, and committed as
+    /// is.
+    pub fn new_setup_data(&self) -> Result<Bytes, Error> {
+        let setup = self.poa_setup()?;
+        match &self.kind {
+            SetupUpdateKind::Direct(new_setup) => serialize_poa_setup(new_setup),
+            SetupUpdateKind::Propose {
+                setup: new_setup,
+                activation_subtime,
+            } => {
+                if setup.proposal.is_some() {
+                    return Err(Error::ProposalPending);
+                }
+                let proposal = SetupProposal::new(*activation_subtime, new_setup)?;
+                replace_setup_proposal(&self.poa_setup_cell.data, Some(&proposal))
+            }
+            SetupUpdateKind::Commit => Ok(setup.proposal.ok_or(Error::NoProposal)?.setup),
+            SetupUpdateKind::Cancel => {
+                setup.proposal.ok_or(Error::NoProposal)?;
+                replace_setup_proposal(&self.poa_setup_cell.data, None)
+            }
+        }
+    }
+
+    /// Setup written to the PoA setup cell by the update.
+    pub fn new_setup(&self) -> Result<PoASetup, Error> {
+        parse_poa_setup(&self.new_setup_data()?)
+    }
+
+    fn all_inputs(&self) -> impl Iterator<Item = &CellInfo> {
+        iter::once(&self.poa_cell)
+            .chain(iter::once(&self.poa_setup_cell))
+            .chain(self.poa_data.iter().map(|(cell, _)| cell))
+            .chain(self.approvals.iter())
+            .chain(self.inputs.iter())
+    }
+
+    /// Marks the identities in current setup that approve the update.
+    pub fn approved_identities(&self) -> Result<Vec<bool>, Error> {
+        let setup = self.poa_setup()?;
+        let approvers: Vec<Bytes> = if setup.signature_library.is_some() {
+            self.signers.clone()
+        } else {
+            self.all_inputs().map(|cell| cell.lock_hash()).collect()
+        };
+        Ok(approved_identities(&setup, &approvers))
+    }
+
+    /// Identities in current setup that have not approved the update yet.
+    pub fn missing_approvals(&self) -> Result<Vec<Bytes>, Error> {
+        let setup = self.poa_setup()?;
+        Ok(setup
+            .identities
+            .iter()
+            .zip(self.approved_identities()?)
+            .filter(|(_, approved)| !approved)
+            .map(|(identity, _)| identity.clone())
+            .collect())
+    }
+
+    /// Checks whether current approvers are sufficient to update the setup,
+    /// by head count or by voting weight, depending on current setup.
+    pub fn has_enough_approvals(&self) -> Result<bool, Error> {
+        Ok(has_enough_approvals(
+            &self.poa_setup()?,
+            &self.approved_identities()?,
+        ))
+    }
+
+    fn check_approvals(&self) -> Result<(), Error> {
+        let setup = self.poa_setup()?;
+        let approved = self.approved_identities()?;
+        if has_enough_approvals(&setup, &approved) {
+            return Ok(());
+        }
+        let missing = self.missing_approvals()?;
+        if setup.voting_weights.is_empty() {
+            Err(Error::NotEnoughApprovals {
+                required: setup.aggregator_change_threshold,
+                missing,
+            })
+        } else {
+            Err(Error::NotEnoughVotingWeight {
+                required: setup.voting_threshold,
+                approved: approved_voting_weight(&setup, &approved),
+                missing,
+            })
+        }
+    }
+
+    pub fn build(&self) -> Result<TransactionView, Error> {
+        let args = self.poa_cell.output.lock().args().raw_data();
+        if !is_poa_lock_args(&args) || args.slice(0..32) != self.poa_setup_cell.type_args() {
+            return Err(Error::PoACellMismatch);
+        }
+        if let Some((cell, _)) = &self.poa_data {
+            if !is_poa_data_cell(&args[32..64], cell) {
+                return Err(Error::PoACellMismatch);
+            }
+        }
+        self.check_approvals()?;
+        let setup = self.poa_setup()?;
+        let new_setup_data = self.new_setup_data()?;
+        let new_setup = parse_poa_setup(&new_setup_data)?;
+        let (poa_since, poa_setup_since) = match (&self.kind, &setup.proposal) {
+            (SetupUpdateKind::Commit, Some(proposal)) => (
+                subtime_to_since(&setup, proposal.activation_subtime)?,
+                proposal_delay_since(&setup),
+            ),
+            _ => (0, 0),
+        };
+        validate_setup_proposal(&self.poa_setup_cell.data, &new_setup_data)?;
+        validate_setup_update(
+            &setup,
+            &new_setup,
+            minimum_change_threshold(&args),
+            self.poa_data.as_ref().map(|(_, poa_data)| poa_data),
+        )?;
+        let (poa_output, poa_output_data) = self
+            .poa_output
+            .clone()
+            .unwrap_or_else(|| (self.poa_cell.output.clone(), self.poa_cell.data.clone()));
+
+        let tx = TransactionBuilder::default()
+            .input(self.poa_cell.input(poa_since))
+            .input(self.poa_setup_cell.input(poa_setup_since))
+            .inputs(self.all_inputs().skip(2).map(|cell| cell.input(0)))
+            .output(poa_output)
+            .output_data(poa_output_data.pack())
+            .output(self.poa_setup_cell.output.clone())
+            .output_data(new_setup_data.pack())
+            .outputs(self.poa_data.iter().map(|(cell, _)| cell.output.clone()))
+            .outputs_data(
+                self.poa_data
+                    .iter()
+                    .map(|(_, poa_data)| serialize_poa_data(poa_data).pack()),
+            )
+            .outputs(self.outputs.iter().map(|(output, _)| output.clone()))
+            .outputs_data(self.outputs.iter().map(|(_, data)| data.pack()))
+            .cell_deps(self.cell_deps.clone())
+            .build();
+        Ok(tx)
+    }
+}
@@ -0,0 +1,39 @@
+//! # clerkb
+//!
+//! Rust counterpart of the TypeScript integrator shipped in this repository.
+//! It contains the data structures and encodings shared by the on-chain PoA
+//! lock in `c/poa.c`, so off-chain aggregators never have to copy them.
+
+mod cell;
+mod error;
+mod genesis;
+mod governance;
+mod poa;
+mod scheduler;
+mod signature;
+mod subblock;
+
+pub use cell::CellInfo;
+pub use error::{Error, PoaError};
+pub use genesis::{
+    poa_data_type_args, type_id_args, type_id_script, Genesis, GenesisTxBuilder, POA_CELL_INDEX,
+    POA_DATA_CELL_INDEX, POA_SETUP_CELL_INDEX, TYPE_ID_CODE_HASH,
+};
+pub use governance::{
+    approved_identities, approved_voting_weight, has_enough_approvals, identities_changed,
+    is_poa_data_reset, migrate_poa_data, minimum_change_threshold, missed_rounds,
+    offline_aggregators, proposal_delay_since, validate_setup_proposal, validate_setup_update,
+    SetupUpdateTxBuilder, DEFAULT_MINIMUM_CHANGE_THRESHOLD, SINCE_RELATIVE_FLAG,
+};
+pub use poa::{
+    parse_poa_data, parse_poa_setup, serialize_poa_data, serialize_poa_setup, PoAData, PoASetup,
+    SetupProposal, SignatureLibrary, MAX_IDENTITY_SIZE, MAX_PUBKEY_HASH_IDENTITY_SIZE,
+    MISSED_ROUNDS_SIZE, POA_DATA_SIZE, POA_LOCK_ARGS_SIZE, POA_SETUP_HEADER_SIZE, POA_SETUP_V0,
+    POA_SETUP_V1, POA_SETUP_VERSIONED_FLAG, SIGNATURE_LIBRARY_SIZE,
+};
+pub use scheduler::{
+    current_subtime, handoff_duration, record_missed_rounds, skipped_aggregators,
+    skipped_rotations, steps, RoundScheduler, State,
+};
+pub use signature::{pubkey_hash, sign_poa_tx, signing_message, BLAKE160_SIZE, SIGNATURE_SIZE};
+pub use subblock::{next_poa_data, subtime_to_since, SubblockTxBuilder, SINCE_TIMESTAMP_FLAG};
@@ -0,0 +1,756 @@
+use crate::cell::CellInfo;
+use crate::error::Error;
+use ckb_tool::ckb_types::bytes::{Bytes, BytesMut};
+use std::convert::TryInto;
+
+pub const POA_SETUP_HEADER_SIZE: usize = 12;
+pub const POA_DATA_SIZE: usize = 22;
+/// Size of the missed rounds counter of one aggregator, optionally appended
+/// to PoA data.
+pub const MISSED_ROUNDS_SIZE: usize = 4;
+pub const MAX_IDENTITY_SIZE: u8 = 32;
+/// Maximum identity size when identities are public key hashes.
+pub const MAX_PUBKEY_HASH_IDENTITY_SIZE: u8 = 20;
+pub const SIGNATURE_LIBRARY_SIZE: usize = 33;
+/// Legacy PoA setup layout, a fixed 12-byte header followed by identities.
+pub const POA_SETUP_V0: u8 = 0;
+/// PoA setup encoded as the `PoASetupV1` molecule table in `c/poa_setup.mol`.
+pub const POA_SETUP_V1: u8 = 1;
+/// Set in the first byte of versioned PoA setups, which holds the version.
+pub const POA_SETUP_VERSIONED_FLAG: u8 = 0x80;
+const POA_SETUP_V1_REQUIRED_FIELD_COUNT: usize = 7;
+const POA_SETUP_V1_FIELD_COUNT: usize = 13;
+pub(crate) const POA_SETUP_V1_PAUSED_FIELD: usize = 7;
+pub(crate) const POA_SETUP_V1_PROPOSAL_FIELD: usize = 12;
+/// Size of the activation subtime preceding the proposed setup in a proposal.
+const PROPOSAL_HEADER_SIZE: usize = 8;
+const MOLECULE_NUMBER_SIZE: usize = 4;
+/// Size of the 2 type ID args in PoA lock args, an optional byte after them
+/// holds the minimum aggregator change threshold for setup updates.
+pub const POA_LOCK_ARGS_SIZE: usize = 64;
+
+pub(crate) fn is_poa_lock_args(args: &[u8]) -> bool {
+    args.len() == POA_LOCK_ARGS_SIZE || args.len() == POA_LOCK_ARGS_SIZE + 1
+}
+
+/// Checks if `cell` is the PoA data cell referenced by `id` in PoA lock args,
+/// which is either type ID args, or the type script hash when the cell uses
+/// the PoA data type script.
+pub(crate) fn is_poa_data_cell(id: &[u8], cell: &CellInfo) -> bool {
+    cell.type_args() == id || cell.type_hash() == id
+}
+
+/// Configuration of the PoA lock, stored in the PoA setup cell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoASetup {
+    /// Encoding of the setup, `POA_SETUP_V0` or `POA_SETUP_V1`. Parsing keeps
+    /// the encoding found in the setup cell.
+    pub version: u8,
+    pub identity_size: u8,
+    pub round_interval_uses_seconds: bool,
+    pub identities: Vec<Bytes>,
+    pub aggregator_change_threshold: u8,
+    pub round_intervals: u32,
+    pub subblocks_per_round: u32,
+    /// When present, identities are secp256k1 public key hashes, and
+    /// aggregators sign transactions in the PoA cell witness instead of
+    /// providing owner cells.
+    pub signature_library: Option<SignatureLibrary>,
+    /// Halts subblock production until a setup update clears it. Like any
+    /// setup change, pausing requires `aggregator_change_threshold`
+    /// approvals.
+    pub paused: bool,
+    /// Weight of each aggregator, in the order of identities. An aggregator
+    /// with weight `w` owns `w` consecutive slots: its rounds last
+    /// `w * round_intervals`, and hold up to `w * subblocks_per_round`
+    /// subblocks. Empty when all aggregators have weight 1, only
+    /// `POA_SETUP_V1` can hold weights.
+    pub weights: Vec<u8>,
+    /// Voting weight of each aggregator in setup updates, in the order of
+    /// identities. When present, a setup update needs approvals whose voting
+    /// weights add up to `voting_threshold`, instead of
+    /// `aggregator_change_threshold` distinct approvals. Only
+    /// `POA_SETUP_V1` can hold voting weights.
+    pub voting_weights: Vec<u32>,
+    /// Voting weight required by a setup update, 0 without voting weights.
+    pub voting_threshold: u32,
+    /// When not 0, setup changes are time locked: they must be proposed
+    /// first, and can only be committed `proposal_delay` seconds or blocks
+    /// after the proposal. Only `POA_SETUP_V1` can hold a proposal delay.
+    pub proposal_delay: u32,
+    /// Pending setup change, see `SetupUpdateTxBuilder::propose`.
+    pub proposal: Option<SetupProposal>,
+}
+
+/// Setup change proposed in a time-locked setup, which can be committed once
+/// both `activation_subtime` and the proposal delay have passed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetupProposal {
+    pub activation_subtime: u64,
+    /// Serialized proposed setup, committed to the PoA setup cell as is.
+    pub setup: Bytes,
+}
+
+impl SetupProposal {
+    pub fn new(activation_subtime: u64, setup: &PoASetup) -> Result<Self, Error> {
+        if setup.proposal.is_some() {
+            return Err(Error::NestedProposal);
+        }
+        Ok(SetupProposal {
+            activation_subtime,
+            setup: serialize_poa_setup(setup)?,
+        })
+    }
+
+    pub fn parse_setup(&self) -> Result<PoASetup, Error> {
+        parse_poa_setup(&self.setup)
+    }
+}
+
+/// Dynamic library validating secp256k1 signatures, such as
+/// `secp256k1_blake2b_sighash_all_dual`, loaded by the PoA lock from cell
+/// deps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureLibrary {
+    pub code_hash: [u8; 32],
+    pub hash_type: u8,
+}
+
+/// Legacy encoded setup with 32-byte identities and no aggregators, where
+/// all optional features are off. Fields added by newer setup versions keep
+/// this default, so setups can be written as
+/// `PoASetup { identities, ..Default::default() }`.
+impl Default for PoASetup {
+    fn default() -> Self {
+        PoASetup {
+            version: POA_SETUP_V0,
+            identity_size: MAX_IDENTITY_SIZE,
+            round_interval_uses_seconds: false,
+            identities: Vec::new(),
+            aggregator_change_threshold: 0,
+            round_intervals: 0,
+            subblocks_per_round: 0,
+            signature_library: None,
+            paused: false,
+            weights: Vec::new(),
+            voting_weights: Vec::new(),
+            voting_threshold: 0,
+            proposal_delay: 0,
+            proposal: None,
+        }
+    }
+}
+
+impl PoASetup {
+    /// Weight of an aggregator, the index wraps around like in `steps`.
+    pub fn weight(&self, aggregator_index: usize) -> u64 {
+        if self.weights.is_empty() {
+            1
+        } else {
+            self.weights[aggregator_index % self.weights.len()] as u64
+        }
+    }
+
+    /// Length of a round of `aggregator_index`, in subtime.
+    pub fn round_duration(&self, aggregator_index: usize) -> u64 {
+        self.weight(aggregator_index) * self.round_intervals as u64
+    }
+
+    /// Subtime it takes for every aggregator to have a round.
+    pub fn rotation_duration(&self) -> u64 {
+        (0..self.identities.len())
+            .map(|aggregator_index| self.round_duration(aggregator_index))
+            .fold(0u64, |duration, round| duration.saturating_add(round))
+    }
+
+    /// Maximum number of subblocks in a round of `aggregator_index`.
+    pub fn round_subblocks(&self, aggregator_index: usize) -> u64 {
+        self.weight(aggregator_index) * self.subblocks_per_round as u64
+    }
+
+    /// Sum of all voting weights.
+    pub fn total_voting_weight(&self) -> u64 {
+        self.voting_weights
+            .iter()
+            .map(|weight| *weight as u64)
+            .sum()
+    }
+
+    fn max_identity_size(&self) -> u8 {
+        if self.signature_library.is_some() {
+            MAX_PUBKEY_HASH_IDENTITY_SIZE
+        } else {
+            MAX_IDENTITY_SIZE
+        }
+    }
+
+    /// Returns the index of the aggregator whose identity matches the given
+    /// lock script hash. Like the PoA lock, only the first `identity_size`
+    /// bytes of the lock script hash are compared.
+    pub fn find_aggregator(&self, lock_hash: &[u8]) -> Option<usize> {
+        let size = self.identity_size as usize;
+        if lock_hash.len() < size {
+            return None;
+        }
+        self.identities
+            .iter()
+            .position(|identity| identity.len() >= size && identity[..size] == lock_hash[..size])
+    }
+}
+
+/// Information about the latest subblock, stored in the PoA data cell.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoAData {
+    pub round_initial_subtime: u64,
+    pub subblock_subtime: u64,
+    pub subblock_index: u32,
+    pub aggregator_index: u16,
+    /// Number of rounds each aggregator has missed, in the order of
+    /// identities in the setup. Empty when the PoA data cell does not track
+    /// missed rounds.
+    pub missed_rounds: Vec<u32>,
+}
+
+impl PoAData {
+    /// Checks that missed rounds are either not tracked, or tracked for each
+    /// aggregator in `setup`.
+    pub fn check_missed_rounds(&self, setup: &PoASetup) -> Result<(), Error> {
+        if !self.missed_rounds.is_empty() && self.missed_rounds.len() != setup.identities.len() {
+            return Err(Error::InvalidMissedRounds {
+                expected: setup.identities.len(),
+                actual: self.missed_rounds.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn check_poa_setup(setup: &PoASetup) -> Result<(), Error> {
+    if setup.identities.len() > 255 {
+        return Err(Error::TooManyIdentities(setup.identities.len()));
+    }
+    if setup.identity_size > setup.max_identity_size() {
+        return Err(Error::InvalidIdentitySize(setup.identity_size));
+    }
+    // Unlike the legacy layout, versioned setups derive the aggregator number
+    // from the identities, which `c/poa.c` rejects as a length mismatch when
+    // identity size is 0.
+    if setup.version != POA_SETUP_V0 && setup.identity_size == 0 && !setup.identities.is_empty() {
+        return Err(Error::EmptyIdentitySize(setup.identities.len()));
+    }
+    let aggregator_number = setup.identities.len() as u8;
+    if setup.aggregator_change_threshold > aggregator_number {
+        return Err(Error::InvalidChangeThreshold {
+            threshold: setup.aggregator_change_threshold,
+            aggregator_number,
+        });
+    }
+    for (index, identity) in setup.identities.iter().enumerate() {
+        if identity.len() < setup.identity_size as usize {
+            return Err(Error::IdentityTooShort {
+                index,
+                length: identity.len(),
+            });
+        }
+    }
+    check_weights(&setup.weights, setup.identities.len())?;
+    check_voting_weights(
+        &setup.voting_weights,
+        setup.voting_threshold,
+        setup.identities.len(),
+    )
+}
+
+fn check_weights(weights: &[u8], aggregator_number: usize) -> Result<(), Error> {
+    if weights.is_empty() {
+        return Ok(());
+    }
+    if weights.len() != aggregator_number {
+        return Err(Error::InvalidWeights {
+            expected: aggregator_number,
+            actual: weights.len(),
+        });
+    }
+    if let Some(index) = weights.iter().position(|weight| *weight == 0) {
+        return Err(Error::ZeroWeight(index));
+    }
+    Ok(())
+}
+
+fn check_voting_weights(
+    voting_weights: &[u32],
+    voting_threshold: u32,
+    aggregator_number: usize,
+) -> Result<(), Error> {
+    if voting_weights.is_empty() {
+        if voting_threshold != 0 {
+            return Err(Error::InvalidVotingThreshold {
+                threshold: voting_threshold,
+                total_voting_weight: 0,
+            });
+        }
+        return Ok(());
+    }
+    if voting_weights.len() != aggregator_number {
+        return Err(Error::InvalidVotingWeights {
+            expected: aggregator_number,
+            actual: voting_weights.len(),
+        });
+    }
+    let total_voting_weight = voting_weights.iter().map(|weight| *weight as u64).sum();
+    if voting_threshold == 0 || voting_threshold as u64 > total_voting_weight {
+        return Err(Error::InvalidVotingThreshold {
+            threshold: voting_threshold,
+            total_voting_weight,
+        });
+    }
+    Ok(())
+}
+
+/// Serializes a PoA setup in the encoding specified by `version`.
+pub fn serialize_poa_setup(setup: &PoASetup) -> Result<Bytes, Error> {
+    check_poa_setup(setup)?;
+    match setup.version {
+        POA_SETUP_V0 if !setup.weights.is_empty() => Err(Error::WeightsUnsupported(POA_SETUP_V0)),
+        POA_SETUP_V0 if !setup.voting_weights.is_empty() => {
+            Err(Error::VotingWeightsUnsupported(POA_SETUP_V0))
+        }
+        POA_SETUP_V0 if setup.proposal_delay != 0 || setup.proposal.is_some() => {
+            Err(Error::ProposalUnsupported(POA_SETUP_V0))
+        }
+        POA_SETUP_V0 => Ok(serialize_poa_setup_v0(setup)),
+        POA_SETUP_V1 => Ok(serialize_poa_setup_v1(setup)),
+        version => Err(Error::UnsupportedSetupVersion(version)),
+    }
+}
+
+fn serialize_poa_setup_v0(setup: &PoASetup) -> Bytes {
+    let mut buffer = BytesMut::with_capacity(
+        POA_SETUP_HEADER_SIZE
+            + setup.identity_size as usize * setup.identities.len()
+            + SIGNATURE_LIBRARY_SIZE,
+    );
+    let mut flags = 0;
+    if setup.round_interval_uses_seconds {
+        flags |= 1;
+    }
+    if setup.signature_library.is_some() {
+        flags |= 2;
+    }
+    if setup.paused {
+        flags |= 4;
+    }
+    buffer.extend_from_slice(&[flags]);
+    buffer.extend_from_slice(&[
+        setup.identity_size,
+        setup.identities.len() as u8,
+        setup.aggregator_change_threshold,
+    ]);
+    buffer.extend_from_slice(&setup.round_intervals.to_le_bytes()[..]);
+    buffer.extend_from_slice(&setup.subblocks_per_round.to_le_bytes()[..]);
+    for identity in &setup.identities {
+        buffer.extend_from_slice(&identity[0..setup.identity_size as usize]);
+    }
+    if let Some(library) = &setup.signature_library {
+        buffer.extend_from_slice(&library.code_hash);
+        buffer.extend_from_slice(&[library.hash_type]);
+    }
+    buffer.freeze()
+}
+
+fn serialize_poa_setup_v1(setup: &PoASetup) -> Bytes {
+    let mut identities = BytesMut::new();
+    for identity in &setup.identities {
+        identities.extend_from_slice(&identity[0..setup.identity_size as usize]);
+    }
+    let signature_library = match &setup.signature_library {
+        Some(library) => {
+            let mut buffer = library.code_hash.to_vec();
+            buffer.push(library.hash_type);
+            buffer
+        }
+        None => Vec::new(),
+    };
+    let proposal = match &setup.proposal {
+        Some(proposal) => {
+            let mut buffer = proposal.activation_subtime.to_le_bytes().to_vec();
+            buffer.extend_from_slice(&proposal.setup);
+            buffer
+        }
+        None => Vec::new(),
+    };
+    let fields = [
+        vec![setup.round_interval_uses_seconds as u8],
+        vec![setup.identity_size],
+        vec![setup.aggregator_change_threshold],
+        setup.round_intervals.to_le_bytes().to_vec(),
+        setup.subblocks_per_round.to_le_bytes().to_vec(),
+        molecule_bytes(&identities),
+        signature_library,
+        vec![setup.paused as u8],
+        molecule_bytes(&setup.weights),
+        molecule_uint32_vec(&setup.voting_weights),
+        setup.voting_threshold.to_le_bytes().to_vec(),
+        setup.proposal_delay.to_le_bytes().to_vec(),
+        molecule_bytes(&proposal),
+    ];
+    let mut buffer = BytesMut::new();
+    buffer.extend_from_slice(&[POA_SETUP_VERSIONED_FLAG | POA_SETUP_V1]);
+    buffer.extend_from_slice(&molecule_table(&fields));
+    buffer.freeze()
+}
+
+fn molecule_bytes(data: &[u8]) -> Vec<u8> {
+    let mut buffer = (data.len() as u32).to_le_bytes().to_vec();
+    buffer.extend_from_slice(data);
+    buffer
+}
+
+fn molecule_uint32_vec(items: &[u32]) -> Vec<u8> {
+    let mut buffer = (items.len() as u32).to_le_bytes().to_vec();
+    for item in items {
+        buffer.extend_from_slice(&item.to_le_bytes());
+    }
+    buffer
+}
+
+fn molecule_table(fields: &[Vec<u8>]) -> Vec<u8> {
+    let header_size = MOLECULE_NUMBER_SIZE * (fields.len() + 1);
+    let total_size = header_size + fields.iter().map(Vec::len).sum::<usize>();
+    let mut buffer = Vec::with_capacity(total_size);
+    buffer.extend_from_slice(&(total_size as u32).to_le_bytes());
+    let mut offset = header_size;
+    for field in fields {
+        buffer.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in fields {
+        buffer.extend_from_slice(field);
+    }
+    buffer
+}
+
+fn unpack_number(data: &[u8]) -> usize {
+    u32::from_le_bytes(data[0..MOLECULE_NUMBER_SIZE].try_into().unwrap()) as usize
+}
+
+/// Slices the fields of a molecule table like `parse_poa_setup_v1_fields` in
+/// `c/poa.c`, accepting extra trailing fields. Optional fields missing from
+/// the table are returned empty.
+fn molecule_table_fields(
+    table: &[u8],
+    required_field_count: usize,
+    field_count: usize,
+) -> Result<Vec<&[u8]>, Error> {
+    if table.len() < MOLECULE_NUMBER_SIZE * 2 {
+        return Err(Error::InvalidSetupEncoding);
+    }
+    let total_size = unpack_number(table);
+    let header_size = unpack_number(&table[MOLECULE_NUMBER_SIZE..]);
+    if total_size != table.len()
+        || header_size % MOLECULE_NUMBER_SIZE != 0
+        || header_size > total_size
+        || header_size < MOLECULE_NUMBER_SIZE * (required_field_count + 1)
+    {
+        return Err(Error::InvalidSetupEncoding);
+    }
+    let actual_field_count = header_size / MOLECULE_NUMBER_SIZE - 1;
+    let mut fields = Vec::with_capacity(field_count);
+    let mut start = header_size;
+    for i in 0..actual_field_count {
+        let end = if i + 1 < actual_field_count {
+            unpack_number(&table[MOLECULE_NUMBER_SIZE * (i + 2)..])
+        } else {
+            total_size
+        };
+        if unpack_number(&table[MOLECULE_NUMBER_SIZE * (i + 1)..]) != start
+            || end < start
+            || end > total_size
+        {
+            return Err(Error::InvalidSetupEncoding);
+        }
+        if i < field_count {
+            fields.push(&table[start..end]);
+        }
+        start = end;
+    }
+    fields.resize(field_count, &[]);
+    Ok(fields)
+}
+
+/// Parses a PoA setup in any supported encoding, performing the same checks
+/// as `parse_poa_setup` in `c/poa.c`, in the same order.
+pub fn parse_poa_setup(data: &[u8]) -> Result<PoASetup, Error> {
+    if is_poa_setup_v1(data) {
+        parse_poa_setup_v1(data)
+    } else {
+        parse_poa_setup_v0(data)
+    }
+}
+
+pub(crate) fn is_poa_setup_v1(data: &[u8]) -> bool {
+    !data.is_empty() && data[0] & POA_SETUP_VERSIONED_FLAG != 0
+}
+
+/// Slices and checks the raw fields of a versioned setup like
+/// `parse_poa_setup_v1_fields` in `c/poa.c`, optional fields missing from the
+/// table are returned empty.
+pub(crate) fn poa_setup_v1_fields(data: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let version = data[0] & !POA_SETUP_VERSIONED_FLAG;
+    if version != POA_SETUP_V1 {
+        return Err(Error::UnsupportedSetupVersion(version));
+    }
+    let fields = molecule_table_fields(
+        &data[1..],
+        POA_SETUP_V1_REQUIRED_FIELD_COUNT,
+        POA_SETUP_V1_FIELD_COUNT,
+    )?;
+    if fields[0].len() != 1
+        || fields[0][0] > 1
+        || fields[1].len() != 1
+        || fields[2].len() != 1
+        || fields[3].len() != 4
+        || fields[4].len() != 4
+        || fields[5].len() < MOLECULE_NUMBER_SIZE
+        || unpack_number(fields[5]) != fields[5].len() - MOLECULE_NUMBER_SIZE
+        || (!fields[6].is_empty() && fields[6].len() != SIGNATURE_LIBRARY_SIZE)
+        || (!fields[7].is_empty() && (fields[7].len() != 1 || fields[7][0] > 1))
+        || (!fields[8].is_empty()
+            && (fields[8].len() < MOLECULE_NUMBER_SIZE
+                || unpack_number(fields[8]) != fields[8].len() - MOLECULE_NUMBER_SIZE))
+        || (!fields[9].is_empty()
+            && (fields[9].len() < MOLECULE_NUMBER_SIZE
+                || unpack_number(fields[9]) as u64 * 4
+                    != (fields[9].len() - MOLECULE_NUMBER_SIZE) as u64))
+        || (!fields[10].is_empty() && fields[10].len() != 4)
+        || (!fields[11].is_empty() && fields[11].len() != 4)
+        || (!fields[12].is_empty()
+            && (fields[12].len() < MOLECULE_NUMBER_SIZE
+                || unpack_number(fields[12]) != fields[12].len() - MOLECULE_NUMBER_SIZE
+                || (fields[12].len() > MOLECULE_NUMBER_SIZE
+                    && fields[12].len() < MOLECULE_NUMBER_SIZE + PROPOSAL_HEADER_SIZE)))
+    {
+        return Err(Error::InvalidSetupEncoding);
+    }
+    Ok(fields)
+}
+
+/// Rewrites the proposal of a serialized v1 setup, keeping all other fields
+/// byte for byte, as required by the PoA lock when proposing or cancelling.
+pub(crate) fn replace_setup_proposal(
+    data: &[u8],
+    proposal: Option<&SetupProposal>,
+) -> Result<Bytes, Error> {
+    if !is_poa_setup_v1(data) {
+        return Err(Error::ProposalUnsupported(POA_SETUP_V0));
+    }
+    let mut fields: Vec<Vec<u8>> = poa_setup_v1_fields(data)?
+        .into_iter()
+        .map(<[u8]>::to_vec)
+        .collect();
+    let proposal = match proposal {
+        Some(proposal) => {
+            let mut buffer = proposal.activation_subtime.to_le_bytes().to_vec();
+            buffer.extend_from_slice(&proposal.setup);
+            buffer
+        }
+        None => Vec::new(),
+    };
+    fields[POA_SETUP_V1_PROPOSAL_FIELD] = molecule_bytes(&proposal);
+    let mut buffer = BytesMut::new();
+    buffer.extend_from_slice(&data[0..1]);
+    buffer.extend_from_slice(&molecule_table(&fields));
+    Ok(buffer.freeze())
+}
+
+fn parse_poa_setup_v1(data: &[u8]) -> Result<PoASetup, Error> {
+    let fields = poa_setup_v1_fields(data)?;
+    let identity_size = fields[1][0];
+    let aggregator_change_threshold = fields[2][0];
+    let identities_data = &fields[5][MOLECULE_NUMBER_SIZE..];
+    let signature_library = if fields[6].is_empty() {
+        None
+    } else {
+        let mut code_hash = [0u8; 32];
+        code_hash.copy_from_slice(&fields[6][0..32]);
+        Some(SignatureLibrary {
+            code_hash,
+            hash_type: fields[6][32],
+        })
+    };
+
+    if identity_size > MAX_IDENTITY_SIZE
+        || (signature_library.is_some() && identity_size > MAX_PUBKEY_HASH_IDENTITY_SIZE)
+    {
+        return Err(Error::InvalidIdentitySize(identity_size));
+    }
+    let aggregator_number = if identity_size == 0 {
+        0
+    } else {
+        identities_data.len() / identity_size as usize
+    };
+    if (identity_size == 0 && !identities_data.is_empty())
+        || (identity_size != 0 && identities_data.len() % identity_size as usize != 0)
+        || aggregator_number > 255
+    {
+        return Err(Error::SetupLengthMismatch {
+            expected: aggregator_number.min(255) * identity_size as usize,
+            actual: identities_data.len(),
+        });
+    }
+    if aggregator_change_threshold as usize > aggregator_number {
+        return Err(Error::InvalidChangeThreshold {
+            threshold: aggregator_change_threshold,
+            aggregator_number: aggregator_number as u8,
+        });
+    }
+    let weights = fields[8]
+        .get(MOLECULE_NUMBER_SIZE..)
+        .unwrap_or(&[])
+        .to_vec();
+    check_weights(&weights, aggregator_number)?;
+    let voting_weights: Vec<u32> = fields[9]
+        .get(MOLECULE_NUMBER_SIZE..)
+        .unwrap_or(&[])
+        .chunks(4)
+        .map(|weight| u32::from_le_bytes(weight.try_into().unwrap()))
+        .collect();
+    let voting_threshold = if fields[10].is_empty() {
+        0
+    } else {
+        u32::from_le_bytes(fields[10].try_into().unwrap())
+    };
+    check_voting_weights(&voting_weights, voting_threshold, aggregator_number)?;
+    let proposal_delay = if fields[11].is_empty() {
+        0
+    } else {
+        u32::from_le_bytes(fields[11].try_into().unwrap())
+    };
+    let proposal = match fields[12].get(MOLECULE_NUMBER_SIZE..) {
+        Some(proposal) if !proposal.is_empty() => Some(SetupProposal {
+            activation_subtime: u64::from_le_bytes(
+                proposal[0..PROPOSAL_HEADER_SIZE].try_into().unwrap(),
+            ),
+            setup: Bytes::from(proposal[PROPOSAL_HEADER_SIZE..].to_vec()),
+        }),
+        _ => None,
+    };
+    Ok(PoASetup {
+        version: POA_SETUP_V1,
+        identity_size,
+        round_interval_uses_seconds: fields[0][0] == 1,
+        identities: identities_data
+            .chunks(identity_size.max(1) as usize)
+            .map(|identity| Bytes::from(identity.to_vec()))
+            .collect(),
+        aggregator_change_threshold,
+        round_intervals: u32::from_le_bytes(fields[3].try_into().unwrap()),
+        subblocks_per_round: u32::from_le_bytes(fields[4].try_into().unwrap()),
+        signature_library,
+        paused: !fields[7].is_empty() && fields[7][0] == 1,
+        weights,
+        voting_weights,
+        voting_threshold,
+        proposal_delay,
+        proposal,
+    })
+}
+
+fn parse_poa_setup_v0(data: &[u8]) -> Result<PoASetup, Error> {
+    if data.len() < POA_SETUP_HEADER_SIZE {
+        return Err(Error::SetupTooShort(data.len()));
+    }
+    let round_interval_uses_seconds = (data[0] & 1) == 1;
+    let signature_identities = (data[0] & 2) == 2;
+    let paused = (data[0] & 4) == 4;
+    let identity_size = data[1];
+    let aggregator_number = data[2];
+    let aggregator_change_threshold = data[3];
+    let round_intervals = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    let subblocks_per_round = u32::from_le_bytes(data[8..12].try_into().unwrap());
+
+    if identity_size > MAX_IDENTITY_SIZE
+        || (signature_identities && identity_size > MAX_PUBKEY_HASH_IDENTITY_SIZE)
+    {
+        return Err(Error::InvalidIdentitySize(identity_size));
+    }
+    if aggregator_change_threshold > aggregator_number {
+        return Err(Error::InvalidChangeThreshold {
+            threshold: aggregator_change_threshold,
+            aggregator_number,
+        });
+    }
+    let identities_end =
+        POA_SETUP_HEADER_SIZE + identity_size as usize * aggregator_number as usize;
+    let expected = if signature_identities {
+        identities_end + SIGNATURE_LIBRARY_SIZE
+    } else {
+        identities_end
+    };
+    if data.len() != expected {
+        return Err(Error::SetupLengthMismatch {
+            expected,
+            actual: data.len(),
+        });
+    }
+    let identities = (0..aggregator_number as usize)
+        .map(|i| {
+            let offset = POA_SETUP_HEADER_SIZE + i * identity_size as usize;
+            Bytes::from(data[offset..offset + identity_size as usize].to_vec())
+        })
+        .collect();
+    let signature_library = if signature_identities {
+        let mut code_hash = [0u8; 32];
+        code_hash.copy_from_slice(&data[identities_end..identities_end + 32]);
+        Some(SignatureLibrary {
+            code_hash,
+            hash_type: data[identities_end + 32],
+        })
+    } else {
+        None
+    };
+    Ok(PoASetup {
+        version: POA_SETUP_V0,
+        identity_size,
+        round_interval_uses_seconds,
+        identities,
+        aggregator_change_threshold,
+        round_intervals,
+        subblocks_per_round,
+        signature_library,
+        paused,
+        ..Default::default()
+    })
+}
+
+pub fn serialize_poa_data(data: &PoAData) -> Bytes {
+    let mut buffer =
+        BytesMut::with_capacity(POA_DATA_SIZE + MISSED_ROUNDS_SIZE * data.missed_rounds.len());
+    buffer.extend_from_slice(&data.round_initial_subtime.to_le_bytes()[..]);
+    buffer.extend_from_slice(&data.subblock_subtime.to_le_bytes()[..]);
+    buffer.extend_from_slice(&data.subblock_index.to_le_bytes()[..]);
+    buffer.extend_from_slice(&data.aggregator_index.to_le_bytes()[..]);
+    for missed_rounds in &data.missed_rounds {
+        buffer.extend_from_slice(&missed_rounds.to_le_bytes()[..]);
+    }
+    buffer.freeze()
+}
+
+pub fn parse_poa_data(data: &[u8]) -> Result<PoAData, Error> {
+    if data.len() < POA_DATA_SIZE
+        || (data.len() - POA_DATA_SIZE) % MISSED_ROUNDS_SIZE != 0
+        || (data.len() - POA_DATA_SIZE) / MISSED_ROUNDS_SIZE > 255
+    {
+        return Err(Error::InvalidDataLength(data.len()));
+    }
+    Ok(PoAData {
+        round_initial_subtime: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+        subblock_subtime: u64::from_le_bytes(data[8..16].try_into().unwrap()),
+        subblock_index: u32::from_le_bytes(data[16..20].try_into().unwrap()),
+        aggregator_index: u16::from_le_bytes(data[20..22].try_into().unwrap()),
+        missed_rounds: data[POA_DATA_SIZE..]
+            .chunks(MISSED_ROUNDS_SIZE)
+            .map(|counter| u32::from_le_bytes(counter.try_into().unwrap()))
+            .collect(),
+    })
+}
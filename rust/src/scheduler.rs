@@ -0,0 +1,218 @@
+use crate::error::Error;
+use crate::poa::{PoAData, PoASetup};
+use ckb_tool::ckb_types::bytes::Bytes;
+
+/// Answer to the question: should the aggregator issue a new subblock now?
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// A new round for current aggregator has started.
+    Yes,
+    /// Current aggregator is still in its round, it can issue more subblocks
+    /// when there are enough pending actions.
+    YesIfFull,
+    No,
+}
+
+/// Number of rounds that must pass before `aggregator_index` can take over
+/// from `last_aggregator_index`, using the same calculation as `c/poa.c`.
+/// An aggregator following itself has to wait for a full rotation. Fails
+/// when there is no aggregator.
+pub fn steps(
+    aggregator_index: u16,
+    last_aggregator_index: u16,
+    aggregator_number: u8,
+) -> Result<u64, Error> {
+    if aggregator_number == 0 {
+        return Err(Error::InvalidAggregatorNumber(0));
+    }
+    let aggregator_number = aggregator_number as u64;
+    // Wrapping arithmetics mirror the unsigned integer behavior in C.
+    let steps = (aggregator_index as u64)
+        .wrapping_add(aggregator_number)
+        .wrapping_sub(last_aggregator_index as u64)
+        % aggregator_number;
+    Ok(if steps == 0 { aggregator_number } else { steps })
+}
+
+// Number of aggregators as stored in PoA setup by the PoA lock.
+fn aggregator_number(count: usize) -> Result<u8, Error> {
+    if count == 0 || count > 255 {
+        return Err(Error::InvalidAggregatorNumber(count));
+    }
+    Ok(count as u8)
+}
+
+/// Subtime that must pass from the start of the round of
+/// `last_aggregator_index` till `aggregator_index` can start a new round:
+/// the rounds of the last aggregator and all aggregators skipped in between,
+/// using the same calculation as `c/poa.c`.
+pub fn handoff_duration(
+    setup: &PoASetup,
+    aggregator_index: u16,
+    last_aggregator_index: u16,
+) -> Result<u64, Error> {
+    let steps = steps(
+        aggregator_index,
+        last_aggregator_index,
+        aggregator_number(setup.identities.len())?,
+    )?;
+    Ok((0..steps)
+        .map(|step| setup.round_duration(last_aggregator_index as usize + step as usize))
+        .fold(0u64, |duration, round| duration.saturating_add(round)))
+}
+
+/// Aggregators that miss their rounds when `aggregator_index` takes over from
+/// `last_aggregator_index`: all aggregators between them in the rotation.
+pub fn skipped_aggregators(
+    aggregator_index: u16,
+    last_aggregator_index: u16,
+    aggregator_number: u8,
+) -> Result<Vec<u16>, Error> {
+    let steps = steps(aggregator_index, last_aggregator_index, aggregator_number)?;
+    Ok((1..steps)
+        .map(|step| ((last_aggregator_index as u64 + step) % aggregator_number as u64) as u16)
+        .collect())
+}
+
+/// Full rotations that have passed between `round_start`, the earliest
+/// subtime a new round can start at, and `subtime`, when it actually starts,
+/// using the same calculation as `c/poa.c`.
+pub fn skipped_rotations(setup: &PoASetup, round_start: u64, subtime: u64) -> u64 {
+    match setup.rotation_duration() {
+        0 => 0,
+        rotation => subtime.saturating_sub(round_start) / rotation,
+    }
+}
+
+/// Updates missed rounds counters for a new round of `aggregator_index`, the
+/// same way as `validate_missed_rounds` in `c/poa.c`: skipped aggregators
+/// miss one round, and each of `skipped_rotations` makes all aggregators miss
+/// one more. Counters saturate instead of wrapping around. Fails unless there
+/// are between 1 and 255 counters.
+pub fn record_missed_rounds(
+    missed_rounds: &[u32],
+    aggregator_index: u16,
+    last_aggregator_index: u16,
+    skipped_rotations: u64,
+) -> Result<Vec<u32>, Error> {
+    let skipped = skipped_aggregators(
+        aggregator_index,
+        last_aggregator_index,
+        aggregator_number(missed_rounds.len())?,
+    )?;
+    Ok(missed_rounds
+        .iter()
+        .enumerate()
+        .map(|(index, counter)| {
+            let missed = if skipped.contains(&(index as u16)) {
+                skipped_rotations.saturating_add(1)
+            } else {
+                skipped_rotations
+            };
+            (*counter as u64)
+                .saturating_add(missed)
+                .min(u32::max_value() as u64) as u32
+        })
+        .collect())
+}
+
+/// Current subtime on chain: the median time in seconds when the setup uses
+/// timestamp based round intervals, the tip block number otherwise.
+/// `median_time` is expressed in milliseconds, as returned by CKB RPCs.
+pub fn current_subtime(setup: &PoASetup, median_time: u64, tip_block_number: u64) -> u64 {
+    if setup.round_interval_uses_seconds {
+        median_time / 1000
+    } else {
+        tip_block_number
+    }
+}
+
+/// Round scheduling for a single aggregator, ported from `PoAGenerator` in
+/// `src/generator.ts`.
+///
+/// All times here are expressed in subtime: seconds for setups using
+/// timestamp based round intervals, block numbers otherwise. Use
+/// `current_subtime` to derive it from the chain tip.
+pub struct RoundScheduler {
+    lock_hash: Bytes,
+    round_start_subtime: Option<u64>,
+}
+
+impl RoundScheduler {
+    /// Creates a scheduler for the aggregator identified by the lock script
+    /// hash of its owner cells.
+    pub fn new(lock_hash: Bytes) -> Self {
+        RoundScheduler {
+            lock_hash,
+            round_start_subtime: None,
+        }
+    }
+
+    pub fn lock_hash(&self) -> &Bytes {
+        &self.lock_hash
+    }
+
+    /// Subtime at which current aggregator's round started, if the
+    /// aggregator is in its round.
+    pub fn round_start_subtime(&self) -> Option<u64> {
+        self.round_start_subtime
+    }
+
+    pub fn cancel_issue_block(&mut self) {
+        self.round_start_subtime = None;
+    }
+
+    /// Locates current aggregator in the setup. The lookup is repeated each
+    /// time since governance updates might reorder identities.
+    pub fn aggregator_index(&self, setup: &PoASetup) -> Result<u16, Error> {
+        setup
+            .find_aggregator(&self.lock_hash)
+            .map(|index| index as u16)
+            .ok_or(Error::IdentityNotFound)
+    }
+
+    /// Subtime from which current aggregator can start a new round.
+    pub fn next_round_start(&self, poa_data: &PoAData, setup: &PoASetup) -> Result<u64, Error> {
+        let aggregator_index = self.aggregator_index(setup)?;
+        Ok(poa_data
+            .round_initial_subtime
+            .saturating_add(handoff_duration(
+                setup,
+                aggregator_index,
+                poa_data.aggregator_index,
+            )?))
+    }
+
+    /// Subtime left till current aggregator can start a new round, 0 means
+    /// a new round can be started right away.
+    pub fn wait_time(
+        &self,
+        current_subtime: u64,
+        poa_data: &PoAData,
+        setup: &PoASetup,
+    ) -> Result<u64, Error> {
+        Ok(self
+            .next_round_start(poa_data, setup)?
+            .saturating_sub(current_subtime))
+    }
+
+    pub fn should_issue_new_block(
+        &mut self,
+        current_subtime: u64,
+        poa_data: &PoAData,
+        setup: &PoASetup,
+    ) -> Result<State, Error> {
+        if let Some(round_start_subtime) = self.round_start_subtime {
+            let round_duration = setup.round_duration(self.aggregator_index(setup)? as usize);
+            if round_start_subtime.saturating_add(round_duration) > current_subtime {
+                return Ok(State::YesIfFull);
+            }
+            self.round_start_subtime = None;
+        }
+        if self.wait_time(current_subtime, poa_data, setup)? == 0 {
+            self.round_start_subtime = Some(current_subtime);
+            return Ok(State::Yes);
+        }
+        Ok(State::No)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::error::Error;
+use ckb_tool::ckb_crypto::secp::{Privkey, Pubkey};
+use ckb_tool::ckb_hash::{blake2b_256, new_blake2b};
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::TransactionView,
+    packed::{Bytes as PackedBytes, WitnessArgs},
+    prelude::*,
+    H256,
+};
+
+pub const SIGNATURE_SIZE: usize = 65;
+pub const BLAKE160_SIZE: usize = 20;
+
+/// Identity of an aggregator signing with `pubkey`: blake160 hash of the
+/// compressed public key.
+pub fn pubkey_hash(pubkey: &Pubkey) -> Bytes {
+    Bytes::from(blake2b_256(pubkey.serialize())[..BLAKE160_SIZE].to_vec())
+}
+
+/// Calculates the message signed in the PoA cell witness, following
+/// `calculate_signing_message` in `c/poa.c`. `witness` is the PoA cell
+/// witness with its lock field zero-filled.
+pub fn signing_message(tx: &TransactionView, witness: &[u8]) -> [u8; 32] {
+    let mut blake2b = new_blake2b();
+    blake2b.update(&tx.hash().raw_data());
+    blake2b.update(&(witness.len() as u64).to_le_bytes());
+    blake2b.update(witness);
+    for extra_witness in tx.witnesses().into_iter().skip(tx.inputs().len()) {
+        let data = extra_witness.raw_data();
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+    message
+}
+
+/// Signs a PoA transaction with the keys of aggregators whose identities are
+/// public key hashes. Signatures are put in the lock field of the witness at
+/// `poa_input_index`, other fields of an existing witness are kept.
+pub fn sign_poa_tx(
+    tx: &TransactionView,
+    poa_input_index: usize,
+    keys: &[Privkey],
+) -> Result<TransactionView, Error> {
+    let mut witnesses: Vec<PackedBytes> = tx.witnesses().into_iter().collect();
+    if witnesses.len() <= poa_input_index {
+        witnesses.resize(poa_input_index + 1, PackedBytes::default());
+    }
+    let existing = witnesses[poa_input_index].raw_data();
+    let witness_args = if existing.is_empty() {
+        WitnessArgs::default()
+    } else {
+        WitnessArgs::from_slice(&existing).map_err(|_| Error::InvalidWitness)?
+    };
+    let placeholder = witness_args
+        .clone()
+        .as_builder()
+        .lock(Some(Bytes::from(vec![0u8; SIGNATURE_SIZE * keys.len()])).pack())
+        .build();
+    let message = signing_message(tx, placeholder.as_slice());
+
+    let mut signatures = Vec::with_capacity(SIGNATURE_SIZE * keys.len());
+    for key in keys {
+        let signature = key
+            .sign_recoverable(&H256::from(message))
+            .map_err(|_| Error::InvalidPrivateKey)?;
+        signatures.extend_from_slice(&signature.serialize());
+    }
+    witnesses[poa_input_index] = witness_args
+        .as_builder()
+        .lock(Some(Bytes::from(signatures)).pack())
+        .build()
+        .as_bytes()
+        .pack();
+    Ok(tx.as_advanced_builder().set_witnesses(witnesses).build())
+}
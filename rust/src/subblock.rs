@@ -0,0 +1,245 @@
+use crate::cell::CellInfo;
+use crate::error::Error;
+use crate::poa::{
+    is_poa_data_cell, is_poa_lock_args, parse_poa_data, parse_poa_setup, serialize_poa_data,
+    PoAData, PoASetup,
+};
+use crate::scheduler::{handoff_duration, record_missed_rounds, skipped_rotations};
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use std::cmp::max;
+
+pub const SINCE_TIMESTAMP_FLAG: u64 = 0x4000_0000_0000_0000;
+
+/// Builds the absolute `since` value required by the PoA lock for a subblock
+/// issued at `subtime`.
+pub fn subtime_to_since(setup: &PoASetup, subtime: u64) -> Result<u64, Error> {
+    if subtime >> 56 != 0 {
+        return Err(Error::InvalidSubtime(subtime));
+    }
+    if setup.round_interval_uses_seconds {
+        Ok(SINCE_TIMESTAMP_FLAG | subtime)
+    } else {
+        Ok(subtime)
+    }
+}
+
+/// Calculates the PoA data for the next subblock issued by `aggregator_index`.
+/// A new subblock is added to the current round when the round is still
+/// running and has room for more subblocks, otherwise a new round is started
+/// at `current_subtime`.
+///
+/// Subtimes are timestamps in seconds or block numbers, depending on
+/// `round_interval_uses_seconds` in the setup. When PoA data tracks missed
+/// rounds, aggregators skipped by a new round each get one more missed round,
+/// and every aggregator gets one more for each full rotation skipped on top.
+pub fn next_poa_data(
+    setup: &PoASetup,
+    poa_data: &PoAData,
+    aggregator_index: u16,
+    current_subtime: u64,
+) -> Result<PoAData, Error> {
+    poa_data.check_missed_rounds(setup)?;
+    let last_aggregator_index = poa_data.aggregator_index as usize;
+    let round_end = poa_data
+        .round_initial_subtime
+        .saturating_add(setup.round_duration(last_aggregator_index));
+    if poa_data.aggregator_index == aggregator_index
+        && current_subtime < round_end
+        && (poa_data.subblock_index as u64) + 1 < setup.round_subblocks(last_aggregator_index)
+    {
+        return Ok(PoAData {
+            round_initial_subtime: poa_data.round_initial_subtime,
+            subblock_subtime: max(poa_data.subblock_subtime, current_subtime),
+            subblock_index: poa_data.subblock_index + 1,
+            aggregator_index,
+            missed_rounds: poa_data.missed_rounds.clone(),
+        });
+    }
+    let next_round_start = poa_data
+        .round_initial_subtime
+        .saturating_add(handoff_duration(
+            setup,
+            aggregator_index,
+            poa_data.aggregator_index,
+        )?);
+    if current_subtime < next_round_start {
+        return Err(Error::RoundNotStarted(next_round_start));
+    }
+    Ok(PoAData {
+        round_initial_subtime: current_subtime,
+        subblock_subtime: current_subtime,
+        subblock_index: 0,
+        aggregator_index,
+        missed_rounds: if poa_data.missed_rounds.is_empty() {
+            Vec::new()
+        } else {
+            record_missed_rounds(
+                &poa_data.missed_rounds,
+                aggregator_index,
+                poa_data.aggregator_index,
+                skipped_rotations(setup, next_round_start, current_subtime),
+            )?
+        },
+    })
+}
+
+/// Assembles a transaction issuing a new subblock, the Rust equivalent of
+/// `fixTransactionSkeleton` in `src/generator.ts`.
+///
+/// The generated transaction has the PoA cell and PoA data cell as its first
+/// 2 inputs and outputs, followed by any inputs and outputs added here. The
+/// PoA setup cell is included as the first cell dep, code deps for the PoA
+/// lock and other scripts should be added via `cell_dep`.
+///
+/// When identities in the setup are public key hashes, the signature library
+/// must be added via `cell_dep`, and the built transaction signed with
+/// `sign_poa_tx`.
+pub struct SubblockTxBuilder {
+    poa_cell: CellInfo,
+    poa_data_cell: CellInfo,
+    poa_setup_cell: CellInfo,
+    identity: Bytes,
+    poa_output: Option<(CellOutput, Bytes)>,
+    inputs: Vec<CellInfo>,
+    outputs: Vec<(CellOutput, Bytes)>,
+    cell_deps: Vec<CellDep>,
+}
+
+impl SubblockTxBuilder {
+    pub fn new(
+        poa_cell: CellInfo,
+        poa_data_cell: CellInfo,
+        poa_setup_cell: CellInfo,
+        owner_lock: Script,
+    ) -> Self {
+        Self::with_identity(
+            poa_cell,
+            poa_data_cell,
+            poa_setup_cell,
+            owner_lock.calc_script_hash().as_bytes(),
+        )
+    }
+
+    /// Creates a builder for the aggregator with `identity`, which is either
+    /// its owner lock hash or its public key hash.
+    pub fn with_identity(
+        poa_cell: CellInfo,
+        poa_data_cell: CellInfo,
+        poa_setup_cell: CellInfo,
+        identity: Bytes,
+    ) -> Self {
+        SubblockTxBuilder {
+            poa_cell,
+            poa_data_cell,
+            poa_setup_cell,
+            identity,
+            poa_output: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            cell_deps: Vec::new(),
+        }
+    }
+
+    /// Sets the new PoA cell. By default the PoA cell is recreated unchanged.
+    pub fn poa_output(mut self, output: CellOutput, data: Bytes) -> Self {
+        self.poa_output = Some((output, data));
+        self
+    }
+
+    /// Adds an input cell. Unless identities are public key hashes, at least
+    /// one input must use the owner lock of current aggregator.
+    pub fn input(mut self, cell: CellInfo) -> Self {
+        self.inputs.push(cell);
+        self
+    }
+
+    pub fn output(mut self, output: CellOutput, data: Bytes) -> Self {
+        self.outputs.push((output, data));
+        self
+    }
+
+    pub fn cell_dep(mut self, cell_dep: CellDep) -> Self {
+        self.cell_deps.push(cell_dep);
+        self
+    }
+
+    pub fn poa_setup(&self) -> Result<PoASetup, Error> {
+        parse_poa_setup(&self.poa_setup_cell.data)
+    }
+
+    pub fn poa_data(&self) -> Result<PoAData, Error> {
+        parse_poa_data(&self.poa_data_cell.data)
+    }
+
+    pub fn aggregator_index(&self) -> Result<u16, Error> {
+        self.poa_setup()?
+            .find_aggregator(&self.identity)
+            .map(|index| index as u16)
+            .ok_or(Error::IdentityNotFound)
+    }
+
+    pub fn next_poa_data(&self, current_subtime: u64) -> Result<PoAData, Error> {
+        next_poa_data(
+            &self.poa_setup()?,
+            &self.poa_data()?,
+            self.aggregator_index()?,
+            current_subtime,
+        )
+    }
+
+    fn check_poa_cells(&self) -> Result<(), Error> {
+        let args = self.poa_cell.output.lock().args().raw_data();
+        if !is_poa_lock_args(&args)
+            || args.slice(0..32) != self.poa_setup_cell.type_args()
+            || !is_poa_data_cell(&args[32..64], &self.poa_data_cell)
+        {
+            return Err(Error::PoACellMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn build(&self, current_subtime: u64) -> Result<TransactionView, Error> {
+        self.check_poa_cells()?;
+        let setup = self.poa_setup()?;
+        if setup.paused {
+            return Err(Error::Paused);
+        }
+        let aggregator_index = self.aggregator_index()?;
+        let identity = &setup.identities[aggregator_index as usize][..setup.identity_size as usize];
+        if setup.signature_library.is_none()
+            && !self
+                .inputs
+                .iter()
+                .any(|cell| cell.lock_hash().starts_with(identity))
+        {
+            return Err(Error::OwnerCellMissing);
+        }
+        let next_poa_data =
+            next_poa_data(&setup, &self.poa_data()?, aggregator_index, current_subtime)?;
+        let since = subtime_to_since(&setup, next_poa_data.subblock_subtime)?;
+        let (poa_output, poa_output_data) = self
+            .poa_output
+            .clone()
+            .unwrap_or_else(|| (self.poa_cell.output.clone(), self.poa_cell.data.clone()));
+
+        let tx = TransactionBuilder::default()
+            .input(self.poa_cell.input(since))
+            .input(self.poa_data_cell.input(0))
+            .inputs(self.inputs.iter().map(|cell| cell.input(0)))
+            .output(poa_output)
+            .output_data(poa_output_data.pack())
+            .output(self.poa_data_cell.output.clone())
+            .output_data(serialize_poa_data(&next_poa_data).pack())
+            .outputs(self.outputs.iter().map(|(output, _)| output.clone()))
+            .outputs_data(self.outputs.iter().map(|(_, data)| data.pack()))
+            .cell_dep(self.poa_setup_cell.code_dep())
+            .cell_deps(self.cell_deps.clone())
+            .build();
+        Ok(tx)
+    }
+}
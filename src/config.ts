@@ -5,12 +5,34 @@ import { readFileSync } from "fs";
 import schema from "./config_schema.json";
 
 export interface PoASetup {
+  // Encoding of the setup cell, 0 for the legacy layout and 1 for the
+  // PoASetupV1 molecule table in c/poa_setup.mol. Defaults to 0.
+  version?: number;
   identity_size: number;
   round_interval_uses_seconds: boolean;
   identities: Array<HexString>;
   aggregator_change_threshold: number;
   round_intervals: number;
   subblocks_per_round: number;
+  // Halts subblock production until a setup update clears it.
+  paused?: boolean;
+  // When set, identities are public key hashes checked by this signature
+  // library: its 32-byte code hash followed by its 1-byte hash type.
+  signature_library?: HexString;
+  // Per aggregator round length multipliers, only supported by version 1.
+  weights?: Array<number>;
+  // Per aggregator voting weights in setup updates, only supported by
+  // version 1. When set, approvals must add up to voting_threshold instead
+  // of aggregator_change_threshold distinct approvals.
+  voting_weights?: Array<number>;
+  voting_threshold?: number;
+  // When set, setup changes must be proposed first, and can only be
+  // committed this many seconds or blocks later. Only supported by version 1.
+  proposal_delay?: number;
+  // Pending proposal: the activation subtime as a little endian u64,
+  // followed by the proposed setup. Proposals are created with the Rust
+  // library, and only carried along here.
+  proposal?: HexString;
 }
 
 export interface PoAData {
@@ -55,6 +77,59 @@ export function validateConfig(config: Config): Config {
       throw new Error("Identity lengths must all be the same!");
     }
   }
+  // Additional check: weights must be non-zero, one per aggregator
+  const weights = config.poa_setup.weights;
+  if (weights !== undefined && weights.length > 0) {
+    if (config.poa_setup.version !== 1) {
+      throw new Error("Weights require PoA setup version 1!");
+    }
+    if (weights.length !== config.poa_setup.identities.length) {
+      throw new Error("Weight count must match aggregator count!");
+    }
+    if (weights.some((weight) => weight === 0)) {
+      throw new Error("Weights must be non-zero!");
+    }
+  }
+  // Additional check: voting threshold must be reachable with voting weights
+  const votingWeights = config.poa_setup.voting_weights || [];
+  const votingThreshold = config.poa_setup.voting_threshold || 0;
+  if (votingWeights.length > 0) {
+    if (config.poa_setup.version !== 1) {
+      throw new Error("Voting weights require PoA setup version 1!");
+    }
+    if (votingWeights.length !== config.poa_setup.identities.length) {
+      throw new Error("Voting weight count must match aggregator count!");
+    }
+    const totalVotingWeight = votingWeights.reduce(
+      (total, weight) => total + weight,
+      0
+    );
+    if (votingThreshold === 0 || votingThreshold > totalVotingWeight) {
+      throw new Error("Invalid voting threshold!");
+    }
+  } else if (votingThreshold !== 0) {
+    throw new Error("Voting threshold requires voting weights!");
+  }
+  // Additional check: time locks need the table encoding
+  if (
+    (config.poa_setup.proposal_delay || config.poa_setup.proposal) &&
+    config.poa_setup.version !== 1
+  ) {
+    throw new Error("Proposals require PoA setup version 1!");
+  }
+  if (
+    config.poa_setup.proposal &&
+    new Reader(config.poa_setup.proposal).length() < 8
+  ) {
+    throw new Error("Invalid proposal!");
+  }
+  // Additional check: public key hashes are at most 20 bytes long
+  if (
+    config.poa_setup.signature_library &&
+    config.poa_setup.identity_size > BLAKE160_SIZE
+  ) {
+    throw new Error("Invalid identity size!");
+  }
   // Additional check: change threshold must not be larger than identity size
   if (
     config.poa_setup.aggregator_change_threshold >
@@ -65,44 +140,362 @@ export function validateConfig(config: Config): Config {
   return config;
 }
 
+const POA_SETUP_VERSIONED_FLAG = 0x80;
+const POA_SETUP_V1_REQUIRED_FIELD_COUNT = 7;
+const POA_SETUP_V1_FIELD_COUNT = 13;
+const MAX_IDENTITY_SIZE = 32;
+const BLAKE160_SIZE = 20;
+const SIGNATURE_LIBRARY_SIZE = 33;
+
+function parseIdentities(
+  bufferArray: Uint8Array,
+  offset: number,
+  identitySize: number,
+  aggregatorNumber: number
+): Array<HexString> {
+  const identities = [];
+  for (let i = 0; i < aggregatorNumber; i++) {
+    const identityBuffer = new ArrayBuffer(identitySize);
+    const identityArray = new Uint8Array(identityBuffer);
+    const start = offset + i * identitySize;
+    identityArray.set(bufferArray.slice(start, start + identitySize));
+    identities.push(new Reader(identityBuffer).serializeJson());
+  }
+  return identities;
+}
+
+// Slices the fields of a molecule table like parse_poa_setup_v1_fields in
+// c/poa.c, extra trailing fields are ignored, and missing optional fields are
+// returned empty.
+function parseMoleculeTable(
+  buffer: ArrayBuffer,
+  requiredFieldCount: number,
+  fieldCount: number
+): Array<ArrayBuffer> {
+  if (buffer.byteLength < 8) {
+    throw new Error("Invalid encoding!");
+  }
+  const view = new DataView(buffer);
+  const totalSize = view.getUint32(0, true);
+  const headerSize = view.getUint32(4, true);
+  if (
+    totalSize !== buffer.byteLength ||
+    headerSize % 4 !== 0 ||
+    headerSize > totalSize ||
+    headerSize < 4 * (requiredFieldCount + 1)
+  ) {
+    throw new Error("Invalid encoding!");
+  }
+  const actualFieldCount = headerSize / 4 - 1;
+  const fields = [];
+  let start = headerSize;
+  for (let i = 0; i < actualFieldCount; i++) {
+    const end =
+      i + 1 < actualFieldCount ? view.getUint32(4 * (i + 2), true) : totalSize;
+    if (
+      view.getUint32(4 * (i + 1), true) !== start ||
+      end < start ||
+      end > totalSize
+    ) {
+      throw new Error("Invalid encoding!");
+    }
+    if (i < fieldCount) {
+      fields.push(buffer.slice(start, end));
+    }
+    start = end;
+  }
+  while (fields.length < fieldCount) {
+    fields.push(new ArrayBuffer(0));
+  }
+  return fields;
+}
+
+function serializeMoleculeTable(fields: Array<Uint8Array>): ArrayBuffer {
+  const headerSize = 4 * (fields.length + 1);
+  const totalSize = fields.reduce(
+    (size, field) => size + field.byteLength,
+    headerSize
+  );
+  const buffer = new ArrayBuffer(totalSize);
+  const view = new DataView(buffer);
+  const uint8array = new Uint8Array(buffer);
+  view.setUint32(0, totalSize, true);
+  let offset = headerSize;
+  for (let i = 0; i < fields.length; i++) {
+    view.setUint32(4 * (i + 1), offset, true);
+    uint8array.set(fields[i], offset);
+    offset += fields[i].byteLength;
+  }
+  return buffer;
+}
+
+// Identities are either lock hashes, or public key hashes when the setup has
+// a signature library.
+function checkIdentitySize(identitySize: number, signatureLibrary: boolean) {
+  if (
+    identitySize > MAX_IDENTITY_SIZE ||
+    (signatureLibrary && identitySize > BLAKE160_SIZE)
+  ) {
+    throw new Error("Invalid identity size!");
+  }
+}
+
+// Parses a version 1 setup, performing the same checks as
+// parse_poa_setup_v1 in c/poa.c.
+function parsePoASetupV1(buffer: ArrayBuffer): PoASetup {
+  const version = new Uint8Array(buffer)[0] & ~POA_SETUP_VERSIONED_FLAG;
+  if (version !== 1) {
+    throw new Error(`Unsupported PoA setup version: ${version}!`);
+  }
+  const fields = parseMoleculeTable(
+    buffer.slice(1),
+    POA_SETUP_V1_REQUIRED_FIELD_COUNT,
+    POA_SETUP_V1_FIELD_COUNT
+  );
+  if (
+    fields[0].byteLength !== 1 ||
+    new Uint8Array(fields[0])[0] > 1 ||
+    fields[1].byteLength !== 1 ||
+    fields[2].byteLength !== 1 ||
+    fields[3].byteLength !== 4 ||
+    fields[4].byteLength !== 4 ||
+    fields[5].byteLength < 4 ||
+    new DataView(fields[5]).getUint32(0, true) !== fields[5].byteLength - 4 ||
+    (fields[6].byteLength !== 0 &&
+      fields[6].byteLength !== SIGNATURE_LIBRARY_SIZE) ||
+    (fields[7].byteLength !== 0 &&
+      (fields[7].byteLength !== 1 || new Uint8Array(fields[7])[0] > 1)) ||
+    (fields[8].byteLength !== 0 &&
+      (fields[8].byteLength < 4 ||
+        new DataView(fields[8]).getUint32(0, true) !==
+          fields[8].byteLength - 4)) ||
+    (fields[9].byteLength !== 0 &&
+      (fields[9].byteLength < 4 ||
+        new DataView(fields[9]).getUint32(0, true) * 4 !==
+          fields[9].byteLength - 4)) ||
+    (fields[10].byteLength !== 0 && fields[10].byteLength !== 4) ||
+    (fields[11].byteLength !== 0 && fields[11].byteLength !== 4) ||
+    (fields[12].byteLength !== 0 &&
+      (fields[12].byteLength < 4 ||
+        new DataView(fields[12]).getUint32(0, true) !==
+          fields[12].byteLength - 4 ||
+        (fields[12].byteLength > 4 && fields[12].byteLength < 12)))
+  ) {
+    throw new Error("Invalid encoding!");
+  }
+  const identitySize = new Uint8Array(fields[1])[0];
+  const aggregatorChangeThreshold = new Uint8Array(fields[2])[0];
+  const identitiesLength = fields[5].byteLength - 4;
+  const signatureLibrary = fields[6].byteLength !== 0;
+  checkIdentitySize(identitySize, signatureLibrary);
+  // Aggregator number is derived from the length of identities
+  const aggregatorNumber =
+    identitySize === 0 ? 0 : Math.floor(identitiesLength / identitySize);
+  if (
+    (identitySize === 0 && identitiesLength !== 0) ||
+    (identitySize !== 0 && identitiesLength % identitySize !== 0) ||
+    aggregatorNumber > 255
+  ) {
+    throw new Error("Invalid length!");
+  }
+  if (aggregatorChangeThreshold > aggregatorNumber) {
+    throw new Error("Invalid change threshold!");
+  }
+  const weights =
+    fields[8].byteLength > 4
+      ? Array.from(new Uint8Array(fields[8].slice(4)))
+      : undefined;
+  if (
+    weights !== undefined &&
+    (weights.length !== aggregatorNumber ||
+      weights.some((weight) => weight === 0))
+  ) {
+    throw new Error("Invalid weights!");
+  }
+  const votingWeights = [];
+  for (let offset = 4; offset < fields[9].byteLength; offset += 4) {
+    votingWeights.push(new DataView(fields[9]).getUint32(offset, true));
+  }
+  const votingThreshold =
+    fields[10].byteLength === 4
+      ? new DataView(fields[10]).getUint32(0, true)
+      : 0;
+  if (votingWeights.length > 0) {
+    const totalVotingWeight = votingWeights.reduce(
+      (total, weight) => total + weight,
+      0
+    );
+    if (
+      votingWeights.length !== aggregatorNumber ||
+      votingThreshold === 0 ||
+      votingThreshold > totalVotingWeight
+    ) {
+      throw new Error("Invalid voting weights!");
+    }
+  } else if (votingThreshold !== 0) {
+    throw new Error("Invalid voting weights!");
+  }
+  const proposalDelay =
+    fields[11].byteLength === 4
+      ? new DataView(fields[11]).getUint32(0, true)
+      : 0;
+  return {
+    version: 1,
+    proposal_delay: proposalDelay > 0 ? proposalDelay : undefined,
+    proposal:
+      fields[12].byteLength > 4
+        ? new Reader(fields[12].slice(4)).serializeJson()
+        : undefined,
+    weights,
+    voting_weights: votingWeights.length > 0 ? votingWeights : undefined,
+    voting_threshold: votingThreshold > 0 ? votingThreshold : undefined,
+    paused: fields[7].byteLength === 1 && new Uint8Array(fields[7])[0] === 1,
+    signature_library: signatureLibrary
+      ? new Reader(fields[6]).serializeJson()
+      : undefined,
+    round_interval_uses_seconds: new Uint8Array(fields[0])[0] === 1,
+    aggregator_change_threshold: aggregatorChangeThreshold,
+    round_intervals: new DataView(fields[3]).getUint32(0, true),
+    subblocks_per_round: new DataView(fields[4]).getUint32(0, true),
+    identity_size: identitySize,
+    identities: parseIdentities(
+      new Uint8Array(fields[5]),
+      4,
+      identitySize,
+      aggregatorNumber
+    ),
+  };
+}
+
+// Parses a setup in any supported encoding, performing the same checks as
+// parse_poa_setup in c/poa.c, so any setup accepted on chain can be read.
 export function parsePoASetup(buffer: ArrayBuffer): PoASetup {
+  if (
+    buffer.byteLength > 0 &&
+    (new Uint8Array(buffer)[0] & POA_SETUP_VERSIONED_FLAG) !== 0
+  ) {
+    return parsePoASetupV1(buffer);
+  }
   if (buffer.byteLength < 12) {
     throw new Error("Invalid length!");
   }
   const bufferArray = new Uint8Array(buffer);
   const view = new DataView(buffer);
+  const flags = view.getUint8(0);
   const identitySize = view.getUint8(1);
   const aggregatorNumber = view.getUint8(2);
-  if (buffer.byteLength !== 12 + identitySize * aggregatorNumber) {
-    throw new Error("Invalid length!");
+  const aggregatorChangeThreshold = view.getUint8(3);
+  const signatureLibrary = (flags & 2) === 2;
+  checkIdentitySize(identitySize, signatureLibrary);
+  if (aggregatorChangeThreshold > aggregatorNumber) {
+    throw new Error("Invalid change threshold!");
   }
-  const identities = [];
-  for (let i = 0; i < aggregatorNumber; i++) {
-    const identityBuffer = new ArrayBuffer(identitySize);
-    const identityArray = new Uint8Array(identityBuffer);
-    const offset = 12 + i * identitySize;
-    identityArray.set(bufferArray.slice(offset, offset + identitySize));
-    identities.push(new Reader(identityBuffer).serializeJson());
+  // Signature library follows identities
+  const identitiesLength = identitySize * aggregatorNumber;
+  if (
+    buffer.byteLength !==
+    12 + identitiesLength + (signatureLibrary ? SIGNATURE_LIBRARY_SIZE : 0)
+  ) {
+    throw new Error("Invalid length!");
   }
-  const setup: PoASetup = {
-    round_interval_uses_seconds: (view.getUint8(0) & 1) === 1,
-    aggregator_change_threshold: view.getUint8(3),
+  return {
+    round_interval_uses_seconds: (flags & 1) === 1,
+    paused: (flags & 4) === 4,
+    signature_library: signatureLibrary
+      ? new Reader(buffer.slice(12 + identitiesLength)).serializeJson()
+      : undefined,
+    aggregator_change_threshold: aggregatorChangeThreshold,
     round_intervals: view.getUint32(4, true),
     subblocks_per_round: view.getUint32(8, true),
     identity_size: identitySize,
-    identities: identities,
+    identities: parseIdentities(
+      bufferArray,
+      12,
+      identitySize,
+      aggregatorNumber
+    ),
   };
-  return validateConfig({ poa_setup: setup }).poa_setup;
+}
+
+function serializePoASetupV1(poaSetup: PoASetup): ArrayBuffer {
+  const identities = new Uint8Array(
+    4 + poaSetup.identities.length * poaSetup.identity_size
+  );
+  new DataView(identities.buffer).setUint32(0, identities.byteLength - 4, true);
+  for (let i = 0; i < poaSetup.identities.length; i++) {
+    identities.set(
+      new Uint8Array(new Reader(poaSetup.identities[i]).toArrayBuffer()),
+      4 + i * poaSetup.identity_size
+    );
+  }
+  const uint32Field = (value: number) => {
+    const field = new Uint8Array(4);
+    new DataView(field.buffer).setUint32(0, value, true);
+    return field;
+  };
+  const weights = poaSetup.weights || [];
+  const weightsField = new Uint8Array(4 + weights.length);
+  new DataView(weightsField.buffer).setUint32(0, weights.length, true);
+  weightsField.set(weights, 4);
+  const votingWeights = poaSetup.voting_weights || [];
+  const votingWeightsField = new Uint8Array(4 + 4 * votingWeights.length);
+  const votingWeightsView = new DataView(votingWeightsField.buffer);
+  votingWeightsView.setUint32(0, votingWeights.length, true);
+  for (let i = 0; i < votingWeights.length; i++) {
+    votingWeightsView.setUint32(4 + 4 * i, votingWeights[i], true);
+  }
+  const proposal = poaSetup.proposal
+    ? new Uint8Array(new Reader(poaSetup.proposal).toArrayBuffer())
+    : new Uint8Array(0);
+  const proposalField = new Uint8Array(4 + proposal.byteLength);
+  new DataView(proposalField.buffer).setUint32(0, proposal.byteLength, true);
+  proposalField.set(proposal, 4);
+  const signatureLibrary = poaSetup.signature_library
+    ? new Uint8Array(new Reader(poaSetup.signature_library).toArrayBuffer())
+    : new Uint8Array(0);
+  const table = serializeMoleculeTable([
+    new Uint8Array([poaSetup.round_interval_uses_seconds ? 1 : 0]),
+    new Uint8Array([poaSetup.identity_size]),
+    new Uint8Array([poaSetup.aggregator_change_threshold]),
+    uint32Field(poaSetup.round_intervals),
+    uint32Field(poaSetup.subblocks_per_round),
+    identities,
+    signatureLibrary,
+    new Uint8Array([poaSetup.paused ? 1 : 0]),
+    weightsField,
+    votingWeightsField,
+    uint32Field(poaSetup.voting_threshold || 0),
+    uint32Field(poaSetup.proposal_delay || 0),
+    proposalField,
+  ]);
+  const buffer = new Uint8Array(1 + table.byteLength);
+  buffer[0] = POA_SETUP_VERSIONED_FLAG | 1;
+  buffer.set(new Uint8Array(table), 1);
+  return buffer.buffer;
 }
 
 export function serializePoASetup(poaSetup: PoASetup): ArrayBuffer {
-  const length =
-    12 +
-    poaSetup.identities.length * new Reader(poaSetup.identities[0]).length();
-  const buffer = new ArrayBuffer(length);
+  if (poaSetup.version === 1) {
+    return serializePoASetupV1(poaSetup);
+  }
+  if (poaSetup.version !== undefined && poaSetup.version !== 0) {
+    throw new Error(`Unsupported PoA setup version: ${poaSetup.version}!`);
+  }
+  const identitiesLength = poaSetup.identities.length * poaSetup.identity_size;
+  const signatureLibrary = poaSetup.signature_library
+    ? new Uint8Array(new Reader(poaSetup.signature_library).toArrayBuffer())
+    : new Uint8Array(0);
+  const buffer = new ArrayBuffer(
+    12 + identitiesLength + signatureLibrary.byteLength
+  );
   const view = new DataView(buffer);
   const uint8array = new Uint8Array(buffer);
-  view.setUint8(0, poaSetup.round_interval_uses_seconds ? 1 : 0);
+  view.setUint8(
+    0,
+    (poaSetup.round_interval_uses_seconds ? 1 : 0) |
+      (poaSetup.signature_library ? 2 : 0) |
+      (poaSetup.paused ? 4 : 0)
+  );
   view.setUint8(1, poaSetup.identity_size);
   view.setUint8(2, poaSetup.identities.length);
   view.setUint8(3, poaSetup.aggregator_change_threshold);
@@ -114,6 +507,7 @@ export function serializePoASetup(poaSetup: PoASetup): ArrayBuffer {
       12 + i * poaSetup.identity_size
     );
   }
+  uint8array.set(signatureLibrary, 12 + identitiesLength);
   return buffer;
 }
 
@@ -0,0 +1,41 @@
+// Checks parsePoASetup in src/config.ts against the setups dumped by
+// test_dump_poa_setup_vectors in tests/src/codec_tests.rs, run `make test`
+// to dump them before `yarn test`.
+const assert = require("assert");
+const { readFileSync } = require("fs");
+const { join } = require("path");
+const test = require("node:test");
+const { parsePoASetup, serializePoASetup } = require("../lib/config");
+
+const ENVIRONMENT = process.env.ENVIRONMENT || "debug";
+const vectors = JSON.parse(
+  readFileSync(
+    join(__dirname, "..", "build", ENVIRONMENT, "poa_setup_vectors.json"),
+    "utf8"
+  )
+);
+
+function toArrayBuffer(hex) {
+  const buffer = Buffer.from(hex.slice(2), "hex");
+  return buffer.buffer.slice(
+    buffer.byteOffset,
+    buffer.byteOffset + buffer.byteLength
+  );
+}
+
+function toHex(arrayBuffer) {
+  return "0x" + Buffer.from(arrayBuffer).toString("hex");
+}
+
+test("setups serialized by the Rust library round trip", () => {
+  for (const hex of vectors.valid) {
+    const setup = parsePoASetup(toArrayBuffer(hex));
+    assert.strictEqual(toHex(serializePoASetup(setup)), hex);
+  }
+});
+
+test("setups rejected by the PoA lock are rejected", () => {
+  for (const hex of vectors.invalid) {
+    assert.throws(() => parsePoASetup(toArrayBuffer(hex)), Error, hex);
+  }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use]
+extern crate lazy_static;
+
+use libfuzzer_sys::fuzz_target;
+use tests_fuzz::{Harness, PoaInput};
+
+lazy_static! {
+    static ref HARNESS: Harness = Harness::from_env();
+}
+
+fuzz_target!(|data: &[u8]| {
+    HARNESS.run(&PoaInput::parse(data));
+});
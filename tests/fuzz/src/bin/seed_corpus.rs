@@ -0,0 +1,24 @@
+//! Writes the seed corpus of the `poa_sim` fuzz target, with one input for
+//! each subblock transaction dumped by the tests.
+
+use std::fs;
+use std::path::Path;
+use tests_fuzz::{build_dir, Template};
+
+fn main() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("corpus")
+        .join("poa_sim");
+    fs::create_dir_all(&corpus).expect("create corpus folder");
+    let dumped_tests = build_dir().join("dumped_tests");
+    let mut seeds = 0;
+    for entry in fs::read_dir(&dumped_tests).expect("read dumped tests, run the tests first") {
+        let folder = entry.expect("dumped test").path();
+        if let Some(template) = Template::load(&folder) {
+            let name = folder.file_name().expect("test name");
+            fs::write(corpus.join(name), template.extract().serialize()).expect("write seed");
+            seeds += 1;
+        }
+    }
+    println!("{} seeds written to {}", seeds, corpus.display());
+}
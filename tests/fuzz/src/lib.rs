@@ -0,0 +1,276 @@
+//! Fuzzing harness for the PoA lock. Subblock transactions dumped by the
+//! tests serve as templates: the PoA setup cell, the PoA data cells and the
+//! `since` value of the PoA cell are replaced by fuzz input, and the
+//! transaction is run by the x64 simulator builds of `c/poa.c` under ASan and
+//! UBSan.
+
+use ckb_standalone_debugger::transaction::{MockTransaction, ReprMockTransaction};
+use ckb_tool::ckb_types::{bytes::Bytes, packed::*, prelude::*};
+use serde_json::{from_str, to_string_pretty};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const BUILD_DIR_ENV_VAR: &str = "POA_FUZZ_BUILD_DIR";
+const TEMPLATE_ENV_VAR: &str = "POA_FUZZ_TEMPLATE";
+const DEFAULT_TEMPLATE: &str = "poa_normal_update";
+const SANITIZERS: [&str; 2] = ["asan", "ubsan"];
+
+/// Cells and `since` value of a subblock transaction controlled by the
+/// fuzzer. It is encoded as the u64 `since`, the PoA setup and the last PoA
+/// data, each prefixed by its u16 length, followed by the new PoA data.
+/// All integers are little endian, truncated input leaves the remaining
+/// fields empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoaInput {
+    pub since: u64,
+    pub setup: Bytes,
+    pub last_data: Bytes,
+    pub current_data: Bytes,
+}
+
+// Splits `len` bytes off the front of `data`, or what is left of it.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> &'a [u8] {
+    let (head, tail) = data.split_at(len.min(data.len()));
+    *data = tail;
+    head
+}
+
+fn take_prefixed(data: &mut &[u8]) -> Bytes {
+    let mut len = [0u8; 2];
+    let prefix = take(data, 2);
+    len[..prefix.len()].copy_from_slice(prefix);
+    Bytes::from(take(data, u16::from_le_bytes(len) as usize).to_vec())
+}
+
+impl PoaInput {
+    pub fn parse(mut data: &[u8]) -> Self {
+        let mut since = [0u8; 8];
+        let prefix = take(&mut data, 8);
+        since[..prefix.len()].copy_from_slice(prefix);
+        let setup = take_prefixed(&mut data);
+        let last_data = take_prefixed(&mut data);
+        PoaInput {
+            since: u64::from_le_bytes(since),
+            setup,
+            last_data,
+            current_data: Bytes::from(data.to_vec()),
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = self.since.to_le_bytes().to_vec();
+        for field in &[&self.setup, &self.last_data] {
+            data.extend_from_slice(&(field.len() as u16).to_le_bytes());
+            data.extend_from_slice(field);
+        }
+        data.extend_from_slice(&self.current_data);
+        data
+    }
+}
+
+fn type_args(output: &CellOutput) -> Option<Bytes> {
+    output
+        .type_()
+        .to_opt()
+        .map(|script| script.args().raw_data())
+}
+
+fn type_hash(output: &CellOutput) -> Option<Bytes> {
+    output
+        .type_()
+        .to_opt()
+        .map(|script| script.calc_script_hash().as_bytes())
+}
+
+// Like the PoA lock, PoA data cells are matched by type ID args, or by the
+// type script hash when they use the PoA data type script.
+fn is_poa_data_cell(output: &CellOutput, args: &[u8]) -> bool {
+    type_args(output).as_deref() == Some(args) || type_hash(output).as_deref() == Some(args)
+}
+
+/// A dumped subblock transaction, with the locations of the cells replaced
+/// by fuzz input.
+pub struct Template {
+    tx: MockTransaction,
+    poa_input: usize,
+    setup_dep: usize,
+    data_input: usize,
+    data_output: usize,
+}
+
+impl Template {
+    /// Loads `tx.json` in a folder written by `write_native_setup`. Returns
+    /// `None` unless it is a subblock transaction, with the PoA setup cell as
+    /// a cell dep.
+    pub fn load(folder: &Path) -> Option<Self> {
+        let json = fs::read_to_string(folder.join("tx.json")).ok()?;
+        let repr_tx: ReprMockTransaction = from_str(&json).ok()?;
+        let tx: MockTransaction = repr_tx.into();
+        let info = &tx.mock_info;
+        for (poa_input, input) in info.inputs.iter().enumerate() {
+            let args = input.output.lock().args().raw_data();
+            if args.len() != 64 && args.len() != 65 {
+                continue;
+            }
+            let setup_dep = info
+                .cell_deps
+                .iter()
+                .position(|cell_dep| type_args(&cell_dep.output).as_deref() == Some(&args[0..32]));
+            let data_input = info
+                .inputs
+                .iter()
+                .position(|input| is_poa_data_cell(&input.output, &args[32..64]));
+            let data_output = tx
+                .tx
+                .raw()
+                .outputs()
+                .into_iter()
+                .position(|output| is_poa_data_cell(&output, &args[32..64]));
+            if let (Some(setup_dep), Some(data_input), Some(data_output)) =
+                (setup_dep, data_input, data_output)
+            {
+                return Some(Template {
+                    tx,
+                    poa_input,
+                    setup_dep,
+                    data_input,
+                    data_output,
+                });
+            }
+        }
+        None
+    }
+
+    /// Fuzz input reproducing the template transaction.
+    pub fn extract(&self) -> PoaInput {
+        let info = &self.tx.mock_info;
+        let raw = self.tx.tx.raw();
+        PoaInput {
+            since: info.inputs[self.poa_input].input.since().unpack(),
+            setup: info.cell_deps[self.setup_dep].data.clone(),
+            last_data: info.inputs[self.data_input].data.clone(),
+            current_data: raw
+                .outputs_data()
+                .get(self.data_output)
+                .expect("output data")
+                .raw_data(),
+        }
+    }
+
+    pub fn apply(&self, input: &PoaInput) -> MockTransaction {
+        let mut tx = self.tx.clone();
+        let poa_input = tx.mock_info.inputs[self.poa_input]
+            .input
+            .clone()
+            .as_builder()
+            .since(input.since.pack())
+            .build();
+        tx.mock_info.inputs[self.poa_input].input = poa_input.clone();
+        tx.mock_info.inputs[self.data_input].data = input.last_data.clone();
+        tx.mock_info.cell_deps[self.setup_dep].data = input.setup.clone();
+
+        let view = tx.tx.into_view();
+        let mut inputs: Vec<CellInput> = view.inputs().into_iter().collect();
+        inputs[self.poa_input] = poa_input;
+        let mut outputs_data: Vec<Bytes> = view
+            .outputs_data()
+            .into_iter()
+            .map(|data| data.raw_data())
+            .collect();
+        outputs_data[self.data_output] = input.current_data.clone();
+        tx.tx = view
+            .as_advanced_builder()
+            .set_inputs(inputs)
+            .set_outputs_data(outputs_data.into_iter().map(|data| data.pack()).collect())
+            .build()
+            .data();
+        tx
+    }
+}
+
+/// Build folder holding the simulators and the transactions dumped by the
+/// tests, `build/debug` unless overridden via `POA_FUZZ_BUILD_DIR`.
+pub fn build_dir() -> PathBuf {
+    env::var(BUILD_DIR_ENV_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("..")
+                .join("..")
+                .join("build")
+                .join("debug")
+        })
+}
+
+pub struct Harness {
+    template: Template,
+    template_folder: PathBuf,
+    simulator: PathBuf,
+    work_folder: PathBuf,
+}
+
+impl Harness {
+    /// Uses the dumped test named by `POA_FUZZ_TEMPLATE`, which defaults to
+    /// `poa_normal_update`.
+    pub fn from_env() -> Self {
+        let build_dir = build_dir();
+        let template_name =
+            env::var(TEMPLATE_ENV_VAR).unwrap_or_else(|_| DEFAULT_TEMPLATE.to_string());
+        let template_folder = build_dir.join("dumped_tests").join(template_name);
+        let template = Template::load(&template_folder).unwrap_or_else(|| {
+            panic!(
+                "{} is not a dumped subblock transaction, run the tests first",
+                template_folder.display()
+            )
+        });
+        // Fuzzing jobs run in separate processes
+        let work_folder = build_dir.join("fuzz").join(std::process::id().to_string());
+        fs::create_dir_all(&work_folder).expect("create work folder");
+        Harness {
+            template,
+            template_folder,
+            simulator: build_dir.join("poa_sim"),
+            work_folder,
+        }
+    }
+
+    fn run_simulator(&self, sanitizer: &str, tx_file: &Path) -> Output {
+        let mut binary = self.simulator.clone().into_os_string();
+        binary.push(format!(".{}", sanitizer));
+        Command::new(binary)
+            .env("CKB_TX_FILE", tx_file)
+            .env(
+                "CKB_RUNNING_SETUP",
+                self.template_folder
+                    .join(format!("{}_setup.json", sanitizer)),
+            )
+            .output()
+            .expect("run simulator")
+    }
+
+    /// Runs `input` under each sanitizer. The PoA lock may accept or reject
+    /// it, but any sanitizer report, or the simulator being killed by a
+    /// signal, is a crash.
+    pub fn run(&self, input: &PoaInput) {
+        let repr_tx: ReprMockTransaction = self.template.apply(input).into();
+        let tx_file = self.work_folder.join("tx.json");
+        fs::write(
+            &tx_file,
+            to_string_pretty(&repr_tx).expect("serialize to json"),
+        )
+        .expect("write tx to local file");
+        for sanitizer in SANITIZERS.iter() {
+            let output = self.run_simulator(sanitizer, &tx_file);
+            if output.status.code().is_none() || !output.stderr.is_empty() {
+                panic!(
+                    "{} simulator failed with {}:\n{}",
+                    sanitizer,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,760 @@
+use super::*;
+use ckb_tool::ckb_types::bytes::{Bytes, BytesMut};
+use clerkb::{
+    parse_poa_data, parse_poa_setup, serialize_poa_data, serialize_poa_setup, Error, PoAData,
+    PoASetup, SetupProposal, SignatureLibrary, POA_SETUP_V0, POA_SETUP_V1,
+};
+use serde_json::{json, to_string_pretty};
+use std::fs;
+
+fn sample_poa_setup() -> PoASetup {
+    PoASetup {
+        identity_size: 20,
+        round_interval_uses_seconds: true,
+        identities: vec![random_32bytes(), random_32bytes(), random_32bytes()],
+        aggregator_change_threshold: 2,
+        round_intervals: 90,
+        subblocks_per_round: 3,
+        ..Default::default()
+    }
+}
+
+fn raw_poa_setup(header: [u8; 4], identities: &[u8]) -> Bytes {
+    let mut buffer = BytesMut::new();
+    buffer.extend_from_slice(&header);
+    buffer.extend_from_slice(&90u32.to_le_bytes()[..]);
+    buffer.extend_from_slice(&3u32.to_le_bytes()[..]);
+    buffer.extend_from_slice(identities);
+    buffer.freeze()
+}
+
+#[test]
+fn test_poa_setup_roundtrip() {
+    let setup = sample_poa_setup();
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    assert_eq!(data.len(), 12 + 20 * 3);
+    assert_eq!(&data[0..4], &[1, 20, 3, 2]);
+    assert_eq!(&data[4..8], &90u32.to_le_bytes()[..]);
+    assert_eq!(&data[8..12], &3u32.to_le_bytes()[..]);
+
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.identity_size, 20);
+    assert!(parsed.round_interval_uses_seconds);
+    assert_eq!(parsed.aggregator_change_threshold, 2);
+    assert_eq!(parsed.round_intervals, 90);
+    assert_eq!(parsed.subblocks_per_round, 3);
+    for (parsed_identity, identity) in parsed.identities.iter().zip(setup.identities.iter()) {
+        assert_eq!(parsed_identity, &identity.slice(0..20));
+    }
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+}
+
+#[test]
+fn test_poa_setup_block_interval_roundtrip() {
+    let data = raw_poa_setup([0, 1, 2, 1], &[7, 9]);
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert!(!parsed.round_interval_uses_seconds);
+    assert_eq!(
+        parsed.identities,
+        vec![Bytes::from_static(&[7]), Bytes::from_static(&[9])]
+    );
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+}
+
+#[test]
+fn test_parse_poa_setup_errors() {
+    assert_eq!(
+        parse_poa_setup(&[1, 32, 0, 0]),
+        Err(Error::SetupTooShort(4))
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup([1, 33, 1, 1], &[0u8; 33])),
+        Err(Error::InvalidIdentitySize(33))
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup([1, 1, 1, 2], &[0u8; 1])),
+        Err(Error::InvalidChangeThreshold {
+            threshold: 2,
+            aggregator_number: 1,
+        })
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup([1, 2, 2, 1], &[0u8; 5])),
+        Err(Error::SetupLengthMismatch {
+            expected: 16,
+            actual: 17,
+        })
+    );
+}
+
+#[test]
+fn test_serialize_poa_setup_errors() {
+    let mut setup = sample_poa_setup();
+    setup.identity_size = 33;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidIdentitySize(33))
+    );
+
+    let mut setup = sample_poa_setup();
+    setup.aggregator_change_threshold = 4;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidChangeThreshold {
+            threshold: 4,
+            aggregator_number: 3,
+        })
+    );
+
+    let mut setup = sample_poa_setup();
+    setup.identities[1] = Bytes::from_static(&[1, 2, 3]);
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::IdentityTooShort {
+            index: 1,
+            length: 3,
+        })
+    );
+
+    let mut setup = sample_poa_setup();
+    setup.identities = vec![random_32bytes(); 256];
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::TooManyIdentities(256))
+    );
+
+    // Identity size 0 only works in the legacy layout
+    let mut setup = sample_poa_setup();
+    setup.identity_size = 0;
+    assert!(serialize_poa_setup(&setup).is_ok());
+    setup.version = POA_SETUP_V1;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::EmptyIdentitySize(3))
+    );
+}
+
+// Molecule table with the given fields, used to build v1 setups by hand.
+fn molecule_table(fields: &[&[u8]]) -> Vec<u8> {
+    let header_size = 4 * (fields.len() + 1);
+    let total_size = header_size + fields.iter().map(|field| field.len()).sum::<usize>();
+    let mut buffer = (total_size as u32).to_le_bytes().to_vec();
+    let mut offset = header_size;
+    for field in fields {
+        buffer.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in fields {
+        buffer.extend_from_slice(field);
+    }
+    buffer
+}
+
+fn raw_poa_setup_v1(header: [u8; 3], identities: &[u8], extra_fields: &[&[u8]]) -> Bytes {
+    let mut identities_field = (identities.len() as u32).to_le_bytes().to_vec();
+    identities_field.extend_from_slice(identities);
+    let mut fields: Vec<&[u8]> = vec![
+        &header[0..1],
+        &header[1..2],
+        &header[2..3],
+        &[90, 0, 0, 0],
+        &[3, 0, 0, 0],
+        &identities_field,
+        &[],
+    ];
+    fields.extend_from_slice(extra_fields);
+    let mut buffer = vec![0x81];
+    buffer.extend_from_slice(&molecule_table(&fields));
+    buffer.into()
+}
+
+#[test]
+fn test_poa_setup_v1_roundtrip() {
+    let mut setup = sample_poa_setup();
+    setup.version = POA_SETUP_V1;
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    assert_eq!(data[0], 0x81);
+    assert_eq!(
+        data.slice(1..),
+        Bytes::from(molecule_table(&[
+            &[1],
+            &[20],
+            &[2],
+            &90u32.to_le_bytes()[..],
+            &3u32.to_le_bytes()[..],
+            &[
+                &60u32.to_le_bytes()[..],
+                &setup.identities[0][0..20],
+                &setup.identities[1][0..20],
+                &setup.identities[2][0..20],
+            ]
+            .concat(),
+            &[],
+            &[0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+        ]))
+    );
+
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.version, POA_SETUP_V1);
+    assert_eq!(parsed.identity_size, 20);
+    assert!(parsed.round_interval_uses_seconds);
+    assert_eq!(parsed.aggregator_change_threshold, 2);
+    assert_eq!(parsed.round_intervals, 90);
+    assert_eq!(parsed.subblocks_per_round, 3);
+    assert_eq!(parsed.signature_library, None);
+    for (parsed_identity, identity) in parsed.identities.iter().zip(setup.identities.iter()) {
+        assert_eq!(parsed_identity, &identity.slice(0..20));
+    }
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+
+    setup.signature_library = Some(SignatureLibrary {
+        code_hash: [3u8; 32],
+        hash_type: 1,
+    });
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.signature_library, setup.signature_library);
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+}
+
+#[test]
+fn test_poa_setup_v1_extra_fields() {
+    let data = raw_poa_setup_v1(
+        [0, 1, 1],
+        &[7, 9],
+        &[
+            &[1],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[1, 2, 3],
+            &[],
+        ],
+    );
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.version, POA_SETUP_V1);
+    assert!(!parsed.round_interval_uses_seconds);
+    assert!(parsed.paused);
+    assert_eq!(
+        parsed.identities,
+        vec![Bytes::from_static(&[7]), Bytes::from_static(&[9])]
+    );
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        raw_poa_setup_v1(
+            [0, 1, 1],
+            &[7, 9],
+            &[
+                &[1],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0]
+            ]
+        )
+    );
+
+    // Tables written before optional fields were added omit them
+    let data = raw_poa_setup_v1([0, 1, 1], &[7, 9], &[]);
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert!(!parsed.paused);
+    assert!(parsed.weights.is_empty());
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        raw_poa_setup_v1(
+            [0, 1, 1],
+            &[7, 9],
+            &[
+                &[0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0]
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_poa_setup_paused_roundtrip() {
+    let mut setup = sample_poa_setup();
+    setup.paused = true;
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    assert_eq!(data[0], 5);
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert!(parsed.paused);
+
+    setup.version = POA_SETUP_V1;
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert!(parsed.paused);
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([1, 1, 1], &[7, 9], &[&[2]])),
+        Err(Error::InvalidSetupEncoding)
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([1, 1, 1], &[7, 9], &[&[1, 0]])),
+        Err(Error::InvalidSetupEncoding)
+    );
+}
+
+#[test]
+fn test_poa_setup_weights_roundtrip() {
+    let mut setup = sample_poa_setup();
+    setup.version = POA_SETUP_V1;
+    setup.weights = vec![3, 1, 2];
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.weights, vec![3, 1, 2]);
+    assert_eq!(parsed.round_duration(0), 270);
+    assert_eq!(parsed.round_subblocks(2), 6);
+    assert_eq!(parsed.round_duration(3), 270);
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[1, 0, 0, 0, 1]]
+        )),
+        Err(Error::InvalidWeights {
+            expected: 2,
+            actual: 1,
+        })
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[2, 0, 0, 0, 1, 0]]
+        )),
+        Err(Error::ZeroWeight(1))
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[2, 0, 0, 0, 1]]
+        )),
+        Err(Error::InvalidSetupEncoding)
+    );
+
+    setup.weights = vec![1, 2];
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidWeights {
+            expected: 3,
+            actual: 2,
+        })
+    );
+    setup.weights = vec![1, 0, 2];
+    assert_eq!(serialize_poa_setup(&setup), Err(Error::ZeroWeight(1)));
+    setup.weights = vec![1, 1, 2];
+    setup.version = POA_SETUP_V0;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::WeightsUnsupported(POA_SETUP_V0))
+    );
+}
+
+#[test]
+fn test_poa_setup_voting_weights_roundtrip() {
+    let mut setup = sample_poa_setup();
+    setup.version = POA_SETUP_V1;
+    setup.voting_weights = vec![100, 50, 0x01020304];
+    setup.voting_threshold = 120;
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.voting_weights, vec![100, 50, 0x01020304]);
+    assert_eq!(parsed.voting_threshold, 120);
+    assert_eq!(parsed.total_voting_weight(), 0x01020304 + 150);
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+
+    let voting_weights = [&2u32.to_le_bytes()[..], &[1, 0, 0, 0, 2, 0, 0, 0]].concat();
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[0, 0, 0, 0], &voting_weights, &[3, 0, 0, 0]]
+        ))
+        .map(|setup| setup.voting_threshold),
+        Ok(3)
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[0, 0, 0, 0], &voting_weights, &[4, 0, 0, 0]]
+        )),
+        Err(Error::InvalidVotingThreshold {
+            threshold: 4,
+            total_voting_weight: 3,
+        })
+    );
+    // Voting threshold is optional, but then voting weights cannot be used
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[0, 0, 0, 0], &voting_weights]
+        )),
+        Err(Error::InvalidVotingThreshold {
+            threshold: 0,
+            total_voting_weight: 3,
+        })
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[
+                &[0],
+                &[0, 0, 0, 0],
+                &[1, 0, 0, 0, 1, 0, 0, 0],
+                &[1, 0, 0, 0]
+            ]
+        )),
+        Err(Error::InvalidVotingWeights {
+            expected: 2,
+            actual: 1,
+        })
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[
+                &[0],
+                &[0, 0, 0, 0],
+                &[2, 0, 0, 0, 1, 0, 0, 0],
+                &[1, 0, 0, 0]
+            ]
+        )),
+        Err(Error::InvalidSetupEncoding)
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[0], &[0, 0, 0, 0], &[0, 0, 0, 0], &[1, 0]]
+        )),
+        Err(Error::InvalidSetupEncoding)
+    );
+
+    setup.voting_threshold = 0;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidVotingThreshold {
+            threshold: 0,
+            total_voting_weight: 0x01020304 + 150,
+        })
+    );
+    setup.voting_weights = Vec::new();
+    setup.voting_threshold = 1;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidVotingThreshold {
+            threshold: 1,
+            total_voting_weight: 0,
+        })
+    );
+    setup.voting_weights = vec![1, 1];
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::InvalidVotingWeights {
+            expected: 3,
+            actual: 2,
+        })
+    );
+    setup.voting_weights = vec![1, 1, 1];
+    setup.version = POA_SETUP_V0;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::VotingWeightsUnsupported(POA_SETUP_V0))
+    );
+}
+
+#[test]
+fn test_poa_setup_proposal_roundtrip() {
+    let mut setup = sample_poa_setup();
+    setup.version = POA_SETUP_V1;
+    let mut proposed_setup = setup.clone();
+    proposed_setup.round_intervals = 47;
+    setup.proposal_delay = 3600;
+    setup.proposal = Some(SetupProposal::new(0x0102030405060708, &proposed_setup).unwrap());
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    let parsed = parse_poa_setup(&data).expect("parse poa setup");
+    assert_eq!(parsed.proposal_delay, 3600);
+    let proposal = parsed.proposal.clone().expect("proposal");
+    assert_eq!(proposal.activation_subtime, 0x0102030405060708);
+    assert_eq!(
+        proposal.setup,
+        serialize_poa_setup(&proposed_setup).expect("serialize poa setup")
+    );
+    assert_eq!(
+        proposal.parse_setup().map(|setup| setup.round_intervals),
+        Ok(47)
+    );
+    assert_eq!(
+        serialize_poa_setup(&parsed).expect("serialize poa setup"),
+        data
+    );
+
+    assert_eq!(SetupProposal::new(0, &setup), Err(Error::NestedProposal));
+    // Proposals hold at least the activation subtime
+    let short_proposal = [&7u32.to_le_bytes()[..], &[0u8; 7]].concat();
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[
+                &[0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[10, 0, 0, 0],
+                &short_proposal
+            ]
+        )),
+        Err(Error::InvalidSetupEncoding)
+    );
+
+    setup.version = POA_SETUP_V0;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::ProposalUnsupported(POA_SETUP_V0))
+    );
+    setup.proposal = None;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::ProposalUnsupported(POA_SETUP_V0))
+    );
+}
+
+#[test]
+fn test_parse_poa_setup_v1_errors() {
+    let data = raw_poa_setup_v1([1, 1, 1], &[7, 9], &[]);
+    let mut unsupported = data.to_vec();
+    unsupported[0] = 0x82;
+    assert_eq!(
+        parse_poa_setup(&unsupported),
+        Err(Error::UnsupportedSetupVersion(2))
+    );
+    assert_eq!(
+        parse_poa_setup(&data[0..data.len() - 1]),
+        Err(Error::InvalidSetupEncoding)
+    );
+    assert_eq!(parse_poa_setup(&[0x81]), Err(Error::InvalidSetupEncoding));
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([2, 1, 1], &[7, 9], &[])),
+        Err(Error::InvalidSetupEncoding)
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([1, 33, 0], &[], &[])),
+        Err(Error::InvalidIdentitySize(33))
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([1, 2, 1], &[0u8; 5], &[])),
+        Err(Error::SetupLengthMismatch {
+            expected: 4,
+            actual: 5,
+        })
+    );
+    assert_eq!(
+        parse_poa_setup(&raw_poa_setup_v1([1, 1, 3], &[7, 9], &[])),
+        Err(Error::InvalidChangeThreshold {
+            threshold: 3,
+            aggregator_number: 2,
+        })
+    );
+
+    let mut setup = sample_poa_setup();
+    setup.version = 2;
+    assert_eq!(
+        serialize_poa_setup(&setup),
+        Err(Error::UnsupportedSetupVersion(2))
+    );
+}
+
+fn raw_signature_poa_setup_v1(identity_size: u8, identities: &[u8], library: &[u8]) -> Bytes {
+    let mut identities_field = (identities.len() as u32).to_le_bytes().to_vec();
+    identities_field.extend_from_slice(identities);
+    let fields: Vec<&[u8]> = vec![
+        &[1],
+        &[identity_size],
+        &[1],
+        &[90, 0, 0, 0],
+        &[3, 0, 0, 0],
+        &identities_field,
+        library,
+    ];
+    let mut buffer = vec![0x81];
+    buffer.extend_from_slice(&molecule_table(&fields));
+    buffer.into()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", hex)
+}
+
+// Dumps setups serialized by the Rust library, along with setups rejected by
+// `c/poa.c`, to check `parsePoASetup` in `src/config.ts` against them.
+#[test]
+fn test_dump_poa_setup_vectors() {
+    let signature_library = SignatureLibrary {
+        code_hash: [7u8; 32],
+        hash_type: 1,
+    };
+    let v0 = sample_poa_setup();
+    let v0_signature = PoASetup {
+        signature_library: Some(signature_library.clone()),
+        paused: true,
+        ..sample_poa_setup()
+    };
+    let v1 = PoASetup {
+        version: POA_SETUP_V1,
+        ..sample_poa_setup()
+    };
+    let v1_full = PoASetup {
+        signature_library: Some(signature_library),
+        paused: true,
+        weights: vec![1, 2, 3],
+        voting_weights: vec![10, 20, 30],
+        voting_threshold: 50,
+        proposal_delay: 3600,
+        proposal: Some(SetupProposal::new(5000, &v1).expect("proposal")),
+        ..v1.clone()
+    };
+    // Identity size 0 is accepted on chain
+    let v0_empty_identities = PoASetup {
+        identity_size: 0,
+        identities: vec![Bytes::new(); 2],
+        aggregator_change_threshold: 0,
+        ..sample_poa_setup()
+    };
+    let v1_no_identity = PoASetup {
+        version: POA_SETUP_V1,
+        identity_size: 0,
+        identities: Vec::new(),
+        aggregator_change_threshold: 0,
+        ..sample_poa_setup()
+    };
+    let valid: Vec<Bytes> = [
+        v0,
+        v0_signature,
+        v1,
+        v1_full,
+        v0_empty_identities,
+        v1_no_identity,
+    ]
+    .iter()
+    .map(|setup| serialize_poa_setup(setup).expect("serialize poa setup"))
+    .collect();
+
+    // Field offsets must be continuous, here the first field is preceded by
+    // an extra byte
+    let mut gap = raw_poa_setup_v1([1, 1, 1], &[7, 9], &[]).to_vec();
+    let header_size = u32::from_le_bytes([gap[5], gap[6], gap[7], gap[8]]) as usize;
+    for i in 0..header_size / 4 {
+        let offset = 1 + 4 * i;
+        let number = u32::from_le_bytes([
+            gap[offset],
+            gap[offset + 1],
+            gap[offset + 2],
+            gap[offset + 3],
+        ]);
+        gap[offset..offset + 4].copy_from_slice(&(number + 1).to_le_bytes());
+    }
+    gap.insert(1 + header_size, 0);
+    let invalid: Vec<Bytes> = vec![
+        // Signature flag without the signature library
+        raw_poa_setup([3, 20, 1, 1], &[0u8; 20]),
+        raw_poa_setup([1, 33, 0, 0], &[]),
+        raw_poa_setup([3, 21, 0, 0], &[0u8; 33]),
+        raw_poa_setup([1, 1, 1, 2], &[7]),
+        gap.into(),
+        raw_poa_setup_v1([2, 1, 1], &[7, 9], &[]),
+        raw_poa_setup_v1([1, 33, 0], &[], &[]),
+        raw_poa_setup_v1([1, 0, 0], &[7], &[]),
+        raw_poa_setup_v1([1, 1, 3], &[7, 9], &[]),
+        // Signature library that is too short, or with long identities
+        raw_signature_poa_setup_v1(1, &[7, 9], &[0u8; 32]),
+        raw_signature_poa_setup_v1(21, &[0u8; 21], &[0u8; 33]),
+        // Zero weight, and voting threshold without voting weights
+        raw_poa_setup_v1([1, 1, 1], &[7, 9], &[&[], &[2, 0, 0, 0, 1, 0]]),
+        raw_poa_setup_v1(
+            [1, 1, 1],
+            &[7, 9],
+            &[&[], &[], &[0, 0, 0, 0], &[1, 0, 0, 0]],
+        ),
+    ];
+
+    for data in &valid {
+        assert!(parse_poa_setup(data).is_ok());
+    }
+    for data in &invalid {
+        assert!(parse_poa_setup(data).is_err());
+    }
+    let vectors = json!({
+        "valid": valid.iter().map(|data| to_hex(data)).collect::<Vec<_>>(),
+        "invalid": invalid.iter().map(|data| to_hex(data)).collect::<Vec<_>>(),
+    });
+    fs::write(
+        Loader::default().path("poa_setup_vectors.json"),
+        to_string_pretty(&vectors).expect("serialize to json"),
+    )
+    .expect("write poa setup vectors");
+}
+
+#[test]
+fn test_poa_data_roundtrip() {
+    let data = PoAData {
+        round_initial_subtime: 0x0102030405060708,
+        subblock_subtime: 1100,
+        subblock_index: 3,
+        aggregator_index: 0x0201,
+        missed_rounds: Vec::new(),
+    };
+    let bytes = serialize_poa_data(&data);
+    assert_eq!(bytes.len(), 22);
+    assert_eq!(&bytes[0..8], &[8, 7, 6, 5, 4, 3, 2, 1]);
+    assert_eq!(&bytes[20..22], &[1, 2]);
+    assert_eq!(parse_poa_data(&bytes), Ok(data));
+    assert_eq!(
+        parse_poa_data(&bytes[0..21]),
+        Err(Error::InvalidDataLength(21))
+    );
+}
@@ -0,0 +1,196 @@
+use super::*;
+use ckb_tool::ckb_types::{core::TransactionView, packed::*, prelude::*};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Cycle limit of a CKB block, cycle budgets of each path are enforced by
+// baselines instead.
+const MAX_CYCLES: u64 = 3_500_000_000;
+
+const AGGREGATOR_COUNTS: [usize; 3] = [1, 16, 255];
+const INPUT_COUNTS: [usize; 3] = [1, 16, 64];
+const MAX_IDENTITY_SIZE: u8 = 32;
+
+// A path regresses when it consumes more than this many percent of cycles
+// over its baseline.
+const TOLERANCE_PERCENT: u64 = 5;
+
+const BASELINES_FILE: &str = "cycle_baselines.txt";
+// When set, measured cycles are written to the baselines file instead of
+// being checked against it. Otherwise every measured path must have a
+// baseline.
+const UPDATE_BASELINES_ENV_VAR: &str = "UPDATE_CYCLE_BASELINES";
+
+// Moves the first `count` inputs following the PoA cell and the PoA setup
+// or PoA data cell behind all other inputs. The PoA lock stops at the first
+// owner cell it finds, so this makes it search through all inputs.
+fn move_inputs_to_end(tx: TransactionView, count: usize) -> TransactionView {
+    let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    inputs[2..].rotate_left(count);
+    tx.as_advanced_builder().set_inputs(inputs).build()
+}
+
+// Subblock issued by the last aggregator once its round has started, with its
+// owner cell after all other inputs, so the PoA lock has to search through all
+// identities and inputs.
+fn subblock_tx(fixture: &mut PoAFixture, inputs: usize) -> TransactionView {
+    let aggregators = fixture.owner_scripts.len();
+    let mut builder = fixture.subblock_builder(aggregators - 1);
+    for _ in 1..inputs {
+        builder = builder.input(fixture.padding_cell());
+    }
+    let subtime = 1000 + 90 * aggregators as u64;
+    move_inputs_to_end(builder.build(subtime).expect("build tx"), 1)
+}
+
+// Setup update approved by the last aggregator_change_threshold aggregators,
+// following all other inputs.
+fn setup_update_tx(fixture: &mut PoAFixture, inputs: usize) -> TransactionView {
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    let aggregators = fixture.owner_scripts.len();
+    let approvers: Vec<usize> =
+        (aggregators - fixture.setup.aggregator_change_threshold as usize..aggregators).collect();
+    let mut builder = fixture.setup_update_builder(new_setup, &approvers);
+    for _ in 1..inputs {
+        builder = builder.input(fixture.padding_cell());
+    }
+    move_inputs_to_end(builder.build().expect("build tx"), approvers.len())
+}
+
+fn cycles(fixture: &mut PoAFixture, tx: TransactionView) -> u64 {
+    let tx = fixture.context.complete_tx(tx);
+    fixture
+        .context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification")
+}
+
+// Baselines are stored one path per line, as the path name followed by its
+// cycles. Lines starting with `#` are comments.
+fn baselines_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(BASELINES_FILE)
+}
+
+fn read_baselines() -> BTreeMap<String, u64> {
+    let content = fs::read_to_string(baselines_path()).unwrap_or_default();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next().expect("path name");
+            let cycles = parts
+                .next()
+                .and_then(|cycles| cycles.parse().ok())
+                .unwrap_or_else(|| panic!("invalid baseline: {}", line));
+            (name.to_string(), cycles)
+        })
+        .collect()
+}
+
+fn write_baselines(baselines: &BTreeMap<String, u64>) {
+    let mut content = format!(
+        "# Cycles consumed by each PoA lock path, checked by cycle_tests with a\n\
+         # tolerance of {}%. Regenerate with `make cycle-baselines`.\n",
+        TOLERANCE_PERCENT
+    );
+    for (name, cycles) in baselines {
+        content.push_str(&format!("{} {}\n", name, cycles));
+    }
+    fs::write(baselines_path(), content).expect("write baselines");
+}
+
+fn check_cycles(measured: &BTreeMap<String, u64>) {
+    let mut baselines = read_baselines();
+    if env::var(UPDATE_BASELINES_ENV_VAR).is_ok() {
+        baselines.extend(
+            measured
+                .iter()
+                .map(|(name, cycles)| (name.clone(), *cycles)),
+        );
+        write_baselines(&baselines);
+        return;
+    }
+    let mut regressions = Vec::new();
+    let mut missing = Vec::new();
+    for (name, cycles) in measured {
+        match baselines.get(name) {
+            Some(baseline) if *cycles * 100 > *baseline * (100 + TOLERANCE_PERCENT) => {
+                regressions.push(format!(
+                    "{}: {} cycles, baseline {}",
+                    name, cycles, baseline
+                ));
+            }
+            Some(baseline) if *cycles * 100 < *baseline * (100 - TOLERANCE_PERCENT) => {
+                println!(
+                    "{} improved to {} cycles from {}, consider updating baselines",
+                    name, cycles, baseline
+                );
+            }
+            Some(_) => (),
+            None => missing.push(format!("{}: {} cycles", name, cycles)),
+        }
+    }
+    assert!(
+        missing.is_empty(),
+        "paths without a baseline, run `make cycle-baselines`:\n{}",
+        missing.join("\n")
+    );
+    assert!(
+        regressions.is_empty(),
+        "cycle regressions over {}%:\n{}",
+        TOLERANCE_PERCENT,
+        regressions.join("\n")
+    );
+}
+
+#[test]
+fn test_cycle_budgets() {
+    let mut measured = BTreeMap::new();
+    for aggregators in AGGREGATOR_COUNTS.iter() {
+        for identity_size in 1..=MAX_IDENTITY_SIZE {
+            let mut fixture = PoAFixture::builder(*aggregators)
+                .identity_size(identity_size)
+                // 2/3 of all aggregators
+                .aggregator_change_threshold(((aggregators * 2 + 2) / 3) as u8)
+                .build();
+            let tx = subblock_tx(&mut fixture, 1);
+            measured.insert(
+                format!(
+                    "subblock/aggregators={}/identity_size={}",
+                    aggregators, identity_size
+                ),
+                cycles(&mut fixture, tx),
+            );
+            let tx = setup_update_tx(&mut fixture, 1);
+            measured.insert(
+                format!(
+                    "setup_update/aggregators={}/identity_size={}",
+                    aggregators, identity_size
+                ),
+                cycles(&mut fixture, tx),
+            );
+        }
+        let mut fixture = PoAFixture::builder(*aggregators)
+            .identity_size(MAX_IDENTITY_SIZE)
+            .aggregator_change_threshold(((aggregators * 2 + 2) / 3) as u8)
+            .build();
+        for inputs in INPUT_COUNTS.iter() {
+            let tx = subblock_tx(&mut fixture, *inputs);
+            measured.insert(
+                format!("subblock/aggregators={}/inputs={}", aggregators, inputs),
+                cycles(&mut fixture, tx),
+            );
+            let tx = setup_update_tx(&mut fixture, *inputs);
+            measured.insert(
+                format!("setup_update/aggregators={}/inputs={}", aggregators, inputs),
+                cycles(&mut fixture, tx),
+            );
+        }
+    }
+    check_cycles(&measured);
+}
@@ -0,0 +1,215 @@
+use super::*;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
+use ckb_tool::ckb_types::{bytes::Bytes, core::TransactionView, packed::*, prelude::*};
+use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    parse_poa_data, parse_poa_setup, type_id_args, GenesisTxBuilder, PoAData, PoASetup,
+    SetupUpdateTxBuilder, SubblockTxBuilder,
+};
+use std::collections::HashMap;
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+fn verify(context: &mut Context, tx: TransactionView) -> TransactionView {
+    let tx = context.complete_tx(tx);
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+    tx
+}
+
+#[test]
+fn test_genesis_bootstrap() {
+    // deploy contract
+    let mut context = Context::default();
+    let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let state_bin: Bytes = Loader::default().load_binary("state.strip");
+    let state_out_point = deploy_cell(&mut context, state_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
+    let cell_deps = vec![
+        CellDep::new_builder()
+            .out_point(poa_out_point.clone())
+            .build(),
+        CellDep::new_builder()
+            .out_point(state_out_point.clone())
+            .build(),
+        CellDep::new_builder()
+            .out_point(always_success_out_point.clone())
+            .build(),
+    ];
+
+    // prepare scripts
+    let poa_owner_script1 = context
+        .build_script(&always_success_out_point, random_32bytes())
+        .expect("build script");
+    let poa_owner_script2 = context
+        .build_script(&always_success_out_point, random_32bytes())
+        .expect("build script");
+    let poa_lock_script = context
+        .build_script(&poa_out_point, Bytes::new())
+        .expect("build script");
+    let state_lock_script = context
+        .build_script(&state_out_point, Bytes::new())
+        .expect("build script");
+    let setup = PoASetup {
+        round_interval_uses_seconds: true,
+        identities: vec![
+            poa_owner_script1.calc_script_hash().as_bytes(),
+            poa_owner_script2.calc_script_hash().as_bytes(),
+        ],
+        aggregator_change_threshold: 2,
+        round_intervals: 90,
+        subblocks_per_round: 1,
+        ..Default::default()
+    };
+
+    // genesis transaction
+    let funding_cell = create_cell_info(
+        &mut context,
+        CellOutput::new_builder()
+            .capacity(100_000_000_000u64.pack())
+            .lock(poa_owner_script1.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let genesis = GenesisTxBuilder::new(
+        funding_cell.clone(),
+        setup.clone(),
+        poa_lock_script,
+        state_lock_script,
+    )
+    .initial_subtime(1000)
+    .poa_cell_data(Bytes::from_static(b"genesis"))
+    .cell_dep(cell_deps[2].clone())
+    .build()
+    .expect("build genesis");
+
+    let first_input = funding_cell.input(0);
+    assert_eq!(
+        genesis.poa_setup_type_id_args,
+        type_id_args(&first_input, 1)
+    );
+    assert_eq!(genesis.poa_data_type_id_args, type_id_args(&first_input, 2));
+    assert_eq!(
+        genesis.poa_lock_args.slice(0..32),
+        genesis.poa_setup_type_id_args
+    );
+    assert_eq!(
+        genesis.poa_lock_args.slice(32..64),
+        genesis.poa_data_type_id_args
+    );
+    let poa_cell = genesis.poa_cell();
+    let poa_setup_cell = genesis.poa_setup_cell();
+    let poa_data_cell = genesis.poa_data_cell();
+    assert_eq!(
+        poa_cell.output.lock().args().raw_data(),
+        genesis.poa_lock_args
+    );
+    assert_eq!(
+        genesis.state_lock_args,
+        poa_cell.output.lock().calc_script_hash().as_bytes()
+    );
+    assert_eq!(
+        poa_setup_cell.output.lock().args().raw_data(),
+        genesis.state_lock_args
+    );
+    assert_eq!(
+        poa_data_cell.output.lock().args().raw_data(),
+        genesis.state_lock_args
+    );
+    assert_eq!(parse_poa_setup(&poa_setup_cell.data), Ok(setup.clone()));
+    assert_eq!(
+        parse_poa_data(&poa_data_cell.data),
+        Ok(PoAData {
+            round_initial_subtime: 1000,
+            subblock_subtime: 1000,
+            subblock_index: 0,
+            aggregator_index: 0,
+            missed_rounds: Vec::new(),
+        })
+    );
+    // Type ID scripts validate the calculated type ID args.
+    verify(&mut context, genesis.tx.clone());
+    for cell in &[&poa_cell, &poa_setup_cell, &poa_data_cell] {
+        context.create_cell_with_out_point(
+            cell.out_point.clone(),
+            cell.output.clone(),
+            cell.data.clone(),
+        );
+    }
+
+    let running_setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+
+    // issue a subblock from genesis cells
+    let owner2_cell = create_cell_info(
+        &mut context,
+        CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(poa_owner_script2.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let tx = cell_deps
+        .iter()
+        .fold(
+            SubblockTxBuilder::new(
+                poa_cell.clone(),
+                poa_data_cell.clone(),
+                poa_setup_cell.clone(),
+                poa_owner_script2.clone(),
+            )
+            .input(owner2_cell.clone()),
+            |builder, cell_dep| builder.cell_dep(cell_dep.clone()),
+        )
+        .build(1090)
+        .expect("build subblock");
+    let tx = verify(&mut context, tx);
+    write_native_setup(
+        "genesis_subblock",
+        "poa_sim",
+        &tx,
+        &context,
+        &running_setup,
+        0,
+        true,
+    );
+
+    // update setup from genesis cells
+    let owner1_cell = create_cell_info(
+        &mut context,
+        CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(poa_owner_script1.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let mut new_setup = setup;
+    new_setup.round_intervals = 47;
+    let tx = cell_deps
+        .iter()
+        .fold(
+            SetupUpdateTxBuilder::new(poa_cell, poa_setup_cell, new_setup)
+                .approval(owner1_cell)
+                .approval(owner2_cell),
+            |builder, cell_dep| builder.cell_dep(cell_dep.clone()),
+        )
+        .build()
+        .expect("build setup update");
+    let tx = verify(&mut context, tx);
+    write_native_setup(
+        "genesis_setup_update",
+        "poa_sim",
+        &tx,
+        &context,
+        &running_setup,
+        0,
+        true,
+    );
+}
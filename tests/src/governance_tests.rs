@@ -0,0 +1,1050 @@
+use super::*;
+use ckb_tool::ckb_types::{
+    bytes::{Bytes, BytesMut},
+    core::TransactionView,
+    packed::*,
+    prelude::*,
+};
+use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    approved_identities, approved_voting_weight, has_enough_approvals, identities_changed,
+    migrate_poa_data, offline_aggregators, parse_poa_data, parse_poa_setup, serialize_poa_data,
+    serialize_poa_setup, validate_setup_proposal, validate_setup_update, Error, PoAData, PoASetup,
+    PoaError, SetupProposal, SetupUpdateTxBuilder, POA_SETUP_V1, SINCE_RELATIVE_FLAG,
+    SINCE_TIMESTAMP_FLAG,
+};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+// PoA deployment in the middle of the first round, where aggregator 0 has
+// issued 2 subblocks.
+fn governance_fixture_builder(
+    aggregators: usize,
+    aggregator_change_threshold: u8,
+) -> PoAFixtureBuilder {
+    PoAFixture::builder(aggregators)
+        .aggregator_change_threshold(aggregator_change_threshold)
+        .poa_data(poa_data(1000, 1010, 1, 0))
+}
+
+fn governance_fixture(aggregators: usize, aggregator_change_threshold: u8) -> PoAFixture {
+    governance_fixture_builder(aggregators, aggregator_change_threshold).build()
+}
+
+// Continues from the PoA cell and PoA setup cell updated by `tx`.
+fn apply(fixture: &mut PoAFixture, tx: &TransactionView) {
+    fixture.poa_cell = fixture.commit_output(tx, 0);
+    fixture.poa_setup_cell = fixture.commit_output(tx, 1);
+    fixture.setup = parse_poa_setup(&fixture.poa_setup_cell.data).expect("parse poa setup");
+}
+
+#[test]
+fn test_setup_update_builder() {
+    let mut fixture = governance_fixture(2, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    new_setup.subblocks_per_round = 2;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1]);
+    assert_eq!(builder.missing_approvals(), Ok(vec![]));
+    let tx = builder.build().expect("build tx");
+    assert_eq!(
+        parse_poa_setup(&tx.outputs_data().get(1).unwrap().raw_data()),
+        Ok(new_setup)
+    );
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "setup_update_builder",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_setup_update_builder_partial_approvals() {
+    let mut fixture = governance_fixture(3, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.identities.remove(1);
+    new_setup.aggregator_change_threshold = 1;
+    let poa_data = migrate_poa_data(
+        &fixture.setup,
+        &new_setup,
+        &parse_poa_data(&fixture.poa_data_cell.data).expect("parse poa data"),
+    );
+    let builder = fixture
+        .setup_update_builder(new_setup, &[0, 2])
+        .poa_data_cell(fixture.poa_data_cell.clone(), poa_data);
+    assert_eq!(
+        builder.missing_approvals(),
+        Ok(vec![fixture.setup.identities[1].clone()])
+    );
+    let tx = builder.build().expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_setup_update_builder_not_enough_approvals() {
+    let mut fixture = governance_fixture(3, 2);
+    let new_setup = fixture.setup.clone();
+    let builder = fixture.setup_update_builder(new_setup, &[1]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::NotEnoughApprovals {
+            required: 2,
+            missing: vec![
+                fixture.setup.identities[0].clone(),
+                fixture.setup.identities[2].clone(),
+            ],
+        })
+    );
+}
+
+#[test]
+fn test_setup_update_builder_duplicate_approvals() {
+    let mut fixture = governance_fixture(2, 2);
+    let new_setup = fixture.setup.clone();
+    let builder = fixture.setup_update_builder(new_setup, &[0, 0]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::NotEnoughApprovals {
+            required: 2,
+            missing: vec![fixture.setup.identities[1].clone()],
+        })
+    );
+}
+
+#[test]
+fn test_setup_update_builder_invalid_setup() {
+    let mut fixture = governance_fixture(2, 1);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.aggregator_change_threshold = 3;
+    let builder = fixture.setup_update_builder(new_setup, &[0]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::InvalidChangeThreshold {
+            threshold: 3,
+            aggregator_number: 2,
+        })
+    );
+}
+
+#[test]
+fn test_approved_identities() {
+    let identity = random_32bytes();
+    let other = random_32bytes();
+    let setup = PoASetup {
+        identity_size: 4,
+        round_interval_uses_seconds: true,
+        identities: vec![identity.clone(), other.clone(), identity.clone()],
+        aggregator_change_threshold: 2,
+        round_intervals: 90,
+        subblocks_per_round: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        approved_identities(&setup, &[identity.clone()]),
+        vec![true, false, false]
+    );
+    assert_eq!(
+        approved_identities(&setup, &[identity.clone(), identity.clone()]),
+        vec![true, false, true]
+    );
+    assert_eq!(
+        approved_identities(&setup, &[random_32bytes(), other, identity.slice(0..3)]),
+        vec![false, true, false]
+    );
+}
+
+// Replaces data of the output at `index`, bypassing checks in the builder.
+fn replace_output_data(tx: &TransactionView, index: usize, data: Bytes) -> TransactionView {
+    let mut outputs_data: Vec<Bytes> = tx
+        .outputs_data()
+        .into_iter()
+        .map(|data| data.raw_data())
+        .collect();
+    outputs_data[index] = data;
+    tx.as_advanced_builder()
+        .set_outputs_data(outputs_data.into_iter().map(|data| data.pack()).collect())
+        .build()
+}
+
+#[test]
+fn test_setup_update_no_identity() {
+    let mut fixture = governance_fixture(2, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.identities.clear();
+    new_setup.aggregator_change_threshold = 0;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1]);
+    assert_eq!(builder.build().err(), Some(Error::NoIdentity));
+
+    let tx = fixture
+        .setup_update_builder(fixture.setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::NoIdentity));
+}
+
+#[test]
+fn test_setup_update_threshold_below_default_minimum() {
+    let mut fixture = governance_fixture(2, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.aggregator_change_threshold = 0;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::ThresholdBelowMinimum {
+            threshold: 0,
+            minimum: 1,
+        })
+    );
+
+    let tx = fixture
+        .setup_update_builder(fixture.setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::ThresholdBelowMinimum)
+    );
+}
+
+#[test]
+fn test_setup_update_threshold_below_custom_minimum() {
+    let mut fixture = governance_fixture_builder(3, 3)
+        .minimum_change_threshold(2)
+        .build();
+    let mut new_setup = fixture.setup.clone();
+    new_setup.aggregator_change_threshold = 1;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1, 2]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::ThresholdBelowMinimum {
+            threshold: 1,
+            minimum: 2,
+        })
+    );
+
+    let tx = fixture
+        .setup_update_builder(fixture.setup.clone(), &[0, 1, 2])
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::ThresholdBelowMinimum)
+    );
+
+    new_setup.aggregator_change_threshold = 2;
+    let tx = fixture
+        .setup_update_builder(new_setup, &[0, 1, 2])
+        .build()
+        .expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_setup_update_switch_interval_kind() {
+    let mut fixture = governance_fixture(2, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_interval_uses_seconds = false;
+    new_setup.round_intervals = 10;
+    let reset_data = poa_data(200, 200, 0, 1);
+    let tx = fixture
+        .setup_update_builder(new_setup.clone(), &[0, 1])
+        .poa_data_cell(fixture.poa_data_cell.clone(), reset_data.clone())
+        .build()
+        .expect("build tx");
+    assert_eq!(
+        tx.outputs_data().get(2).unwrap().raw_data(),
+        serialize_poa_data(&reset_data)
+    );
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "setup_update_switch_interval_kind",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_setup_update_switch_interval_kind_without_reset() {
+    let mut fixture = governance_fixture(2, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_interval_uses_seconds = false;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1]);
+    assert_eq!(builder.build().err(), Some(Error::PoADataResetRequired));
+    let stale_data = poa_data(1000, 1010, 1, 0);
+    let builder = fixture
+        .setup_update_builder(new_setup.clone(), &[0, 1])
+        .poa_data_cell(fixture.poa_data_cell.clone(), stale_data.clone());
+    assert_eq!(builder.build().err(), Some(Error::PoADataResetRequired));
+
+    // PoA data cell is left untouched
+    let tx = fixture
+        .setup_update_builder(fixture.setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataCellMissing));
+
+    // PoA data cell keeps the old round
+    let tx = fixture
+        .setup_update_builder(new_setup.clone(), &[0, 1])
+        .poa_data_cell(fixture.poa_data_cell.clone(), PoAData::default())
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(&tx, 2, serialize_poa_data(&stale_data));
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataNotReset));
+
+    // Reset PoA data refers to a missing aggregator
+    let tx = replace_output_data(&tx, 2, serialize_poa_data(&poa_data(200, 200, 0, 2)));
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataNotReset));
+}
+
+#[test]
+fn test_validate_setup_update() {
+    let fixture = governance_fixture(2, 2);
+    let setup = fixture.setup.clone();
+    assert_eq!(validate_setup_update(&setup, &setup, 2, None), Ok(()));
+    assert_eq!(
+        validate_setup_update(&setup, &setup, 3, None),
+        Err(Error::ThresholdBelowMinimum {
+            threshold: 2,
+            minimum: 3,
+        })
+    );
+    let mut new_setup = setup.clone();
+    new_setup.round_interval_uses_seconds = false;
+    assert_eq!(
+        validate_setup_update(&setup, &new_setup, 1, None),
+        Err(Error::PoADataResetRequired)
+    );
+    assert_eq!(
+        validate_setup_update(&setup, &new_setup, 1, Some(&PoAData::default())),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_setup_update_remove_current_aggregator() {
+    let mut fixture = governance_fixture(3, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.identities.remove(0);
+    let reset_data = migrate_poa_data(
+        &fixture.setup,
+        &new_setup,
+        &parse_poa_data(&fixture.poa_data_cell.data).expect("parse poa data"),
+    );
+    assert_eq!(reset_data, poa_data(1010, 1010, 0, 0));
+    let tx = fixture
+        .setup_update_builder(new_setup, &[0, 1])
+        .poa_data_cell(fixture.poa_data_cell.clone(), reset_data)
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "setup_update_remove_current_aggregator",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+
+    // The chain moves on with the remaining aggregators.
+    fixture.poa_cell = fixture.commit_output(&tx, 0);
+    fixture.poa_setup_cell = fixture.commit_output(&tx, 1);
+    fixture.poa_data_cell = fixture.commit_output(&tx, 2);
+    let builder = fixture.subblock_builder(2);
+    assert_eq!(
+        builder.build(1099).err(),
+        Some(Error::RoundNotStarted(1100))
+    );
+    let tx = builder.build(1100).expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_setup_update_remove_aggregator_without_reset() {
+    let mut fixture = governance_fixture(3, 2);
+    let mut new_setup = fixture.setup.clone();
+    new_setup.identities.remove(0);
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 1]);
+    assert_eq!(builder.build().err(), Some(Error::PoADataResetRequired));
+
+    let tx = fixture
+        .setup_update_builder(fixture.setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataCellMissing));
+
+    // Aggregator index still points past the new aggregator list
+    let tx = fixture
+        .setup_update_builder(new_setup, &[0, 1])
+        .poa_data_cell(fixture.poa_data_cell.clone(), PoAData::default())
+        .build()
+        .expect("build tx");
+    let tx = replace_output_data(&tx, 2, serialize_poa_data(&poa_data(1010, 1010, 0, 2)));
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataNotReset));
+}
+
+#[test]
+fn test_migrate_poa_data() {
+    let identities: Vec<Bytes> = (0..4).map(|_| random_32bytes()).collect();
+    let setup = PoASetup {
+        round_interval_uses_seconds: true,
+        identities: identities.clone(),
+        aggregator_change_threshold: 2,
+        round_intervals: 90,
+        subblocks_per_round: 1,
+        ..Default::default()
+    };
+    let poa_data = poa_data(1000, 1020, 3, 2);
+    let migrated_index = |kept: &[usize]| {
+        let mut new_setup = setup.clone();
+        new_setup.identities = kept.iter().map(|i| identities[*i].clone()).collect();
+        let migrated = migrate_poa_data(&setup, &new_setup, &poa_data);
+        assert_eq!(migrated.round_initial_subtime, 1020);
+        assert_eq!(migrated.subblock_subtime, 1020);
+        assert_eq!(migrated.subblock_index, 0);
+        migrated.aggregator_index
+    };
+    assert_eq!(migrated_index(&[0, 1, 2, 3]), 2);
+    assert_eq!(migrated_index(&[3, 2]), 1);
+    assert_eq!(migrated_index(&[0, 1, 3]), 2);
+    assert_eq!(migrated_index(&[1, 0]), 1);
+
+    let mut new_setup = setup.clone();
+    assert!(!identities_changed(&setup, &new_setup));
+    new_setup.identities.swap(0, 1);
+    assert!(identities_changed(&setup, &new_setup));
+    new_setup.identities.swap(0, 1);
+    new_setup.round_intervals = 10;
+    assert!(!identities_changed(&setup, &new_setup));
+    new_setup.identity_size = 20;
+    assert!(identities_changed(&setup, &new_setup));
+}
+
+// Index sharing the same mask bit with `index` when bits are set with a 32
+// bit shift.
+fn aliased_index(index: usize) -> usize {
+    index / 64 * 64 + index % 64 % 32
+}
+
+#[test]
+fn test_setup_update_high_index_approvals() {
+    for aggregators in &[33, 64, 128, 255] {
+        let mut fixture = governance_fixture(*aggregators, 2);
+        let high_index = aggregators - 1;
+        let low_index = aliased_index(high_index);
+        assert_ne!(high_index, low_index);
+        let tx = fixture
+            .setup_update_builder(fixture.setup.clone(), &[high_index, low_index])
+            .build()
+            .expect("build tx");
+        fixture.verify(tx.clone());
+
+        // The same high-index aggregator approving twice only counts once.
+        let duplicate_input = fixture.owner_cell(high_index).input(0);
+        let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+        inputs[3] = duplicate_input;
+        let tx = tx.as_advanced_builder().set_inputs(inputs).build();
+        assert_eq!(fixture.verify_error(tx), Some(PoaError::NotEnoughApprovals));
+    }
+}
+
+#[test]
+fn test_setup_update_many_aggregators() {
+    for aggregators in &[33, 64, 128, 255] {
+        let threshold = (aggregators / 2) as u8;
+        let mut fixture = governance_fixture(*aggregators, threshold);
+        let mut new_setup = fixture.setup.clone();
+        new_setup.round_intervals = 47;
+        let approvers: Vec<usize> = (aggregators - threshold as usize..*aggregators).collect();
+        let builder = fixture.setup_update_builder(new_setup.clone(), &approvers);
+        assert_eq!(
+            builder.missing_approvals().map(|missing| missing.len()),
+            Ok(aggregators - threshold as usize)
+        );
+        let tx = builder.build().expect("build tx");
+        fixture.verify(tx);
+
+        let builder = fixture.setup_update_builder(new_setup, &approvers[1..]);
+        assert_eq!(
+            builder.build().err().map(|err| match err {
+                Error::NotEnoughApprovals { required, .. } => required,
+                _ => 0,
+            }),
+            Some(threshold)
+        );
+    }
+}
+
+#[test]
+fn test_setup_update_evict_offline_aggregator() {
+    let mut fixture = governance_fixture(3, 2);
+    let poa_data = PoAData {
+        round_initial_subtime: 1000,
+        subblock_subtime: 1000,
+        subblock_index: 0,
+        aggregator_index: 2,
+        missed_rounds: vec![0, 4, 1],
+    };
+    assert_eq!(
+        offline_aggregators(&fixture.setup, &poa_data, 3),
+        Ok(vec![fixture.setup.identities[1].clone()])
+    );
+
+    let mut new_setup = fixture.setup.clone();
+    new_setup.identities.remove(1);
+    let reset_data = migrate_poa_data(&fixture.setup, &new_setup, &poa_data);
+    assert_eq!(reset_data.aggregator_index, 1);
+    assert_eq!(reset_data.missed_rounds, vec![0, 0]);
+    let tx = fixture
+        .setup_update_builder(new_setup.clone(), &[0, 2])
+        .poa_data_cell(fixture.poa_data_cell.clone(), reset_data.clone())
+        .build()
+        .expect("build tx");
+    fixture.verify(tx.clone());
+
+    // Missed rounds of the old aggregator list cannot be carried over
+    let mut stale_data = reset_data;
+    stale_data.missed_rounds = vec![0, 1];
+    let builder = fixture
+        .setup_update_builder(new_setup, &[0, 2])
+        .poa_data_cell(fixture.poa_data_cell.clone(), stale_data.clone());
+    assert_eq!(builder.build().err(), Some(Error::PoADataResetRequired));
+    let tx = replace_output_data(&tx, 2, serialize_poa_data(&stale_data));
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::PoADataNotReset));
+}
+
+#[test]
+fn test_has_enough_approvals() {
+    let mut fixture = governance_fixture(4, 2);
+    let setup = &mut fixture.setup;
+    assert!(has_enough_approvals(setup, &[true, false, false, true]));
+    assert!(!has_enough_approvals(setup, &[false, false, false, true]));
+    assert_eq!(approved_voting_weight(setup, &[true, false, true, true]), 3);
+
+    setup.version = POA_SETUP_V1;
+    setup.voting_weights = vec![60, 20, 15, 5];
+    setup.voting_threshold = 67;
+    assert_eq!(
+        approved_voting_weight(setup, &[false, true, true, true]),
+        40
+    );
+    assert!(!has_enough_approvals(setup, &[false, true, true, true]));
+    assert!(!has_enough_approvals(setup, &[true, false, false, true]));
+    assert!(has_enough_approvals(setup, &[true, false, true, false]));
+    assert!(has_enough_approvals(setup, &[true, true, false, false]));
+
+    setup.aggregator_change_threshold = 0;
+    assert!(has_enough_approvals(setup, &[true, true, false, false]));
+    setup.voting_weights = Vec::new();
+    setup.voting_threshold = 0;
+    assert!(!has_enough_approvals(setup, &[true, true, true, true]));
+}
+
+#[test]
+fn test_setup_update_weighted_voting() {
+    let mut fixture = governance_fixture(4, 2);
+
+    // Switch governance to 2/3 of voting weights, which no longer depends on
+    // the number of approving aggregators
+    let mut weighted_setup = fixture.setup.clone();
+    weighted_setup.version = POA_SETUP_V1;
+    weighted_setup.aggregator_change_threshold = 3;
+    weighted_setup.voting_weights = vec![60, 20, 15, 5];
+    weighted_setup.voting_threshold = 67;
+    let tx = fixture
+        .setup_update_builder(weighted_setup, &[1, 2])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[1, 2, 3]);
+    assert_eq!(builder.has_enough_approvals(), Ok(false));
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::NotEnoughVotingWeight {
+            required: 67,
+            approved: 40,
+            missing: vec![fixture.setup.identities[0].clone()],
+        })
+    );
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 3]);
+    assert_eq!(builder.has_enough_approvals(), Ok(false));
+
+    let builder = fixture.setup_update_builder(new_setup.clone(), &[0, 2]);
+    assert_eq!(builder.has_enough_approvals(), Ok(true));
+    let tx = builder.build().expect("build tx");
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "setup_update_weighted_voting",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+
+    // Swapping the approval of aggregator 2 for aggregator 3 drops the
+    // approved voting weight to 65
+    let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    inputs[3] = fixture.owner_cell(3).input(0);
+    let tx = tx.as_advanced_builder().set_inputs(inputs).build();
+    assert_eq!(
+        fixture.verify_error(tx.clone()),
+        Some(PoaError::NotEnoughApprovals)
+    );
+    let tx = fixture.context.complete_tx(tx);
+    write_native_setup(
+        "setup_update_weighted_voting_failure",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        PoaError::NotEnoughApprovals.code(),
+        true,
+    );
+
+    // Unreachable voting thresholds are rejected, voting threshold is the
+    // last field of the setup
+    let tx = fixture
+        .setup_update_builder(new_setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let mut setup_data = serialize_poa_setup(&new_setup)
+        .expect("serialize poa setup")
+        .to_vec();
+    let len = setup_data.len();
+    setup_data[len - 4..].copy_from_slice(&101u32.to_le_bytes());
+    assert_eq!(
+        parse_poa_setup(&setup_data),
+        Err(Error::InvalidVotingThreshold {
+            threshold: 101,
+            total_voting_weight: 100,
+        })
+    );
+    let tx = replace_output_data(&tx, 1, setup_data.into());
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::InvalidVotingWeights)
+    );
+}
+
+// Replaces the since value of the input at `index`.
+fn rewrite_since(tx: &TransactionView, index: usize, since: u64) -> TransactionView {
+    let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    inputs[index] = inputs[index]
+        .clone()
+        .as_builder()
+        .since(since.pack())
+        .build();
+    tx.as_advanced_builder().set_inputs(inputs).build()
+}
+
+#[test]
+fn test_setup_update_time_lock() {
+    let mut fixture = governance_fixture(3, 2);
+    let mut time_locked_setup = fixture.setup.clone();
+    time_locked_setup.version = POA_SETUP_V1;
+    time_locked_setup.proposal_delay = 3600;
+    let tx = fixture
+        .setup_update_builder(time_locked_setup, &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+
+    // Setup changes can no longer be applied directly
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    assert_eq!(
+        fixture
+            .setup_update_builder(new_setup.clone(), &[0, 1])
+            .build()
+            .err(),
+        Some(Error::ProposalRequired)
+    );
+    let builder = SetupUpdateTxBuilder::propose(
+        fixture.poa_cell.clone(),
+        fixture.poa_setup_cell.clone(),
+        new_setup.clone(),
+        5000,
+    );
+    let tx = fixture.approve(builder, &[0, 1]).build().expect("build tx");
+    let direct_tx = replace_output_data(
+        &tx,
+        1,
+        serialize_poa_setup(&new_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(
+        fixture.verify_error(direct_tx),
+        Some(PoaError::ProposalRequired)
+    );
+
+    // Propose
+    let tx = fixture.verify(tx);
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "setup_update_propose",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+    apply(&mut fixture, &tx);
+    assert_eq!(fixture.setup.round_intervals, 90);
+    let proposal = fixture.setup.proposal.clone().expect("proposal");
+    assert_eq!(proposal.activation_subtime, 5000);
+    assert_eq!(proposal.parse_setup(), Ok(new_setup.clone()));
+
+    let builder = SetupUpdateTxBuilder::propose(
+        fixture.poa_cell.clone(),
+        fixture.poa_setup_cell.clone(),
+        new_setup.clone(),
+        6000,
+    );
+    assert_eq!(
+        fixture.approve(builder, &[0, 1]).build().err(),
+        Some(Error::ProposalPending)
+    );
+
+    // Commit waits for both the activation subtime and the proposal delay
+    let builder =
+        SetupUpdateTxBuilder::commit(fixture.poa_cell.clone(), fixture.poa_setup_cell.clone());
+    let commit_tx = fixture.approve(builder, &[0, 2]).build().expect("build tx");
+    let since: u64 = commit_tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, SINCE_TIMESTAMP_FLAG | 5000);
+    let since: u64 = commit_tx.inputs().get(1).unwrap().since().unpack();
+    assert_eq!(since, SINCE_RELATIVE_FLAG | SINCE_TIMESTAMP_FLAG | 3600);
+
+    let early_tx = rewrite_since(&commit_tx, 0, SINCE_TIMESTAMP_FLAG | 4999);
+    assert_eq!(
+        fixture.verify_error(early_tx.clone()),
+        Some(PoaError::ProposalNotActive)
+    );
+    let early_tx = fixture.context.complete_tx(early_tx);
+    write_native_setup(
+        "setup_update_early_commit",
+        "poa_sim",
+        &early_tx,
+        &fixture.context,
+        &setup,
+        PoaError::ProposalNotActive.code(),
+        true,
+    );
+    let early_tx = rewrite_since(
+        &commit_tx,
+        1,
+        SINCE_RELATIVE_FLAG | SINCE_TIMESTAMP_FLAG | 3599,
+    );
+    assert_eq!(
+        fixture.verify_error(early_tx),
+        Some(PoaError::ProposalNotActive)
+    );
+    // An absolute since on the PoA setup cell does not prove the delay
+    let early_tx = rewrite_since(&commit_tx, 1, SINCE_TIMESTAMP_FLAG | 100_000);
+    assert_eq!(
+        fixture.verify_error(early_tx),
+        Some(PoaError::ProposalNotActive)
+    );
+
+    // Only the proposed setup can be committed
+    let mut other_setup = new_setup.clone();
+    other_setup.round_intervals = 48;
+    let other_tx = replace_output_data(
+        &commit_tx,
+        1,
+        serialize_poa_setup(&other_setup).expect("serialize poa setup"),
+    );
+    assert_eq!(
+        fixture.verify_error(other_tx),
+        Some(PoaError::InvalidProposal)
+    );
+
+    // Cancel
+    let builder =
+        SetupUpdateTxBuilder::cancel(fixture.poa_cell.clone(), fixture.poa_setup_cell.clone());
+    let tx = fixture.approve(builder, &[1, 2]).build().expect("build tx");
+    let since: u64 = tx.inputs().get(1).unwrap().since().unpack();
+    assert_eq!(since, 0);
+    let cancelled_setup =
+        parse_poa_setup(&tx.outputs_data().get(1).unwrap().raw_data()).expect("parse poa setup");
+    assert_eq!(cancelled_setup.proposal, None);
+    assert_eq!(cancelled_setup.round_intervals, 90);
+    fixture.verify(tx);
+
+    // Commit
+    let commit_tx = fixture.verify(commit_tx);
+    write_native_setup(
+        "setup_update_commit",
+        "poa_sim",
+        &commit_tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+    apply(&mut fixture, &commit_tx);
+    assert_eq!(fixture.setup, new_setup);
+    let builder =
+        SetupUpdateTxBuilder::commit(fixture.poa_cell.clone(), fixture.poa_setup_cell.clone());
+    assert_eq!(
+        fixture.approve(builder, &[0, 1]).build().err(),
+        Some(Error::NoProposal)
+    );
+    let builder =
+        SetupUpdateTxBuilder::cancel(fixture.poa_cell.clone(), fixture.poa_setup_cell.clone());
+    assert_eq!(
+        fixture.approve(builder, &[0, 1]).build().err(),
+        Some(Error::NoProposal)
+    );
+}
+
+// Rebuilds a serialized v1 setup with the given fields left empty, optional
+// fields left empty parse the same as their default values.
+fn omit_setup_fields(data: &[u8], omitted: &[usize]) -> Bytes {
+    let table = &data[1..];
+    let number =
+        |offset: usize| u32::from_le_bytes(table[offset..offset + 4].try_into().unwrap()) as usize;
+    let header_size = number(4);
+    let field_count = header_size / 4 - 1;
+    let fields: Vec<&[u8]> = (0..field_count)
+        .map(|i| {
+            let start = number(4 * (i + 1));
+            let end = if i + 1 < field_count {
+                number(4 * (i + 2))
+            } else {
+                table.len()
+            };
+            if omitted.contains(&i) {
+                &table[start..start]
+            } else {
+                &table[start..end]
+            }
+        })
+        .collect();
+    let mut buffer = BytesMut::new();
+    buffer.extend_from_slice(&data[0..1]);
+    let total_size = header_size + fields.iter().map(|field| field.len()).sum::<usize>();
+    buffer.extend_from_slice(&(total_size as u32).to_le_bytes());
+    let mut offset = header_size;
+    for field in &fields {
+        buffer.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in &fields {
+        buffer.extend_from_slice(field);
+    }
+    buffer.freeze()
+}
+
+#[test]
+fn test_setup_update_time_lock_omitted_fields() {
+    let mut fixture = governance_fixture(3, 2);
+    let time_locked_setup = PoASetup {
+        version: POA_SETUP_V1,
+        proposal_delay: 3600,
+        ..fixture.setup.clone()
+    };
+    // paused and weights are omitted from the time-locked setup
+    let setup_data = omit_setup_fields(
+        &serialize_poa_setup(&time_locked_setup).expect("serialize poa setup"),
+        &[7, 8],
+    );
+    assert_eq!(parse_poa_setup(&setup_data), Ok(time_locked_setup.clone()));
+    let tx = fixture
+        .setup_update_builder(time_locked_setup, &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(replace_output_data(&tx, 1, setup_data.clone()));
+    apply(&mut fixture, &tx);
+
+    // Proposing keeps omitted fields, re-serializing them is another change
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    let builder = SetupUpdateTxBuilder::propose(
+        fixture.poa_cell.clone(),
+        fixture.poa_setup_cell.clone(),
+        new_setup.clone(),
+        5000,
+    );
+    let tx = fixture.approve(builder, &[0, 1]).build().expect("build tx");
+    let proposed_setup = PoASetup {
+        proposal: Some(SetupProposal::new(5000, &new_setup).expect("proposal")),
+        ..fixture.setup.clone()
+    };
+    assert_eq!(
+        parse_poa_setup(&tx.outputs_data().get(1).unwrap().raw_data()),
+        Ok(proposed_setup.clone())
+    );
+    let serialized = serialize_poa_setup(&proposed_setup).expect("serialize poa setup");
+    assert_eq!(
+        validate_setup_proposal(&setup_data, &serialized),
+        Err(Error::InvalidProposal)
+    );
+    assert_eq!(
+        fixture.verify_error(replace_output_data(&tx, 1, serialized)),
+        Some(PoaError::InvalidProposal)
+    );
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+
+    // Cancelling restores the setup byte for byte
+    let builder =
+        SetupUpdateTxBuilder::cancel(fixture.poa_cell.clone(), fixture.poa_setup_cell.clone());
+    let tx = fixture.approve(builder, &[1, 2]).build().expect("build tx");
+    assert_eq!(tx.outputs_data().get(1).unwrap().raw_data(), setup_data);
+    let serialized = serialize_poa_setup(&PoASetup {
+        proposal: None,
+        ..fixture.setup.clone()
+    })
+    .expect("serialize poa setup");
+    assert_eq!(
+        validate_setup_proposal(&fixture.poa_setup_cell.data, &serialized),
+        Err(Error::InvalidProposal)
+    );
+    assert_eq!(
+        fixture.verify_error(replace_output_data(&tx, 1, serialized)),
+        Some(PoaError::InvalidProposal)
+    );
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_setup_update_time_lock_pause() {
+    let mut fixture = governance_fixture(3, 2);
+    let time_locked_setup = PoASetup {
+        version: POA_SETUP_V1,
+        proposal_delay: 3600,
+        ..fixture.setup.clone()
+    };
+    let tx = fixture
+        .setup_update_builder(time_locked_setup, &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+
+    // Pausing is applied directly, without waiting for the delay
+    let paused_setup = PoASetup {
+        paused: true,
+        ..fixture.setup.clone()
+    };
+    let tx = fixture
+        .setup_update_builder(paused_setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+    assert_eq!(fixture.setup, paused_setup);
+
+    // Other changes still need a proposal, even together with resuming
+    let new_setup = PoASetup {
+        paused: false,
+        round_intervals: 47,
+        ..fixture.setup.clone()
+    };
+    let serialized = serialize_poa_setup(&new_setup).expect("serialize poa setup");
+    assert_eq!(
+        validate_setup_proposal(&fixture.poa_setup_cell.data, &serialized),
+        Err(Error::ProposalRequired)
+    );
+    let tx = fixture
+        .setup_update_builder(paused_setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    assert_eq!(
+        fixture.verify_error(replace_output_data(&tx, 1, serialized)),
+        Some(PoaError::ProposalRequired)
+    );
+
+    // Resuming is applied directly as well
+    let resumed_setup = PoASetup {
+        paused: false,
+        ..fixture.setup.clone()
+    };
+    let tx = fixture
+        .setup_update_builder(resumed_setup.clone(), &[0, 1])
+        .build()
+        .expect("build tx");
+    let tx = fixture.verify(tx);
+    apply(&mut fixture, &tx);
+    assert_eq!(fixture.setup, resumed_setup);
+}
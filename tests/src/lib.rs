@@ -1,33 +1,75 @@
 #[macro_use]
 extern crate lazy_static;
 
+use blake2b_ref::Blake2bBuilder;
 use ckb_standalone_debugger::transaction::{
     MockCellDep, MockInfo, MockInput, MockTransaction, ReprMockTransaction,
 };
-use ckb_testtool::context::Context;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
+use ckb_tool::ckb_crypto::secp::{Generator, Privkey};
 use ckb_tool::ckb_types::{
     bytes::Bytes,
-    core::{DepType, TransactionView},
+    core::{Capacity, DepType, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, OutPoint, OutPointVec, Script},
+    prelude::*,
 };
 use ckb_x64_simulator::RunningSetup;
-use rand::{thread_rng, Rng};
+use clerkb::{
+    pubkey_hash, serialize_poa_data, serialize_poa_setup, CellInfo, GenesisTxBuilder, PoAData,
+    PoASetup, PoaError, SetupUpdateTxBuilder, SignatureLibrary, SubblockTxBuilder,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde_json::to_string_pretty;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::env;
+use std::fmt;
 use std::fs;
 use std::io::Write;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+#[cfg(test)]
+mod codec_tests;
+#[cfg(test)]
+mod cycle_tests;
+#[cfg(test)]
+mod genesis_tests;
+#[cfg(test)]
+mod governance_tests;
 // Hash tests here shall be enabled on demand.
 // #[cfg(test)]
 // mod hash_tests;
 #[cfg(test)]
+mod poa_data_tests;
+#[cfg(test)]
+mod poa_model_tests;
+#[cfg(test)]
 mod poa_tests;
 #[cfg(test)]
+mod scenario;
+#[cfg(test)]
+mod scenario_tests;
+#[cfg(test)]
+mod scheduler_tests;
+#[cfg(all(test, feature = "signature-library"))]
+mod signature_tests;
+#[cfg(test)]
 mod state_tests;
+#[cfg(test)]
+mod subblock_tests;
 
 lazy_static! {
     static ref LOADER: Loader = Loader::default();
+    static ref TEST_SEED: u64 = {
+        let seed = match env::var(SEED_ENV_VAR) {
+            Ok(val) => val.parse().expect("test seed"),
+            Err(_) => DEFAULT_SEED,
+        };
+        install_seed_panic_hook(seed);
+        seed
+    };
     static ref TX_FOLDER: PathBuf = {
         let path = LOADER.path("dumped_tests");
         if Path::new(&path).exists() {
@@ -39,6 +81,16 @@ lazy_static! {
 }
 
 const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
+// Seed of all randomness used by test fixtures, so a failing run, including
+// the transactions it dumps, can be replayed exactly.
+const SEED_ENV_VAR: &str = "CLERKB_TEST_SEED";
+const DEFAULT_SEED: u64 = 0x636c_6572_6b62;
+
+thread_local! {
+    // Tests run in their own threads, named after the tests, so each test
+    // draws from its own stream regardless of scheduling.
+    static TEST_RNG: RefCell<Option<StdRng>> = RefCell::new(None);
+}
 
 pub enum TestEnv {
     Debug,
@@ -95,13 +147,629 @@ impl Loader {
     }
 }
 
+// Reports the seed of failing tests, along with how to replay them.
+fn install_seed_panic_hook(seed: u64) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let seeded = TEST_RNG
+            .try_with(|rng| rng.borrow().is_some())
+            .unwrap_or(false);
+        if seeded {
+            eprintln!(
+                "test randomness was seeded with {}, rerun with {}={} to replay",
+                seed, SEED_ENV_VAR, seed
+            );
+        }
+    }));
+}
+
+fn thread_seed(seed: u64) -> [u8; 32] {
+    let thread = std::thread::current();
+    let mut blake2b = Blake2bBuilder::new(32).build();
+    blake2b.update(&seed.to_le_bytes());
+    blake2b.update(thread.name().unwrap_or("").as_bytes());
+    let mut thread_seed = [0u8; 32];
+    blake2b.finalize(&mut thread_seed);
+    thread_seed
+}
+
+/// Seed of the randomness used by test fixtures, taken from
+/// `CLERKB_TEST_SEED` when set.
+pub fn test_seed() -> u64 {
+    *TEST_SEED
+}
+
+/// Runs `f` with the random number generator of the current test, seeded
+/// from `test_seed` and the test name.
+pub fn with_test_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    TEST_RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let rng = rng.get_or_insert_with(|| StdRng::from_seed(thread_seed(test_seed())));
+        f(rng)
+    })
+}
+
 pub fn random_32bytes() -> Bytes {
-    let mut rng = thread_rng();
     let mut buf = vec![0u8; 32];
-    rng.fill(&mut buf[..]);
+    with_test_rng(|rng| rng.fill(&mut buf[..]));
     Bytes::from(buf)
 }
 
+pub fn random_out_point() -> OutPoint {
+    OutPoint::new_builder()
+        .tx_hash(random_32bytes().pack())
+        .index(0u32.pack())
+        .build()
+}
+
+/// Like `Context::create_cell`, but at an out point drawn from the test RNG.
+pub fn create_cell(context: &mut Context, output: CellOutput, data: Bytes) -> OutPoint {
+    let out_point = random_out_point();
+    context.create_cell_with_out_point(out_point.clone(), output, data);
+    out_point
+}
+
+/// Like `Context::deploy_cell`, but at an out point drawn from the test RNG.
+pub fn deploy_cell(context: &mut Context, data: Bytes) -> OutPoint {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::bytes(data.len()).expect("script capacity").pack())
+        .build();
+    create_cell(context, output, data)
+}
+
+/// Deploys a dep group cell referencing `out_points`, to be used in cell deps
+/// with `DepType::DepGroup`.
+pub fn deploy_dep_group(context: &mut Context, out_points: Vec<OutPoint>) -> OutPoint {
+    let data = OutPointVec::new_builder()
+        .set(out_points)
+        .build()
+        .as_bytes();
+    deploy_cell(context, data)
+}
+
+/// Extracts the exit code of the failing script from a verification error.
+pub fn script_error_code<E: fmt::Debug>(error: E) -> Option<i8> {
+    let message = format!("{:?}", error);
+    let start = message.find("ValidationFailure")? + "ValidationFailure".len();
+    let code: String = message[start..]
+        .chars()
+        .skip_while(|c| *c != '-' && !c.is_ascii_digit())
+        .take_while(|c| *c == '-' || c.is_ascii_digit())
+        .collect();
+    code.parse().ok()
+}
+
+pub fn create_cell_info(context: &mut Context, output: CellOutput, data: Bytes) -> CellInfo {
+    let out_point = create_cell(context, output.clone(), data.clone());
+    CellInfo::new(out_point, output, data)
+}
+
+/// PoA data with no missed rounds recorded.
+pub fn poa_data(
+    round_initial_subtime: u64,
+    subblock_subtime: u64,
+    subblock_index: u32,
+    aggregator_index: u16,
+) -> PoAData {
+    PoAData {
+        round_initial_subtime,
+        subblock_subtime,
+        subblock_index,
+        aggregator_index,
+        missed_rounds: Vec::new(),
+    }
+}
+
+/// A PoA deployment to run transactions against: aggregators own always
+/// success locks, identified by their lock hashes, and the PoA cell, PoA
+/// setup cell and PoA data cell are live outputs of a genesis transaction
+/// assembled by `GenesisTxBuilder`.
+pub struct PoAFixture {
+    pub context: Context,
+    pub setup: PoASetup,
+    pub owner_scripts: Vec<Script>,
+    // Lock of cells not owned by any aggregator. Its code also stands in for
+    // the state lock of the PoA setup cell and PoA data cell, unless the
+    // fixture is built with `dep_group`.
+    pub simple_lock_script: Script,
+    pub poa_cell: CellInfo,
+    pub poa_setup_cell: CellInfo,
+    pub poa_data_cell: CellInfo,
+    pub cell_deps: Vec<CellDep>,
+    // Private keys of aggregators identified by public key hashes, empty
+    // unless the fixture is built with `signatures`.
+    pub keys: Vec<Privkey>,
+    max_cycles: u64,
+}
+
+impl PoAFixture {
+    pub fn builder(aggregators: usize) -> PoAFixtureBuilder {
+        PoAFixtureBuilder::new(aggregators)
+    }
+
+    pub fn cell(&mut self, output: CellOutput, data: Bytes) -> CellInfo {
+        create_cell_info(&mut self.context, output, data)
+    }
+
+    /// Empty cell locked by the owner lock of aggregator `owner`.
+    pub fn owner_cell(&mut self, owner: usize) -> CellInfo {
+        let output = CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(self.owner_scripts[owner].clone())
+            .build();
+        self.cell(output, Bytes::new())
+    }
+
+    /// Cell of the simple lock, which no aggregator owns.
+    pub fn padding_cell(&mut self) -> CellInfo {
+        let output = CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(self.simple_lock_script.clone())
+            .build();
+        self.cell(output, Bytes::new())
+    }
+
+    /// Another PoA data cell, with the same lock and type script as the live
+    /// one but holding `data`.
+    pub fn poa_data_cell_with(&mut self, data: Bytes) -> CellInfo {
+        let output = self.poa_data_cell.output.clone();
+        self.cell(output, data)
+    }
+
+    /// Replaces the live PoA data cell with another one holding `data`.
+    pub fn set_poa_data(&mut self, data: &PoAData) {
+        self.poa_data_cell = self.poa_data_cell_with(serialize_poa_data(data));
+    }
+
+    pub fn setup_data(&self) -> Bytes {
+        serialize_poa_setup(&self.setup).expect("serialize poa setup")
+    }
+
+    /// Replaces the live PoA setup cell with another one holding `setup`.
+    pub fn set_setup(&mut self, setup: PoASetup) {
+        self.setup = setup;
+        self.poa_setup_cell = self.cell(self.poa_setup_cell.output.clone(), self.setup_data());
+    }
+
+    /// Subblock builder for aggregator `aggregator_index` on the live PoA
+    /// cells, with an owner cell of the aggregator as its first input and
+    /// code deps filled in.
+    pub fn subblock_builder(&mut self, aggregator_index: usize) -> SubblockTxBuilder {
+        let builder = if self.keys.is_empty() {
+            let owner_cell = self.owner_cell(aggregator_index);
+            SubblockTxBuilder::new(
+                self.poa_cell.clone(),
+                self.poa_data_cell.clone(),
+                self.poa_setup_cell.clone(),
+                self.owner_scripts[aggregator_index].clone(),
+            )
+            .input(owner_cell)
+        } else {
+            SubblockTxBuilder::with_identity(
+                self.poa_cell.clone(),
+                self.poa_data_cell.clone(),
+                self.poa_setup_cell.clone(),
+                self.setup.identities[aggregator_index].clone(),
+            )
+        };
+        self.cell_deps.iter().fold(builder, |builder, cell_dep| {
+            builder.cell_dep(cell_dep.clone())
+        })
+    }
+
+    /// Subblock transaction of aggregator `aggregator_index` with code deps
+    /// completed. Signatures cover the transaction hash, so it is signed with
+    /// `sign_poa_tx` afterwards.
+    pub fn unsigned_subblock_tx(
+        &mut self,
+        aggregator_index: usize,
+        subtime: u64,
+    ) -> TransactionView {
+        let tx = self
+            .subblock_builder(aggregator_index)
+            .build(subtime)
+            .expect("build tx");
+        self.context.complete_tx(tx)
+    }
+
+    /// Adds owner cells of `approvers` as approvals to `builder`, or registers
+    /// them as signers when aggregators sign, and fills in code deps.
+    pub fn approve(
+        &mut self,
+        builder: SetupUpdateTxBuilder,
+        approvers: &[usize],
+    ) -> SetupUpdateTxBuilder {
+        let builder = approvers.iter().fold(builder, |builder, aggregator_index| {
+            if self.keys.is_empty() {
+                builder.approval(self.owner_cell(*aggregator_index))
+            } else {
+                builder.signer(self.setup.identities[*aggregator_index].clone())
+            }
+        });
+        self.cell_deps.iter().fold(builder, |builder, cell_dep| {
+            builder.cell_dep(cell_dep.clone())
+        })
+    }
+
+    /// Builder updating the live PoA setup cell to `new_setup`, approved by
+    /// `approvers`.
+    pub fn setup_update_builder(
+        &mut self,
+        new_setup: PoASetup,
+        approvers: &[usize],
+    ) -> SetupUpdateTxBuilder {
+        let builder = SetupUpdateTxBuilder::new(
+            self.poa_cell.clone(),
+            self.poa_setup_cell.clone(),
+            new_setup,
+        );
+        self.approve(builder, approvers)
+    }
+
+    /// Subblock transaction assembled by hand from fresh copies of the PoA
+    /// cells, with the PoA setup cell holding `setup_data` as a cell dep and
+    /// an owner cell of aggregator `owner` as input. Unlike
+    /// `SubblockTxBuilder`, nothing is checked, so the PoA lock gets to judge
+    /// invalid setups, data and since values.
+    pub fn subblock_tx(
+        &mut self,
+        setup_data: Bytes,
+        last_data: &PoAData,
+        next_data: &PoAData,
+        since: u64,
+        owner: usize,
+    ) -> TransactionView {
+        let setup_cell = self.cell(self.poa_setup_cell.output.clone(), setup_data);
+        let poa_cell = self.cell(self.poa_cell.output.clone(), self.poa_cell.data.clone());
+        let poa_data_cell = self.poa_data_cell_with(serialize_poa_data(last_data));
+        let owner_cell = self.owner_cell(owner);
+        TransactionBuilder::default()
+            .input(poa_cell.input(since))
+            .input(poa_data_cell.input(0))
+            .input(owner_cell.input(0))
+            .output(poa_cell.output)
+            .output_data(Bytes::from_static(b"new").pack())
+            .output(poa_data_cell.output)
+            .output_data(serialize_poa_data(next_data).pack())
+            .cell_dep(setup_cell.code_dep())
+            .cell_deps(self.cell_deps.clone())
+            .build()
+    }
+
+    /// Setup update transaction assembled by hand from fresh copies of the
+    /// PoA cell and PoA setup cell, approved by the owner cells of
+    /// `approvers`.
+    pub fn setup_update_tx(
+        &mut self,
+        new_setup_data: Bytes,
+        approvers: &[usize],
+    ) -> TransactionView {
+        let poa_cell = self.cell(self.poa_cell.output.clone(), self.poa_cell.data.clone());
+        let setup_cell = self.cell(self.poa_setup_cell.output.clone(), self.setup_data());
+        let approval_inputs: Vec<CellInput> = approvers
+            .iter()
+            .map(|aggregator_index| self.owner_cell(*aggregator_index).input(0))
+            .collect();
+        TransactionBuilder::default()
+            .input(poa_cell.input(0))
+            .input(setup_cell.input(0))
+            .inputs(approval_inputs)
+            .output(poa_cell.output)
+            .output_data(Bytes::from_static(b"new").pack())
+            .output(setup_cell.output)
+            .output_data(new_setup_data.pack())
+            .cell_deps(self.cell_deps.clone())
+            .build()
+    }
+
+    /// Makes an output of a verified transaction available as a live cell.
+    pub fn commit_output(&mut self, tx: &TransactionView, index: usize) -> CellInfo {
+        let cell = CellInfo::new(
+            OutPoint::new(tx.hash(), index as u32),
+            tx.outputs().get(index).expect("output"),
+            tx.outputs_data()
+                .get(index)
+                .expect("output data")
+                .raw_data(),
+        );
+        self.context.create_cell_with_out_point(
+            cell.out_point.clone(),
+            cell.output.clone(),
+            cell.data.clone(),
+        );
+        cell
+    }
+
+    pub fn verify(&mut self, tx: TransactionView) -> TransactionView {
+        let tx = self.context.complete_tx(tx);
+        let cycles = self
+            .context
+            .verify_tx(&tx, self.max_cycles)
+            .expect("pass verification");
+        println!("consume cycles: {}", cycles);
+        tx
+    }
+
+    /// Exit code of the script failing `tx`.
+    pub fn verify_error_code(&mut self, tx: TransactionView) -> Option<i8> {
+        let tx = self.context.complete_tx(tx);
+        let err = self
+            .context
+            .verify_tx(&tx, self.max_cycles)
+            .expect_err("fail verification");
+        script_error_code(err)
+    }
+
+    pub fn verify_error(&mut self, tx: TransactionView) -> Option<PoaError> {
+        self.verify_error_code(tx).and_then(PoaError::from_code)
+    }
+}
+
+/// Builds a `PoAFixture`. Unless specified otherwise, rounds last 90 seconds
+/// and hold 1 subblock, any single aggregator can update the setup, and the
+/// first round starts at subtime 1000.
+pub struct PoAFixtureBuilder {
+    aggregators: usize,
+    setup: PoASetup,
+    poa_data: Option<PoAData>,
+    minimum_change_threshold: Option<u8>,
+    signatures: bool,
+    poa_data_type: bool,
+    stub_poa_lock: bool,
+    dep_group: bool,
+    max_cycles: u64,
+}
+
+impl PoAFixtureBuilder {
+    pub fn new(aggregators: usize) -> Self {
+        PoAFixtureBuilder {
+            aggregators,
+            setup: PoASetup {
+                round_interval_uses_seconds: true,
+                aggregator_change_threshold: 1,
+                round_intervals: 90,
+                subblocks_per_round: 1,
+                ..Default::default()
+            },
+            poa_data: None,
+            minimum_change_threshold: None,
+            signatures: false,
+            poa_data_type: false,
+            stub_poa_lock: false,
+            dep_group: false,
+            max_cycles: 70_000_000,
+        }
+    }
+
+    /// Adjusts the setup via `f`. Identities left empty are filled in with
+    /// owner lock hashes, truncated to `identity_size`.
+    pub fn setup<F: FnOnce(&mut PoASetup)>(mut self, f: F) -> Self {
+        f(&mut self.setup);
+        self
+    }
+
+    /// Size of identities, which are prefixes of owner lock hashes.
+    pub fn identity_size(mut self, identity_size: u8) -> Self {
+        self.setup.identity_size = identity_size;
+        self
+    }
+
+    pub fn aggregator_change_threshold(mut self, threshold: u8) -> Self {
+        self.setup.aggregator_change_threshold = threshold;
+        self
+    }
+
+    pub fn round_interval_uses_seconds(mut self, round_interval_uses_seconds: bool) -> Self {
+        self.setup.round_interval_uses_seconds = round_interval_uses_seconds;
+        self
+    }
+
+    pub fn round_intervals(mut self, round_intervals: u32) -> Self {
+        self.setup.round_intervals = round_intervals;
+        self
+    }
+
+    pub fn subblocks_per_round(mut self, subblocks_per_round: u32) -> Self {
+        self.setup.subblocks_per_round = subblocks_per_round;
+        self
+    }
+
+    pub fn poa_data(mut self, poa_data: PoAData) -> Self {
+        self.poa_data = Some(poa_data);
+        self
+    }
+
+    pub fn minimum_change_threshold(mut self, threshold: u8) -> Self {
+        self.minimum_change_threshold = Some(threshold);
+        self
+    }
+
+    /// Identifies aggregators by secp256k1 public key hashes, checked via the
+    /// signature library instead of owner cells.
+    pub fn signatures(mut self) -> Self {
+        self.signatures = true;
+        self
+    }
+
+    /// Guards the PoA data cell with the PoA data type script instead of a
+    /// plain type ID script.
+    pub fn poa_data_type(mut self) -> Self {
+        self.poa_data_type = true;
+        self
+    }
+
+    /// Runs always success in place of the PoA lock, for tests of the state
+    /// cell type scripts alone.
+    pub fn stub_poa_lock(mut self) -> Self {
+        self.stub_poa_lock = true;
+        self
+    }
+
+    /// Locks the PoA setup cell and PoA data cell with the state lock, and
+    /// like production deployments, references the PoA lock, state lock and
+    /// always success binaries via a single dep group. `complete_tx` would
+    /// add the binaries again as code deps, so transactions are verified with
+    /// `context.verify_tx` directly.
+    pub fn dep_group(mut self) -> Self {
+        self.dep_group = true;
+        self
+    }
+
+    pub fn max_cycles(mut self, max_cycles: u64) -> Self {
+        self.max_cycles = max_cycles;
+        self
+    }
+
+    pub fn build(self) -> PoAFixture {
+        let mut context = Context::default();
+        let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
+        let mut cell_deps = vec![CellDep::new_builder()
+            .out_point(always_success_out_point.clone())
+            .build()];
+        let poa_lock_out_point = if self.stub_poa_lock {
+            always_success_out_point.clone()
+        } else {
+            let poa_out_point = deploy_cell(&mut context, LOADER.load_binary("poa.strip"));
+            cell_deps.push(
+                CellDep::new_builder()
+                    .out_point(poa_out_point.clone())
+                    .build(),
+            );
+            poa_out_point
+        };
+
+        // Identities are prefixes of owner lock hashes, which must not
+        // collide even when they are only 1 byte long.
+        let identity_size = self.setup.identity_size as usize;
+        let mut identities = HashSet::new();
+        let mut owner_scripts = Vec::new();
+        while owner_scripts.len() < self.aggregators {
+            let script = context
+                .build_script(&always_success_out_point, random_32bytes())
+                .expect("build script");
+            if identities.insert(script.calc_script_hash().as_bytes().slice(0..identity_size)) {
+                owner_scripts.push(script);
+            }
+        }
+        let simple_lock_script = context
+            .build_script(&always_success_out_point, random_32bytes())
+            .expect("build script");
+        let state_lock_script = if self.dep_group {
+            let state_out_point = deploy_cell(&mut context, LOADER.load_binary("state.strip"));
+            let dep_group_out_point = deploy_dep_group(
+                &mut context,
+                vec![
+                    poa_lock_out_point.clone(),
+                    state_out_point.clone(),
+                    always_success_out_point.clone(),
+                ],
+            );
+            cell_deps = vec![CellDep::new_builder()
+                .out_point(dep_group_out_point)
+                .dep_type(DepType::DepGroup.into())
+                .build()];
+            context
+                .build_script(&state_out_point, Bytes::new())
+                .expect("build script")
+        } else {
+            simple_lock_script.clone()
+        };
+        let mut setup = self.setup;
+        let mut keys = Vec::new();
+        if self.signatures {
+            let library_bin = LOADER.load_binary("secp256k1_blake2b_sighash_all_dual");
+            let mut code_hash = [0u8; 32];
+            code_hash.copy_from_slice(CellOutput::calc_data_hash(&library_bin).as_slice());
+            let library_out_point = deploy_cell(&mut context, library_bin);
+            cell_deps.push(CellDep::new_builder().out_point(library_out_point).build());
+            keys = (0..self.aggregators)
+                .map(|_| Generator::random_privkey())
+                .collect();
+            setup.identity_size = 20;
+            setup.identities = keys
+                .iter()
+                .map(|key| pubkey_hash(&key.pubkey().expect("pubkey")))
+                .collect();
+            setup.signature_library = Some(SignatureLibrary {
+                code_hash,
+                hash_type: ScriptHashType::Data as u8,
+            });
+        } else if setup.identities.is_empty() {
+            setup.identities = owner_scripts
+                .iter()
+                .map(|script| script.calc_script_hash().as_bytes().slice(0..identity_size))
+                .collect();
+        }
+
+        let funding_cell = create_cell_info(
+            &mut context,
+            CellOutput::new_builder()
+                .capacity(100_000_000_000u64.pack())
+                .lock(simple_lock_script.clone())
+                .build(),
+            Bytes::new(),
+        );
+        let poa_lock_script = context
+            .build_script(&poa_lock_out_point, Bytes::new())
+            .expect("build script");
+        let mut builder = GenesisTxBuilder::new(
+            funding_cell,
+            setup.clone(),
+            poa_lock_script,
+            state_lock_script,
+        )
+        .initial_subtime(1000)
+        .poa_cell_data(Bytes::from_static(b"old"));
+        if let Some(poa_data) = self.poa_data {
+            builder = builder.poa_data(poa_data);
+        }
+        if let Some(threshold) = self.minimum_change_threshold {
+            builder = builder.minimum_change_threshold(threshold);
+        }
+        if self.poa_data_type {
+            let poa_data_out_point =
+                deploy_cell(&mut context, LOADER.load_binary("poa_data.strip"));
+            cell_deps.push(
+                CellDep::new_builder()
+                    .out_point(poa_data_out_point.clone())
+                    .build(),
+            );
+            builder = builder.poa_data_type(
+                context
+                    .build_script(&poa_data_out_point, Bytes::new())
+                    .expect("build script"),
+            );
+        }
+        // The genesis transaction itself is covered by genesis tests, here
+        // its outputs simply become live cells.
+        let genesis = builder.build().expect("build genesis");
+        let poa_cell = genesis.poa_cell();
+        let poa_setup_cell = genesis.poa_setup_cell();
+        let poa_data_cell = genesis.poa_data_cell();
+        for cell in &[&poa_cell, &poa_setup_cell, &poa_data_cell] {
+            context.create_cell_with_out_point(
+                cell.out_point.clone(),
+                cell.output.clone(),
+                cell.data.clone(),
+            );
+        }
+
+        PoAFixture {
+            context,
+            setup,
+            owner_scripts,
+            simple_lock_script,
+            poa_cell,
+            poa_setup_cell,
+            poa_data_cell,
+            cell_deps,
+            keys,
+            max_cycles: self.max_cycles,
+        }
+    }
+}
+
 pub fn create_test_folder(name: &str) -> PathBuf {
     let mut path = TX_FOLDER.clone();
     path.push(&name);
@@ -109,6 +777,16 @@ pub fn create_test_folder(name: &str) -> PathBuf {
     path
 }
 
+fn build_mock_cell_dep(context: &Context, cell_dep: CellDep) -> MockCellDep {
+    let (output, data) = context.get_cell(&cell_dep.out_point()).expect("get cell");
+    MockCellDep {
+        cell_dep,
+        output,
+        data,
+        header: None,
+    }
+}
+
 pub fn build_mock_transaction(tx: &TransactionView, context: &Context) -> MockTransaction {
     let mock_inputs = tx
         .inputs()
@@ -125,20 +803,30 @@ pub fn build_mock_transaction(tx: &TransactionView, context: &Context) -> MockTr
             }
         })
         .collect();
+    // A dep group is followed by its member cells, where the debugger and
+    // the simulators look them up when resolving the transaction.
     let mock_cell_deps = tx
         .cell_deps()
         .into_iter()
-        .map(|cell_dep| {
-            if cell_dep.dep_type() == DepType::DepGroup.into() {
-                panic!("Implement dep group support later!");
-            }
-            let (output, data) = context.get_cell(&cell_dep.out_point()).expect("get cell");
-            MockCellDep {
-                cell_dep,
-                output,
-                data,
-                header: None,
-            }
+        .flat_map(|cell_dep| {
+            let mock_cell_dep = build_mock_cell_dep(context, cell_dep.clone());
+            let members: Vec<OutPoint> = if cell_dep.dep_type() == DepType::DepGroup.into() {
+                OutPointVec::from_slice(&mock_cell_dep.data)
+                    .expect("parse dep group data")
+                    .into_iter()
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            iter::once(mock_cell_dep).chain(members.into_iter().map(move |out_point| {
+                build_mock_cell_dep(
+                    context,
+                    CellDep::new_builder()
+                        .out_point(out_point)
+                        .dep_type(DepType::Code.into())
+                        .build(),
+                )
+            }))
         })
         .collect();
     let mock_info = MockInfo {
@@ -180,6 +868,14 @@ pub fn write_native_setup(
     fs::write(folder.join("setup.json"), setup_json).expect("write setup to local file");
 
     let mut cmd_file = fs::File::create(folder.join("cmd")).expect("create cmd file");
+    write!(
+        &mut cmd_file,
+        "# Dumped by {} with {}={}\n",
+        std::thread::current().name().unwrap_or(test_name),
+        SEED_ENV_VAR,
+        test_seed()
+    )
+    .expect("write");
     write!(
         &mut cmd_file,
         "CKB_TX_FILE=\"{}\" CKB_RUNNING_SETUP=\"{}\" \"{}\" 2> err\n",
@@ -0,0 +1,675 @@
+use super::*;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    poa_data_type_args, serialize_poa_data, type_id_args, GenesisTxBuilder, PoAData, PoASetup,
+    SubblockTxBuilder,
+};
+use std::collections::HashMap;
+
+const MAX_CYCLES: u64 = 10_000_000;
+// Error codes returned by `c/poa_data.c`.
+const ERROR_TRANSACTION: i8 = -1;
+const ERROR_INVALID_ARGS: i8 = -3;
+const ERROR_INVALID_TYPE_ID: i8 = -4;
+const ERROR_INVALID_POA_DATA: i8 = -5;
+const ERROR_SUBTIME_DECREASED: i8 = -6;
+const ERROR_INVALID_ROUND: i8 = -7;
+const ERROR_INVALID_MISSED_ROUNDS: i8 = -8;
+const ERROR_POA_CELL_MISSING: i8 = -9;
+
+// PoA data cell guarded by the PoA data type script, next to a PoA cell whose
+// lock always succeeds, so only the type script judges the transitions.
+fn poa_data_fixture() -> PoAFixture {
+    PoAFixture::builder(1)
+        .poa_data_type()
+        .stub_poa_lock()
+        .build()
+}
+
+fn rewrite_tx(
+    fixture: &mut PoAFixture,
+    input_data: &PoAData,
+    output_data: Bytes,
+) -> TransactionView {
+    let cell = fixture.poa_data_cell_with(serialize_poa_data(input_data));
+    let tx = TransactionBuilder::default()
+        .input(cell.input(0))
+        .output(cell.output.clone())
+        .output_data(output_data.pack())
+        .cell_deps(fixture.cell_deps.clone())
+        .build();
+    fixture.context.complete_tx(tx)
+}
+
+// Rewrites the PoA data cell along with the PoA cell, as the PoA lock does
+// for every subblock.
+fn approved_tx(
+    fixture: &mut PoAFixture,
+    input_data: &PoAData,
+    output_data: Bytes,
+) -> TransactionView {
+    let tx = rewrite_tx(fixture, input_data, output_data);
+    tx.as_advanced_builder()
+        .input(fixture.poa_cell.input(0))
+        .output(fixture.poa_cell.output.clone())
+        .output_data(fixture.poa_cell.data.pack())
+        .build()
+}
+
+fn type_script_setup() -> RunningSetup {
+    RunningSetup {
+        is_lock_script: false,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    }
+}
+
+#[test]
+fn test_poa_data_genesis_and_subblock() {
+    let mut context = Context::default();
+    let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let state_bin: Bytes = Loader::default().load_binary("state.strip");
+    let state_out_point = deploy_cell(&mut context, state_bin);
+    let poa_data_bin: Bytes = Loader::default().load_binary("poa_data.strip");
+    let poa_data_out_point = deploy_cell(&mut context, poa_data_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
+    let cell_deps: Vec<CellDep> = vec![
+        &poa_out_point,
+        &state_out_point,
+        &poa_data_out_point,
+        &always_success_out_point,
+    ]
+    .into_iter()
+    .map(|out_point| CellDep::new_builder().out_point(out_point.clone()).build())
+    .collect();
+
+    let owner_scripts: Vec<Script> = (0..2)
+        .map(|_| {
+            context
+                .build_script(&always_success_out_point, random_32bytes())
+                .expect("build script")
+        })
+        .collect();
+    let setup = PoASetup {
+        round_interval_uses_seconds: true,
+        identities: owner_scripts
+            .iter()
+            .map(|script| script.calc_script_hash().as_bytes())
+            .collect(),
+        aggregator_change_threshold: 2,
+        round_intervals: 90,
+        subblocks_per_round: 2,
+        ..Default::default()
+    };
+    let funding_cell = create_cell_info(
+        &mut context,
+        CellOutput::new_builder()
+            .capacity(100_000_000_000u64.pack())
+            .lock(owner_scripts[0].clone())
+            .build(),
+        Bytes::new(),
+    );
+    let poa_lock_script = context
+        .build_script(&poa_out_point, Bytes::new())
+        .expect("build script");
+    let state_lock_script = context
+        .build_script(&state_out_point, Bytes::new())
+        .expect("build script");
+    let poa_data_type_script = context
+        .build_script(&poa_data_out_point, Bytes::new())
+        .expect("build script");
+    let genesis = cell_deps
+        .iter()
+        .fold(
+            GenesisTxBuilder::new(
+                funding_cell.clone(),
+                setup,
+                poa_lock_script.clone(),
+                state_lock_script,
+            )
+            .poa_data_type(poa_data_type_script),
+            |builder, cell_dep| builder.cell_dep(cell_dep.clone()),
+        )
+        .initial_subtime(1000)
+        .build()
+        .expect("build genesis");
+
+    let poa_cell = genesis.poa_cell();
+    let poa_setup_cell = genesis.poa_setup_cell();
+    let poa_data_cell = genesis.poa_data_cell();
+    assert_eq!(
+        genesis.poa_data_type_id_args,
+        type_id_args(&funding_cell.input(0), 2)
+    );
+    assert_eq!(
+        poa_data_cell.type_args(),
+        poa_data_type_args(&genesis.poa_data_type_id_args, &poa_lock_script)
+    );
+    assert_eq!(
+        genesis.poa_lock_args.slice(32..64),
+        poa_data_cell.type_hash()
+    );
+
+    // The PoA data type script validates type ID args on creation.
+    let tx = context.complete_tx(genesis.tx.clone());
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    for cell in &[&poa_cell, &poa_setup_cell, &poa_data_cell] {
+        context.create_cell_with_out_point(
+            cell.out_point.clone(),
+            cell.output.clone(),
+            cell.data.clone(),
+        );
+    }
+
+    // The PoA lock locates the PoA data cell via its type script hash.
+    let owner_cell = create_cell_info(
+        &mut context,
+        CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(owner_scripts[0].clone())
+            .build(),
+        Bytes::new(),
+    );
+    let tx = cell_deps
+        .iter()
+        .fold(
+            SubblockTxBuilder::new(
+                poa_cell.clone(),
+                poa_data_cell.clone(),
+                poa_setup_cell.clone(),
+                owner_scripts[0].clone(),
+            )
+            .input(owner_cell),
+            |builder, cell_dep| builder.cell_dep(cell_dep.clone()),
+        )
+        .build(1010)
+        .expect("build subblock");
+    let tx = context.complete_tx(tx);
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let setup = RunningSetup {
+        is_lock_script: false,
+        is_output: false,
+        script_index: 1,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "poa_data_subblock",
+        "poa_data_sim",
+        &tx,
+        &context,
+        &setup,
+        0,
+        true,
+    );
+
+    // Even with the PoA lock's approval, the PoA data cell cannot be
+    // rewritten with invalid data.
+    let tx = tx
+        .as_advanced_builder()
+        .set_outputs_data(vec![
+            tx.outputs_data().get(0).expect("output data"),
+            serialize_poa_data(&poa_data(1000, 990, 1, 0)).pack(),
+        ])
+        .build();
+    assert!(context.verify_tx(&tx, MAX_CYCLES).is_err());
+
+    // Rewriting the PoA data cell without the PoA cell is rejected.
+    let tx = TransactionBuilder::default()
+        .input(poa_data_cell.input(0))
+        .output(poa_data_cell.output.clone())
+        .output_data(serialize_poa_data(&poa_data(1000, 1010, 1, 0)).pack())
+        .cell_deps(cell_deps.clone())
+        .build();
+    let tx = context.complete_tx(tx);
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+}
+
+#[test]
+fn test_poa_data_valid_transitions() {
+    let mut fixture = poa_data_fixture();
+    let last = poa_data(1000, 1010, 1, 0);
+
+    // Next subblock in current round, approved by the PoA lock
+    let tx = approved_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1000, 1020, 2, 0)),
+    );
+    let tx = fixture.verify(tx);
+    write_native_setup(
+        "poa_data_next_subblock",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        0,
+        true,
+    );
+
+    // New round of another aggregator, approved by the PoA lock
+    let tx = approved_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1100, 1100, 0, 1)),
+    );
+    fixture.verify(tx);
+
+    // PoA data reset by a setup update
+    let tx = approved_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1010, 1010, 0, 0)),
+    );
+    fixture.verify(tx);
+
+    // Unchanged PoA data
+    let tx = rewrite_tx(&mut fixture, &last, serialize_poa_data(&last));
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_poa_data_invalid_length() {
+    let mut fixture = poa_data_fixture();
+    let last = poa_data(1000, 1010, 1, 0);
+    let data = serialize_poa_data(&poa_data(1000, 1020, 2, 0));
+    let tx = rewrite_tx(&mut fixture, &last, data.slice(0..21));
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_INVALID_POA_DATA)
+    );
+    write_native_setup(
+        "poa_data_invalid_length",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        ERROR_INVALID_POA_DATA,
+        true,
+    );
+
+    let mut extended = data.to_vec();
+    extended.push(0);
+    let tx = rewrite_tx(&mut fixture, &last, Bytes::from(extended));
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_POA_DATA));
+}
+
+#[test]
+fn test_poa_data_subtime_decreased() {
+    let mut fixture = poa_data_fixture();
+    let last = poa_data(1000, 1010, 1, 0);
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1000, 1005, 2, 0)),
+    );
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_SUBTIME_DECREASED)
+    );
+    write_native_setup(
+        "poa_data_subtime_decreased",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        ERROR_SUBTIME_DECREASED,
+        true,
+    );
+
+    // A new round cannot start before the last one, even with the PoA cell
+    let tracked = |data: PoAData| PoAData {
+        missed_rounds: vec![0, 2, 1],
+        ..data
+    };
+    let tx = approved_tx(
+        &mut fixture,
+        &tracked(last.clone()),
+        serialize_poa_data(&tracked(poa_data(990, 990, 0, 1))),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_SUBTIME_DECREASED));
+
+    // A reset by a setup update starts subtimes over, e.g. as block numbers
+    // after switching round intervals from seconds to blocks
+    let tx = approved_tx(
+        &mut fixture,
+        &tracked(last.clone()),
+        serialize_poa_data(&poa_data(500, 500, 0, 0)),
+    );
+    fixture.verify(tx);
+    let tx = approved_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(500, 500, 0, 1)),
+    );
+    fixture.verify(tx);
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(500, 500, 0, 1)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_POA_CELL_MISSING));
+}
+
+#[test]
+fn test_poa_data_switch_to_blocks() {
+    let mut fixture = PoAFixture::builder(2)
+        .poa_data_type()
+        .poa_data(poa_data(1000, 1010, 1, 0))
+        .build();
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_interval_uses_seconds = false;
+    // Subtimes of the reset PoA data are block numbers, far below the last
+    // timestamp
+    let tx = fixture
+        .setup_update_builder(new_setup, &[0])
+        .poa_data_cell(fixture.poa_data_cell.clone(), poa_data(500, 500, 0, 0))
+        .build()
+        .expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_poa_data_invalid_round() {
+    let mut fixture = poa_data_fixture();
+    let last = poa_data(1000, 1010, 1, 0);
+
+    // Skipping subblock index
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1000, 1020, 3, 0)),
+    );
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_INVALID_ROUND)
+    );
+    write_native_setup(
+        "poa_data_invalid_round",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        ERROR_INVALID_ROUND,
+        true,
+    );
+
+    // Another aggregator in current round
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1000, 1020, 2, 1)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_ROUND));
+
+    // Subblock in a round that never started
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1050, 1060, 1, 0)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_ROUND));
+
+    // New round not starting at its first subblock
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1100, 1110, 0, 1)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_ROUND));
+
+    // Round starting after current subblock
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1030, 1020, 2, 0)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_ROUND));
+
+    // Next subblock in current round without the PoA cell, which could
+    // otherwise push subtimes far into the future or issue more subblocks
+    // than a round allows
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1000, u64::max_value(), 2, 0)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_POA_CELL_MISSING));
+
+    // New round without the PoA cell
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1100, 1100, 0, 1)),
+    );
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_POA_CELL_MISSING)
+    );
+    write_native_setup(
+        "poa_data_poa_cell_missing",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        ERROR_POA_CELL_MISSING,
+        true,
+    );
+}
+
+#[test]
+fn test_poa_data_missed_rounds() {
+    let mut fixture = poa_data_fixture();
+    let with_missed_rounds = |data: PoAData, missed_rounds: Vec<u32>| PoAData {
+        missed_rounds,
+        ..data
+    };
+    let last = with_missed_rounds(poa_data(1000, 1010, 1, 0), vec![0, 2, 1]);
+
+    // Missed rounds are updated by the PoA lock when a new round starts
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![0, 3, 1]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    fixture.verify(tx);
+
+    // Skipped rotations are charged to all counters alike, only the PoA lock
+    // knows how many rotations have passed
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![2, 5, 3]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    fixture.verify(tx);
+    let saturated = with_missed_rounds(poa_data(1000, 1010, 1, 0), vec![0, u32::max_value(), 1]);
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![1, u32::max_value(), 2]);
+    let tx = approved_tx(&mut fixture, &saturated, serialize_poa_data(&next));
+    fixture.verify(tx);
+
+    // Missed rounds are reset by a setup update, which can also change the
+    // number of counters
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 3), vec![0, 0, 0, 0]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    fixture.verify(tx);
+    let tx = approved_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1200, 1200, 0, 0)),
+    );
+    fixture.verify(tx);
+
+    // Even with the PoA cell, missed rounds must follow the rotation
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![0, 2, 1]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(
+        fixture.verify_error_code(tx),
+        Some(ERROR_INVALID_MISSED_ROUNDS)
+    );
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![1, 4, 1]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(
+        fixture.verify_error_code(tx),
+        Some(ERROR_INVALID_MISSED_ROUNDS)
+    );
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![0, 3, 1, 0]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(
+        fixture.verify_error_code(tx),
+        Some(ERROR_INVALID_MISSED_ROUNDS)
+    );
+
+    // Jumping to an aggregator without a counter
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 5), vec![0, 3, 1]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_INVALID_ROUND));
+
+    // Without the PoA cell, a new round can neither jump aggregators, wipe
+    // missed rounds, nor change the number of counters
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 2), vec![0, 3, 1]);
+    let tx = rewrite_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_POA_CELL_MISSING));
+    let next = with_missed_rounds(poa_data(1200, 1200, 0, 1), vec![0, 0, 0]);
+    let tx = rewrite_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_POA_CELL_MISSING));
+    let tx = rewrite_tx(
+        &mut fixture,
+        &last,
+        serialize_poa_data(&poa_data(1200, 1200, 0, 1)),
+    );
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_POA_CELL_MISSING));
+
+    // Next subblock in current round keeps missed rounds
+    let next = with_missed_rounds(poa_data(1000, 1020, 2, 0), vec![0, 2, 1]);
+    let tx = approved_tx(&mut fixture, &last, serialize_poa_data(&next));
+    fixture.verify(tx);
+
+    let next = with_missed_rounds(poa_data(1000, 1020, 2, 0), vec![0, 0, 0]);
+    let tx = rewrite_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_INVALID_MISSED_ROUNDS)
+    );
+    write_native_setup(
+        "poa_data_invalid_missed_rounds",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &type_script_setup(),
+        ERROR_INVALID_MISSED_ROUNDS,
+        true,
+    );
+
+    let next = poa_data(1000, 1020, 2, 0);
+    let tx = rewrite_tx(&mut fixture, &last, serialize_poa_data(&next));
+    assert_eq!(
+        fixture.verify_error_code(tx),
+        Some(ERROR_INVALID_MISSED_ROUNDS)
+    );
+}
+
+#[test]
+fn test_poa_data_duplicate_cell() {
+    let mut fixture = poa_data_fixture();
+    let data = serialize_poa_data(&poa_data(1000, 1010, 1, 0));
+    let cell = fixture.poa_data_cell_with(data.clone());
+    let tx = TransactionBuilder::default()
+        .input(cell.input(0))
+        .output(cell.output.clone())
+        .output_data(data.pack())
+        .output(cell.output.clone())
+        .output_data(data.pack())
+        .cell_deps(fixture.cell_deps.clone())
+        .build();
+    let tx = fixture.context.complete_tx(tx);
+    assert_eq!(fixture.verify_error_code(tx), Some(ERROR_TRANSACTION));
+}
+
+#[test]
+fn test_poa_data_invalid_type_id() {
+    let mut fixture = poa_data_fixture();
+    let funding_cell = create_cell_info(
+        &mut fixture.context,
+        CellOutput::new_builder()
+            .capacity(2000u64.pack())
+            .lock(fixture.simple_lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let data = serialize_poa_data(&poa_data(1000, 1000, 0, 0));
+
+    // Type ID args in the fixture come from its genesis transaction
+    let tx = TransactionBuilder::default()
+        .input(funding_cell.input(0))
+        .output(fixture.poa_data_cell.output.clone())
+        .output_data(data.pack())
+        .cell_deps(fixture.cell_deps.clone())
+        .build();
+    let tx = fixture.context.complete_tx(tx);
+    assert_eq!(
+        fixture.verify_error_code(tx.clone()),
+        Some(ERROR_INVALID_TYPE_ID)
+    );
+    let setup = RunningSetup {
+        is_lock_script: false,
+        is_output: true,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "poa_data_invalid_type_id",
+        "poa_data_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        ERROR_INVALID_TYPE_ID,
+        true,
+    );
+
+    // Creation with calculated type ID args passes
+    let args = poa_data_type_args(
+        &type_id_args(&funding_cell.input(0), 0),
+        &fixture.poa_cell.output.lock(),
+    );
+    let with_args = |args: Bytes| {
+        let type_script = fixture
+            .poa_data_cell
+            .output
+            .type_()
+            .to_opt()
+            .expect("poa data type script")
+            .as_builder()
+            .args(args.pack())
+            .build();
+        fixture
+            .poa_data_cell
+            .output
+            .clone()
+            .as_builder()
+            .type_(ScriptOpt::new_builder().set(Some(type_script)).build())
+            .build()
+    };
+    let valid_tx = tx
+        .as_advanced_builder()
+        .set_outputs(vec![with_args(args.clone())])
+        .build();
+    let invalid_tx = tx
+        .as_advanced_builder()
+        .set_outputs(vec![with_args(args.slice(0..32))])
+        .build();
+    fixture.verify(valid_tx);
+
+    // Type ID args alone, without the PoA lock
+    assert_eq!(
+        fixture.verify_error_code(invalid_tx),
+        Some(ERROR_INVALID_ARGS)
+    );
+}
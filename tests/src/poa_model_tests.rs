@@ -0,0 +1,491 @@
+use super::*;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionBuilder, TransactionView},
+    h256,
+    packed::*,
+    prelude::*,
+    H256,
+};
+use clerkb::{
+    serialize_poa_data, serialize_poa_setup, PoAData, PoASetup, PoaError, POA_SETUP_V0,
+    POA_SETUP_V1, SINCE_TIMESTAMP_FLAG,
+};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+const MAX_CYCLES: u64 = 10_000_000;
+const MAX_AGGREGATORS: usize = 6;
+const MAX_MISSED_ROUNDS: u32 = 0xFFFF_FFFF;
+// Subtimes of generated subblocks are at most this far from the initial
+// subtime of the last round, which covers 2 full rotations of the largest
+// generated setup.
+const MAX_ELAPSED_SUBTIME: u64 = 2400;
+
+lazy_static! {
+    static ref POA_BIN: Bytes = Loader::default().load_binary("poa.strip");
+}
+
+// Parameters of the PoA setup. Identities are prefixes of owner lock hashes,
+// which are derived from aggregator indices, so a shrunk case keeps the same
+// identities.
+#[derive(Clone, Debug)]
+struct SetupParams {
+    version: u8,
+    aggregators: usize,
+    identity_size: u8,
+    round_interval_uses_seconds: bool,
+    round_intervals: u32,
+    subblocks_per_round: u32,
+    weights: Vec<u8>,
+    paused: bool,
+}
+
+#[derive(Clone, Debug)]
+struct SubblockCase {
+    setup: SetupParams,
+    last: PoAData,
+    current: PoAData,
+    since: u64,
+    // Aggregator whose owner cell is included in inputs, if any
+    owner: Option<usize>,
+}
+
+fn setup_params() -> impl Strategy<Value = SetupParams> {
+    (
+        1..=MAX_AGGREGATORS,
+        1u8..=32,
+        any::<bool>(),
+        1u32..=60,
+        1u32..=4,
+        any::<bool>(),
+        proptest::option::of(vec(1u8..=3, MAX_AGGREGATORS)),
+        prop::bool::weighted(0.05),
+    )
+        .prop_map(
+            |(
+                aggregators,
+                identity_size,
+                round_interval_uses_seconds,
+                round_intervals,
+                subblocks_per_round,
+                v1,
+                weights,
+                paused,
+            )| {
+                // Only version 1 setups can hold weights
+                let weights = match weights {
+                    Some(mut weights) if v1 => {
+                        weights.truncate(aggregators);
+                        weights
+                    }
+                    _ => Vec::new(),
+                };
+                SetupParams {
+                    version: if v1 { POA_SETUP_V1 } else { POA_SETUP_V0 },
+                    aggregators,
+                    identity_size,
+                    round_interval_uses_seconds,
+                    round_intervals,
+                    subblocks_per_round,
+                    weights,
+                    paused,
+                }
+            },
+        )
+}
+
+fn last_poa_data(aggregators: usize, track_missed_rounds: bool) -> impl Strategy<Value = PoAData> {
+    (
+        0u64..5000,
+        0u64..60,
+        0u32..8,
+        0..aggregators as u16,
+        vec(
+            prop_oneof![0u32..3, Just(MAX_MISSED_ROUNDS)],
+            if track_missed_rounds { aggregators } else { 0 },
+        ),
+    )
+        .prop_map(
+            |(round_initial_subtime, offset, subblock_index, aggregator_index, missed_rounds)| {
+                PoAData {
+                    round_initial_subtime,
+                    subblock_subtime: round_initial_subtime + offset,
+                    subblock_index,
+                    aggregator_index,
+                    missed_rounds,
+                }
+            },
+        )
+}
+
+// New PoA data is derived from the last one, and is valid for the chosen
+// subtime unless one of its fields is mutated. This keeps most generated
+// transactions close to the boundary between acceptance and rejection.
+fn subblock_case() -> impl Strategy<Value = SubblockCase> {
+    (setup_params(), any::<bool>())
+        .prop_flat_map(|(setup, track_missed_rounds)| {
+            let aggregators = setup.aggregators;
+            (Just(setup), last_poa_data(aggregators, track_missed_rounds))
+        })
+        .prop_flat_map(|(setup, last)| {
+            let aggregators = setup.aggregators;
+            let tracked = last.missed_rounds.len();
+            let aggregator_index = prop_oneof![
+                2 => Just(last.aggregator_index),
+                3 => 0..=aggregators as u16,
+            ];
+            (
+                Just(setup),
+                Just(last.clone()),
+                last.round_initial_subtime..last.round_initial_subtime + MAX_ELAPSED_SUBTIME,
+                aggregator_index,
+                prop_oneof![8 => Just(0i64), 1 => -2i64..=2],
+                (0u8..4, 0u64..6000),
+                (0u8..4, 0u32..8),
+                (0u8..4, vec(0u32..4, tracked)),
+                prop::bool::weighted(0.9),
+                (0u8..8, 0..aggregators),
+            )
+        })
+        .prop_map(
+            |(
+                setup,
+                last,
+                subtime,
+                aggregator_index,
+                subtime_delta,
+                (round_initial_choice, other_round_initial),
+                (index_choice, other_index),
+                (missed_rounds_choice, other_missed_rounds),
+                correct_since_flag,
+                (owner_choice, other_owner),
+            )| {
+                let in_round = subtime < round_end(&setup, &last);
+                let current_subtime = if subtime_delta >= 0 {
+                    subtime + subtime_delta as u64
+                } else {
+                    subtime.saturating_sub(subtime_delta.abs() as u64)
+                };
+                let (round_initial_subtime, other_branch_round_initial) = if in_round {
+                    (last.round_initial_subtime, current_subtime)
+                } else {
+                    (current_subtime, last.round_initial_subtime)
+                };
+                let (subblock_index, other_branch_index) = if in_round {
+                    (last.subblock_index + 1, 0)
+                } else {
+                    (0, last.subblock_index + 1)
+                };
+                let current = PoAData {
+                    round_initial_subtime: match round_initial_choice {
+                        0 | 1 => round_initial_subtime,
+                        2 => other_branch_round_initial,
+                        _ => other_round_initial,
+                    },
+                    subblock_subtime: current_subtime,
+                    subblock_index: match index_choice {
+                        0 | 1 => subblock_index,
+                        2 => other_branch_index,
+                        _ => other_index,
+                    },
+                    aggregator_index,
+                    missed_rounds: match missed_rounds_choice {
+                        0 | 1 if in_round => last.missed_rounds.clone(),
+                        0 | 1 => expected_missed_rounds(&setup, &last, aggregator_index, subtime),
+                        2 => last.missed_rounds.clone(),
+                        _ => other_missed_rounds,
+                    },
+                };
+                let flag = if setup.round_interval_uses_seconds == correct_since_flag {
+                    SINCE_TIMESTAMP_FLAG
+                } else {
+                    0
+                };
+                let owner = match owner_choice {
+                    0..=5 => Some((aggregator_index as usize).min(setup.aggregators - 1)),
+                    6 => Some(other_owner),
+                    _ => None,
+                };
+                SubblockCase {
+                    setup,
+                    last,
+                    current,
+                    since: flag | subtime,
+                    owner,
+                }
+            },
+        )
+}
+
+// Reference model of the subblock rules in `main` of c/poa.c. Checks are
+// done in the same order, so a rejection is expected with the same error
+// code.
+
+fn weight(setup: &SetupParams, index: u64) -> u64 {
+    if setup.weights.is_empty() {
+        return 1;
+    }
+    setup.weights[(index % setup.aggregators as u64) as usize] as u64
+}
+
+fn round_end(setup: &SetupParams, last: &PoAData) -> u64 {
+    last.round_initial_subtime
+        + weight(setup, last.aggregator_index as u64) * setup.round_intervals as u64
+}
+
+// Rounds handed off from the last aggregator to `aggregator_index`, a full
+// rotation when the same aggregator starts a new round.
+fn steps(setup: &SetupParams, last: &PoAData, aggregator_index: u16) -> u64 {
+    let aggregators = setup.aggregators as u64;
+    match (aggregator_index as u64 + aggregators - last.aggregator_index as u64) % aggregators {
+        0 => aggregators,
+        steps => steps,
+    }
+}
+
+// Rounds of the last aggregator and all skipped aggregators.
+fn handoff_duration(setup: &SetupParams, last: &PoAData, steps: u64) -> u64 {
+    (0..steps)
+        .map(|i| weight(setup, last.aggregator_index as u64 + i) * setup.round_intervals as u64)
+        .sum()
+}
+
+// Skipped aggregators miss one round, and every full rotation passing after
+// the handoff makes all aggregators miss one more.
+fn expected_missed_rounds(
+    setup: &SetupParams,
+    last: &PoAData,
+    aggregator_index: u16,
+    subtime: u64,
+) -> Vec<u32> {
+    let aggregators = setup.aggregators as u64;
+    let steps = steps(setup, last, aggregator_index);
+    let rotation: u64 = (0..aggregators)
+        .map(|i| weight(setup, i) * setup.round_intervals as u64)
+        .sum();
+    let skipped_rotations = subtime
+        .saturating_sub(last.round_initial_subtime + handoff_duration(setup, last, steps))
+        / rotation;
+    last.missed_rounds
+        .iter()
+        .enumerate()
+        .map(|(i, missed)| {
+            let offset = (i as u64 + aggregators - last.aggregator_index as u64) % aggregators;
+            let skipped = if offset != 0 && offset < steps { 1 } else { 0 };
+            (*missed as u64 + skipped_rotations + skipped).min(MAX_MISSED_ROUNDS as u64) as u32
+        })
+        .collect()
+}
+
+fn expected_result(
+    case: &SubblockCase,
+    identities: &[Bytes],
+    input_lock_hashes: &[Bytes],
+) -> Result<(), PoaError> {
+    let setup = &case.setup;
+    let (last, current) = (&case.last, &case.current);
+    if setup.paused {
+        return Err(PoaError::Paused);
+    }
+    if current.aggregator_index as usize >= setup.aggregators {
+        return Err(PoaError::InvalidAggregatorIndex);
+    }
+    let flag = if setup.round_interval_uses_seconds {
+        SINCE_TIMESTAMP_FLAG
+    } else {
+        0
+    };
+    if case.since >> 56 != flag >> 56 {
+        return Err(PoaError::InvalidSince);
+    }
+    let since = case.since & 0x00FF_FFFF_FFFF_FFFF;
+    if current.subblock_subtime != since {
+        return Err(PoaError::SubtimeMismatch);
+    }
+    if since < round_end(setup, last) {
+        let last_weight = weight(setup, last.aggregator_index as u64);
+        if current.round_initial_subtime != last.round_initial_subtime {
+            return Err(PoaError::InvalidRoundInitialSubtime);
+        }
+        if current.subblock_subtime < last.subblock_subtime {
+            return Err(PoaError::SubtimeDecreased);
+        }
+        if current.aggregator_index != last.aggregator_index {
+            return Err(PoaError::AggregatorChanged);
+        }
+        if current.subblock_index != last.subblock_index + 1
+            || current.subblock_index as u64 >= last_weight * setup.subblocks_per_round as u64
+        {
+            return Err(PoaError::InvalidSubblockIndex);
+        }
+        if current.missed_rounds != last.missed_rounds {
+            return Err(PoaError::InvalidMissedRounds);
+        }
+    } else {
+        if current.round_initial_subtime != current.subblock_subtime {
+            return Err(PoaError::InvalidRoundInitialSubtime);
+        }
+        if current.subblock_index != 0 {
+            return Err(PoaError::InvalidSubblockIndex);
+        }
+        let steps = steps(setup, last, current.aggregator_index);
+        if since < last.round_initial_subtime + handoff_duration(setup, last, steps) {
+            return Err(PoaError::RoundNotStarted);
+        }
+        if current.missed_rounds
+            != expected_missed_rounds(setup, last, current.aggregator_index, since)
+        {
+            return Err(PoaError::InvalidMissedRounds);
+        }
+    }
+    let identity = &identities[current.aggregator_index as usize];
+    if input_lock_hashes
+        .iter()
+        .any(|lock_hash| lock_hash.starts_with(identity))
+    {
+        Ok(())
+    } else {
+        Err(PoaError::OwnerNotFound)
+    }
+}
+
+fn type_id_script(args: u8) -> Script {
+    Script::new_builder()
+        .code_hash(h256!("0x545950455f4944").pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(vec![args; 32]).pack())
+        .build()
+}
+
+fn typed_output(lock: &Script, type_script: Script) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(lock.clone())
+        .type_(ScriptOpt::new_builder().set(Some(type_script)).build())
+        .build()
+}
+
+fn input(context: &mut Context, output: CellOutput, data: Bytes, since: u64) -> CellInput {
+    let out_point = create_cell(context, output, data);
+    CellInput::new_builder()
+        .previous_output(out_point)
+        .since(since.pack())
+        .build()
+}
+
+// Runs the subblock transaction of `case` through the PoA lock, returning
+// the exit code of a rejection, along with the result expected by the model.
+fn run_subblock_case(case: &SubblockCase) -> (Result<(), Option<i8>>, Result<(), Option<i8>>) {
+    let mut context = Context::default();
+    let poa_out_point = deploy_cell(&mut context, POA_BIN.clone());
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
+    let owner_scripts: Vec<Script> = (0..case.setup.aggregators)
+        .map(|i| {
+            context
+                .build_script(&always_success_out_point, Bytes::from(vec![i as u8; 32]))
+                .expect("build script")
+        })
+        .collect();
+    let simple_lock_script = context
+        .build_script(&always_success_out_point, Bytes::from(vec![0xff; 32]))
+        .expect("build script");
+    let poa_setup_type_id_script = type_id_script(1);
+    let poa_data_type_id_script = type_id_script(2);
+    let poa_lock_script = context
+        .build_script(&poa_out_point, [[1u8; 32], [2u8; 32]].concat().into())
+        .expect("build script");
+
+    let identities: Vec<Bytes> = owner_scripts
+        .iter()
+        .map(|script| {
+            script
+                .calc_script_hash()
+                .as_bytes()
+                .slice(0..case.setup.identity_size as usize)
+        })
+        .collect();
+    let setup = PoASetup {
+        version: case.setup.version,
+        identity_size: case.setup.identity_size,
+        round_interval_uses_seconds: case.setup.round_interval_uses_seconds,
+        identities: identities.clone(),
+        aggregator_change_threshold: 1,
+        round_intervals: case.setup.round_intervals,
+        subblocks_per_round: case.setup.subblocks_per_round,
+        paused: case.setup.paused,
+        weights: case.setup.weights.clone(),
+        ..Default::default()
+    };
+    let setup_out_point = create_cell(
+        &mut context,
+        typed_output(&simple_lock_script, poa_setup_type_id_script),
+        serialize_poa_setup(&setup).expect("serialize poa setup"),
+    );
+    let poa_output = CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(poa_lock_script.clone())
+        .build();
+    let poa_data_output = typed_output(&simple_lock_script, poa_data_type_id_script);
+
+    let mut input_lock_hashes = vec![
+        poa_lock_script.calc_script_hash().as_bytes(),
+        simple_lock_script.calc_script_hash().as_bytes(),
+    ];
+    let mut inputs = vec![
+        input(
+            &mut context,
+            poa_output.clone(),
+            Bytes::from_static(b"old"),
+            case.since,
+        ),
+        input(
+            &mut context,
+            poa_data_output.clone(),
+            serialize_poa_data(&case.last),
+            0,
+        ),
+    ];
+    if let Some(owner) = case.owner {
+        let owner_output = CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(owner_scripts[owner].clone())
+            .build();
+        inputs.push(input(&mut context, owner_output, Bytes::new(), 0));
+        input_lock_hashes.push(owner_scripts[owner].calc_script_hash().as_bytes());
+    }
+    let tx: TransactionView = TransactionBuilder::default()
+        .inputs(inputs)
+        .output(poa_output)
+        .output_data(Bytes::from_static(b"new").pack())
+        .output(poa_data_output)
+        .output_data(serialize_poa_data(&case.current).pack())
+        .cell_dep(CellDep::new_builder().out_point(setup_out_point).build())
+        .cell_dep(CellDep::new_builder().out_point(poa_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point)
+                .build(),
+        )
+        .build();
+    let tx = context.complete_tx(tx);
+
+    let actual = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .map(|_| ())
+        .map_err(script_error_code);
+    let expected =
+        expected_result(case, &identities, &input_lock_hashes).map_err(|error| Some(error.code()));
+    (actual, expected)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn test_poa_subblock_matches_model(case in subblock_case()) {
+        let (actual, expected) = run_subblock_case(&case);
+        prop_assert_eq!(actual, expected);
+    }
+}
@@ -9,68 +9,25 @@ use ckb_tool::ckb_types::{
     H256,
 };
 use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    serialize_poa_data, serialize_poa_setup, PoAData, PoASetup, PoaError, POA_SETUP_V0,
+    POA_SETUP_V1, POA_SETUP_VERSIONED_FLAG,
+};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 const MAX_CYCLES: u64 = 10_000_000;
-
-struct PoASetup {
-    pub identity_size: u8,
-    pub round_interval_uses_seconds: bool,
-    pub identities: Vec<Bytes>,
-    pub aggregator_change_threshold: u8,
-    pub round_intervals: u32,
-    pub subblocks_per_round: u32,
-}
-
-fn serialize_poa_setup(setup: &PoASetup) -> Bytes {
-    let mut buffer = BytesMut::new();
-    if setup.round_interval_uses_seconds {
-        buffer.extend_from_slice(&[1]);
-    } else {
-        buffer.extend_from_slice(&[0]);
-    }
-    if setup.identities.len() > 255 {
-        panic!("Too many identities!");
-    }
-    buffer.extend_from_slice(&[
-        setup.identity_size,
-        setup.identities.len() as u8,
-        setup.aggregator_change_threshold,
-    ]);
-    buffer.extend_from_slice(&setup.round_intervals.to_le_bytes()[..]);
-    buffer.extend_from_slice(&setup.subblocks_per_round.to_le_bytes()[..]);
-    for identity in &setup.identities {
-        if identity.len() < setup.identity_size as usize {
-            panic!("Invalid identity!");
-        }
-        buffer.extend_from_slice(&identity.slice(0..setup.identity_size as usize));
-    }
-    buffer.freeze()
-}
-
-struct PoAData {
-    pub round_initial_subtime: u64,
-    pub subblock_subtime: u64,
-    pub subblock_index: u32,
-    pub aggregator_index: u16,
-}
-
-fn serialize_poa_data(data: &PoAData) -> Bytes {
-    let mut buffer = BytesMut::new();
-    buffer.extend_from_slice(&data.round_initial_subtime.to_le_bytes()[..]);
-    buffer.extend_from_slice(&data.subblock_subtime.to_le_bytes()[..]);
-    buffer.extend_from_slice(&data.subblock_index.to_le_bytes()[..]);
-    buffer.extend_from_slice(&data.aggregator_index.to_le_bytes()[..]);
-    buffer.freeze()
-}
+// Size of the buffer holding PoA setup in `c/poa.c`.
+const POA_BUFFER_SIZE: usize = 16384;
 
 #[test]
 fn test_poa_normal_update() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -111,7 +68,8 @@ fn test_poa_normal_update() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -122,7 +80,6 @@ fn test_poa_normal_update() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -131,13 +88,16 @@ fn test_poa_normal_update() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 1,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_dep = CellDep::new_builder()
         .out_point(poa_setup_out_point.clone())
         .build();
 
-    let owner_input_out_point = context.create_cell(
+    let owner_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script2.clone())
@@ -147,7 +107,8 @@ fn test_poa_normal_update() {
     let owner_input = CellInput::new_builder()
         .previous_output(owner_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -158,7 +119,8 @@ fn test_poa_normal_update() {
         .previous_output(poa_input_out_point)
         .since(0x400000000000044cu64.pack())
         .build();
-    let poa_data_input_out_point = context.create_cell(
+    let poa_data_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -173,6 +135,7 @@ fn test_poa_normal_update() {
             subblock_subtime: 1000,
             aggregator_index: 0,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     );
     let poa_data_input = CellInput::new_builder()
@@ -201,6 +164,7 @@ fn test_poa_normal_update() {
             subblock_subtime: 1100,
             aggregator_index: 1,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     ];
 
@@ -241,13 +205,48 @@ fn test_poa_normal_update() {
     );
 }
 
+#[test]
+fn test_poa_dep_group_update() {
+    let mut fixture = PoAFixture::builder(2).dep_group().build();
+    let tx = fixture.subblock_builder(1).build(1100).expect("build tx");
+
+    // run, the PoA lock and the state lock of the PoA data cell both load
+    // their code from the dep group
+    let cycles = fixture
+        .context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+
+    // the setup cell is followed by the dep group and its 3 members
+    let mock_tx = build_mock_transaction(&tx, &fixture.context);
+    assert_eq!(mock_tx.mock_info.cell_deps.len(), 5);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "poa_dep_group_update",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
 #[test]
 fn test_poa_normal_update_same_round() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -288,7 +287,8 @@ fn test_poa_normal_update_same_round() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -299,7 +299,6 @@ fn test_poa_normal_update_same_round() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -308,13 +307,16 @@ fn test_poa_normal_update_same_round() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 3,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_dep = CellDep::new_builder()
         .out_point(poa_setup_out_point.clone())
         .build();
 
-    let owner_input_out_point = context.create_cell(
+    let owner_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script2.clone())
@@ -324,7 +326,8 @@ fn test_poa_normal_update_same_round() {
     let owner_input = CellInput::new_builder()
         .previous_output(owner_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -335,7 +338,8 @@ fn test_poa_normal_update_same_round() {
         .previous_output(poa_input_out_point)
         .since(0x4000000000000400u64.pack())
         .build();
-    let poa_data_input_out_point = context.create_cell(
+    let poa_data_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -350,6 +354,7 @@ fn test_poa_normal_update_same_round() {
             subblock_subtime: 1023,
             aggregator_index: 1,
             subblock_index: 1,
+            missed_rounds: Vec::new(),
         }),
     );
     let poa_data_input = CellInput::new_builder()
@@ -378,6 +383,7 @@ fn test_poa_normal_update_same_round() {
             subblock_subtime: 1024,
             aggregator_index: 1,
             subblock_index: 2,
+            missed_rounds: Vec::new(),
         }),
     ];
 
@@ -423,8 +429,8 @@ fn test_poa_overtime_update() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -465,7 +471,8 @@ fn test_poa_overtime_update() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -476,7 +483,6 @@ fn test_poa_overtime_update() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -485,13 +491,16 @@ fn test_poa_overtime_update() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 1,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_dep = CellDep::new_builder()
         .out_point(poa_setup_out_point.clone())
         .build();
 
-    let owner_input_out_point = context.create_cell(
+    let owner_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script1.clone())
@@ -501,7 +510,8 @@ fn test_poa_overtime_update() {
     let owner_input = CellInput::new_builder()
         .previous_output(owner_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -512,7 +522,8 @@ fn test_poa_overtime_update() {
         .previous_output(poa_input_out_point)
         .since(0x40000000000004a6u64.pack())
         .build();
-    let poa_data_input_out_point = context.create_cell(
+    let poa_data_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -527,6 +538,7 @@ fn test_poa_overtime_update() {
             subblock_subtime: 1000,
             aggregator_index: 0,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     );
     let poa_data_input = CellInput::new_builder()
@@ -555,6 +567,7 @@ fn test_poa_overtime_update() {
             subblock_subtime: 1190,
             aggregator_index: 0,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     ];
 
@@ -600,8 +613,8 @@ fn test_poa_setup_update() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -637,7 +650,8 @@ fn test_poa_setup_update() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -648,7 +662,6 @@ fn test_poa_setup_update() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -657,13 +670,16 @@ fn test_poa_setup_update() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 1,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_input = CellInput::new_builder()
         .previous_output(poa_setup_out_point)
         .build();
 
-    let owner1_input_out_point = context.create_cell(
+    let owner1_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script1.clone())
@@ -673,7 +689,8 @@ fn test_poa_setup_update() {
     let owner1_input = CellInput::new_builder()
         .previous_output(owner1_input_out_point)
         .build();
-    let owner2_input_out_point = context.create_cell(
+    let owner2_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script2.clone())
@@ -683,7 +700,8 @@ fn test_poa_setup_update() {
     let owner2_input = CellInput::new_builder()
         .previous_output(owner2_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -712,7 +730,6 @@ fn test_poa_setup_update() {
     let outputs_data = vec![
         Bytes::from_static(b"new"),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -721,7 +738,9 @@ fn test_poa_setup_update() {
             aggregator_change_threshold: 2,
             round_intervals: 47,
             subblocks_per_round: 2,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     ];
 
     // build transaction
@@ -766,8 +785,8 @@ fn test_poa_invalid_aggregator_failure() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -808,7 +827,8 @@ fn test_poa_invalid_aggregator_failure() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -819,7 +839,6 @@ fn test_poa_invalid_aggregator_failure() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -828,13 +847,16 @@ fn test_poa_invalid_aggregator_failure() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 1,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_dep = CellDep::new_builder()
         .out_point(poa_setup_out_point.clone())
         .build();
 
-    let owner_input_out_point = context.create_cell(
+    let owner_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script1.clone())
@@ -844,7 +866,8 @@ fn test_poa_invalid_aggregator_failure() {
     let owner_input = CellInput::new_builder()
         .previous_output(owner_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -855,7 +878,8 @@ fn test_poa_invalid_aggregator_failure() {
         .previous_output(poa_input_out_point)
         .since(0x400000000000044cu64.pack())
         .build();
-    let poa_data_input_out_point = context.create_cell(
+    let poa_data_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -870,6 +894,7 @@ fn test_poa_invalid_aggregator_failure() {
             subblock_subtime: 1000,
             aggregator_index: 0,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     );
     let poa_data_input = CellInput::new_builder()
@@ -898,6 +923,7 @@ fn test_poa_invalid_aggregator_failure() {
             subblock_subtime: 1100,
             aggregator_index: 1,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     ];
 
@@ -915,9 +941,13 @@ fn test_poa_invalid_aggregator_failure() {
     let tx = context.complete_tx(tx);
 
     // run
-    context
+    let err = context
         .verify_tx(&tx, MAX_CYCLES)
         .expect_err("fail verification");
+    assert_eq!(
+        script_error_code(err).and_then(PoaError::from_code),
+        Some(PoaError::OwnerNotFound)
+    );
 
     // dump raw test tx files
     let setup = RunningSetup {
@@ -932,7 +962,7 @@ fn test_poa_invalid_aggregator_failure() {
         &tx,
         &context,
         &setup,
-        -2,
+        PoaError::OwnerNotFound.code(),
         true,
     );
 }
@@ -942,8 +972,8 @@ fn test_poa_since_timestamp_failure() {
     // deploy contract
     let mut context = Context::default();
     let poa_bin: Bytes = Loader::default().load_binary("poa.strip");
-    let poa_out_point = context.deploy_cell(poa_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let poa_out_point = deploy_cell(&mut context, poa_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let poa_owner_script1 = context
@@ -984,7 +1014,8 @@ fn test_poa_since_timestamp_failure() {
         .build();
 
     // prepare cells
-    let poa_setup_out_point = context.create_cell(
+    let poa_setup_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -995,7 +1026,6 @@ fn test_poa_since_timestamp_failure() {
             )
             .build(),
         serialize_poa_setup(&PoASetup {
-            identity_size: 32,
             round_interval_uses_seconds: true,
             identities: vec![
                 poa_owner_script1.calc_script_hash().as_bytes(),
@@ -1004,13 +1034,16 @@ fn test_poa_since_timestamp_failure() {
             aggregator_change_threshold: 2,
             round_intervals: 90,
             subblocks_per_round: 1,
-        }),
+            ..Default::default()
+        })
+        .expect("serialize poa setup"),
     );
     let poa_setup_dep = CellDep::new_builder()
         .out_point(poa_setup_out_point.clone())
         .build();
 
-    let owner_input_out_point = context.create_cell(
+    let owner_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(poa_owner_script2.clone())
@@ -1020,7 +1053,8 @@ fn test_poa_since_timestamp_failure() {
     let owner_input = CellInput::new_builder()
         .previous_output(owner_input_out_point)
         .build();
-    let poa_input_out_point = context.create_cell(
+    let poa_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(poa_lock_script.clone())
@@ -1031,7 +1065,8 @@ fn test_poa_since_timestamp_failure() {
         .previous_output(poa_input_out_point)
         .since(0x4000000000000440u64.pack())
         .build();
-    let poa_data_input_out_point = context.create_cell(
+    let poa_data_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(1000u64.pack())
             .lock(simple_lock_script.clone())
@@ -1046,6 +1081,7 @@ fn test_poa_since_timestamp_failure() {
             subblock_subtime: 1000,
             aggregator_index: 0,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     );
     let poa_data_input = CellInput::new_builder()
@@ -1074,6 +1110,7 @@ fn test_poa_since_timestamp_failure() {
             subblock_subtime: 1100,
             aggregator_index: 1,
             subblock_index: 0,
+            missed_rounds: Vec::new(),
         }),
     ];
 
@@ -1091,9 +1128,13 @@ fn test_poa_since_timestamp_failure() {
     let tx = context.complete_tx(tx);
 
     // run
-    context
+    let err = context
         .verify_tx(&tx, MAX_CYCLES)
         .expect_err("fail verification");
+    assert_eq!(
+        script_error_code(err).and_then(PoaError::from_code),
+        Some(PoaError::SubtimeMismatch)
+    );
 
     // dump raw test tx files
     let setup = RunningSetup {
@@ -1108,7 +1149,408 @@ fn test_poa_since_timestamp_failure() {
         &tx,
         &context,
         &setup,
-        -2,
+        PoaError::SubtimeMismatch.code(),
         true,
     );
 }
+
+// PoA lock deployment with 2 aggregators, 90 second rounds and 2 subblocks
+// per round, used to check the exit code of each rejection.
+fn poa_fixture() -> PoAFixture {
+    PoAFixture::builder(2)
+        .setup(|setup| {
+            setup.aggregator_change_threshold = 2;
+            setup.subblocks_per_round = 2;
+        })
+        .build()
+}
+
+fn timestamp_since(subtime: u64) -> u64 {
+    0x4000000000000000 | subtime
+}
+
+#[test]
+fn test_poa_error_codes() {
+    // Every exit code defined in c/poa.c maps to an error
+    let source = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("../c/poa.c"))
+        .expect("read poa.c");
+    let mut c_codes: Vec<i8> = source
+        .lines()
+        .filter(|line| line.starts_with("#define ERROR_"))
+        .map(|line| {
+            line.split_whitespace()
+                .nth(2)
+                .and_then(|code| code.parse().ok())
+                .expect("error code")
+        })
+        .collect();
+    c_codes.sort_unstable_by(|a, b| b.cmp(a));
+
+    let codes: Vec<i8> = (i8::min_value()..=i8::max_value())
+        .rev()
+        .filter(|code| PoaError::from_code(*code).is_some())
+        .collect();
+    assert_eq!(codes, c_codes);
+    // Exit codes are consecutive from -1, and map back to themselves
+    assert_eq!(
+        codes,
+        (codes[codes.len() - 1]..=-1).rev().collect::<Vec<_>>()
+    );
+    for code in codes {
+        assert_eq!(PoaError::from_code(code).map(PoaError::code), Some(code));
+    }
+}
+
+#[test]
+fn test_poa_fixture_success() {
+    let mut fixture = poa_fixture();
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    fixture.verify(tx);
+
+    let tx = fixture.setup_update_tx(fixture.setup_data(), &[0, 1]);
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_poa_setup_v1_success() {
+    let mut fixture = poa_fixture();
+    // Migrating the setup cell from v0 to v1 is a regular setup update
+    let new_setup_data = {
+        let mut setup = fixture.setup.clone();
+        setup.version = POA_SETUP_V1;
+        serialize_poa_setup(&setup).expect("serialize poa setup")
+    };
+    let tx = fixture.setup_update_tx(new_setup_data, &[0, 1]);
+    fixture.verify(tx);
+
+    fixture.setup.version = POA_SETUP_V1;
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_poa_unsupported_setup_version_failure() {
+    let mut fixture = poa_fixture();
+    fixture.setup.version = POA_SETUP_V1;
+    let mut setup_data = fixture.setup_data().to_vec();
+    setup_data[0] = POA_SETUP_VERSIONED_FLAG | 2;
+    let tx = fixture.subblock_tx(
+        setup_data.into(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::UnsupportedSetupVersion)
+    );
+
+    let mut setup_data = fixture.setup_data().to_vec();
+    setup_data.truncate(setup_data.len() - 1);
+    let tx = fixture.subblock_tx(
+        setup_data.into(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::Encoding));
+}
+
+#[test]
+fn test_poa_pause_and_resume() {
+    for version in [POA_SETUP_V0, POA_SETUP_V1].iter() {
+        let mut fixture = poa_fixture();
+        fixture.setup.version = *version;
+
+        // Pausing is a setup update approved by the change threshold
+        let paused_setup_data = {
+            let mut setup = fixture.setup.clone();
+            setup.paused = true;
+            serialize_poa_setup(&setup).expect("serialize poa setup")
+        };
+        let tx = fixture.setup_update_tx(paused_setup_data.clone(), &[0]);
+        assert_eq!(fixture.verify_error(tx), Some(PoaError::NotEnoughApprovals));
+        let tx = fixture.setup_update_tx(paused_setup_data.clone(), &[0, 1]);
+        fixture.verify(tx);
+
+        // No aggregator can issue subblocks while paused
+        for (last_data, next_data, subtime, owner) in vec![
+            (
+                poa_data(1000, 1000, 0, 0),
+                poa_data(1000, 1050, 1, 0),
+                1050,
+                0,
+            ),
+            (
+                poa_data(1000, 1000, 0, 0),
+                poa_data(1100, 1100, 0, 1),
+                1100,
+                1,
+            ),
+        ] {
+            let tx = fixture.subblock_tx(
+                paused_setup_data.clone(),
+                &last_data,
+                &next_data,
+                timestamp_since(subtime),
+                owner,
+            );
+            assert_eq!(fixture.verify_error(tx), Some(PoaError::Paused));
+        }
+
+        // Resuming is another setup update
+        fixture.setup.paused = true;
+        let resumed_setup_data = {
+            let mut setup = fixture.setup.clone();
+            setup.paused = false;
+            serialize_poa_setup(&setup).expect("serialize poa setup")
+        };
+        let tx = fixture.setup_update_tx(resumed_setup_data.clone(), &[0, 1]);
+        fixture.verify(tx);
+
+        let tx = fixture.subblock_tx(
+            resumed_setup_data,
+            &poa_data(1000, 1000, 0, 0),
+            &poa_data(1100, 1100, 0, 1),
+            timestamp_since(1100),
+            1,
+        );
+        fixture.verify(tx);
+    }
+}
+
+#[test]
+fn test_poa_multiple_poa_inputs_failure() {
+    let mut fixture = poa_fixture();
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    let extra_input = fixture
+        .cell(fixture.poa_cell.output.clone(), Bytes::new())
+        .input(0);
+    let tx = tx.as_advanced_builder().input(extra_input).build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::Transaction));
+}
+
+#[test]
+fn test_poa_invalid_args_failure() {
+    let mut fixture = poa_fixture();
+    let lock = fixture
+        .poa_cell
+        .output
+        .lock()
+        .as_builder()
+        .args(random_32bytes().pack())
+        .build();
+    fixture.poa_cell.output = fixture
+        .poa_cell
+        .output
+        .clone()
+        .as_builder()
+        .lock(lock)
+        .build();
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidArgs));
+}
+
+#[test]
+fn test_poa_invalid_setup_failure() {
+    let mut fixture = poa_fixture();
+    let setup_data = fixture.setup_data();
+    let mut cases = vec![
+        (setup_data.slice(0..11), PoaError::SetupTooShort),
+        (
+            Bytes::from(vec![0u8; POA_BUFFER_SIZE + 1]),
+            PoaError::SetupCellTooLarge,
+        ),
+    ];
+    let mut invalid_identity_size = setup_data.to_vec();
+    invalid_identity_size[1] = 33;
+    cases.push((invalid_identity_size.into(), PoaError::InvalidIdentitySize));
+    let mut invalid_threshold = setup_data.to_vec();
+    invalid_threshold[3] = 3;
+    cases.push((invalid_threshold.into(), PoaError::InvalidChangeThreshold));
+    let mut invalid_length = setup_data.to_vec();
+    invalid_length.push(0);
+    cases.push((invalid_length.into(), PoaError::SetupLengthMismatch));
+
+    for (data, error) in cases {
+        let tx = fixture.subblock_tx(
+            data,
+            &poa_data(1000, 1000, 0, 0),
+            &poa_data(1100, 1100, 0, 1),
+            timestamp_since(1100),
+            1,
+        );
+        assert_eq!(fixture.verify_error(tx), Some(error));
+    }
+}
+
+#[test]
+fn test_poa_data_cell_failure() {
+    let mut fixture = poa_fixture();
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        &poa_data(1000, 1000, 0, 0),
+        &poa_data(1100, 1100, 0, 1),
+        timestamp_since(1100),
+        1,
+    );
+    let duplicate_input = fixture
+        .poa_data_cell_with(serialize_poa_data(&poa_data(1000, 1000, 0, 0)))
+        .input(0);
+    let duplicate_tx = tx.as_advanced_builder().input(duplicate_input).build();
+    assert_eq!(
+        fixture.verify_error(duplicate_tx),
+        Some(PoaError::DuplicatePoACell)
+    );
+
+    let missing_tx = tx
+        .as_advanced_builder()
+        .set_outputs(vec![fixture.poa_cell.output.clone()])
+        .set_outputs_data(vec![Bytes::from_static(b"new").pack()])
+        .build();
+    assert_eq!(
+        fixture.verify_error(missing_tx),
+        Some(PoaError::PoADataCellMissing)
+    );
+
+    let truncated_data = serialize_poa_data(&poa_data(1100, 1100, 0, 1)).slice(0..21);
+    let truncated_tx = tx
+        .as_advanced_builder()
+        .set_outputs_data(vec![
+            Bytes::from_static(b"new").pack(),
+            truncated_data.pack(),
+        ])
+        .build();
+    assert_eq!(
+        fixture.verify_error(truncated_tx),
+        Some(PoaError::InvalidPoAData)
+    );
+}
+
+#[test]
+fn test_poa_new_round_failure() {
+    let mut fixture = poa_fixture();
+    let setup_data = fixture.setup_data();
+    let last_data = poa_data(1000, 1000, 0, 0);
+    let cases = vec![
+        (
+            poa_data(1100, 1100, 0, 2),
+            timestamp_since(1100),
+            1,
+            PoaError::InvalidAggregatorIndex,
+        ),
+        (poa_data(1100, 1100, 0, 1), 1100, 1, PoaError::InvalidSince),
+        (
+            poa_data(1099, 1100, 0, 1),
+            timestamp_since(1100),
+            1,
+            PoaError::InvalidRoundInitialSubtime,
+        ),
+        (
+            poa_data(1100, 1100, 1, 1),
+            timestamp_since(1100),
+            1,
+            PoaError::InvalidSubblockIndex,
+        ),
+        (
+            poa_data(1100, 1100, 0, 0),
+            timestamp_since(1100),
+            0,
+            PoaError::RoundNotStarted,
+        ),
+    ];
+    for (next_data, since, owner, error) in cases {
+        let tx = fixture.subblock_tx(setup_data.clone(), &last_data, &next_data, since, owner);
+        assert_eq!(fixture.verify_error(tx), Some(error));
+    }
+}
+
+#[test]
+fn test_poa_same_round_failure() {
+    let mut fixture = poa_fixture();
+    let setup_data = fixture.setup_data();
+    let last_data = poa_data(1000, 1010, 0, 0);
+    let cases = vec![
+        (
+            poa_data(1000, 1005, 1, 0),
+            1005,
+            0,
+            PoaError::SubtimeDecreased,
+        ),
+        (
+            poa_data(1000, 1020, 1, 1),
+            1020,
+            1,
+            PoaError::AggregatorChanged,
+        ),
+        (
+            poa_data(1000, 1020, 2, 0),
+            1020,
+            0,
+            PoaError::InvalidSubblockIndex,
+        ),
+        (
+            poa_data(1001, 1020, 1, 0),
+            1020,
+            0,
+            PoaError::InvalidRoundInitialSubtime,
+        ),
+    ];
+    for (next_data, subtime, owner, error) in cases {
+        let tx = fixture.subblock_tx(
+            setup_data.clone(),
+            &last_data,
+            &next_data,
+            timestamp_since(subtime),
+            owner,
+        );
+        assert_eq!(fixture.verify_error(tx), Some(error));
+    }
+}
+
+#[test]
+fn test_poa_setup_update_failure() {
+    let mut fixture = poa_fixture();
+    let tx = fixture.setup_update_tx(fixture.setup_data(), &[0]);
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::NotEnoughApprovals));
+
+    let tx = fixture.setup_update_tx(fixture.setup_data(), &[0, 1]);
+    let missing_tx = tx
+        .as_advanced_builder()
+        .set_outputs(vec![fixture.poa_cell.output.clone()])
+        .set_outputs_data(vec![Bytes::from_static(b"new").pack()])
+        .build();
+    assert_eq!(
+        fixture.verify_error(missing_tx),
+        Some(PoaError::PoASetupCellMissing)
+    );
+
+    let tx = fixture.setup_update_tx(fixture.setup_data().slice(0..11), &[0, 1]);
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::SetupTooShort));
+}
@@ -0,0 +1,248 @@
+//! Scenario runner for chains of PoA transactions. A scenario keeps one
+//! `Context` alive: each step builds a transaction from the live PoA cells,
+//! checks that the PoA lock accepts or rejects it as the test expects, and
+//! once accepted, its outputs become the inputs of the next step.
+//!
+//! Aggregators are referred to by the index of their owner lock, which
+//! stays the same when governance adds or removes other aggregators.
+
+use super::*;
+use ckb_tool::ckb_types::{
+    core::{TransactionBuilder, TransactionView},
+    prelude::*,
+};
+use clerkb::{
+    identities_changed, migrate_poa_data, next_poa_data, serialize_poa_data, serialize_poa_setup,
+    subtime_to_since, PoAData, PoASetup, PoaError,
+};
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+pub struct Scenario {
+    fixture: PoAFixture,
+    poa_data: PoAData,
+    steps: usize,
+}
+
+impl Scenario {
+    /// Starts a chain with `aggregators` aggregators, rounds of 90 seconds
+    /// holding up to 4 subblocks, and setup updates approved by a majority.
+    /// The first round starts at subtime 0, with aggregator 0.
+    pub fn new(aggregators: usize) -> Self {
+        Self::with_setup(aggregators, |_| ())
+    }
+
+    /// Like `new`, with the setup adjusted by `f` before the chain starts.
+    pub fn with_setup<F: FnOnce(&mut PoASetup)>(aggregators: usize, f: F) -> Self {
+        let poa_data = poa_data(0, 0, 0, 0);
+        let fixture = PoAFixture::builder(aggregators)
+            .setup(|setup| {
+                setup.aggregator_change_threshold = (aggregators / 2 + 1) as u8;
+                setup.subblocks_per_round = 4;
+                f(setup);
+            })
+            .poa_data(poa_data.clone())
+            .build();
+
+        Scenario {
+            fixture,
+            poa_data,
+            steps: 0,
+        }
+    }
+
+    /// Current PoA setup.
+    pub fn setup(&self) -> &PoASetup {
+        &self.fixture.setup
+    }
+
+    /// PoA data of the latest subblock.
+    pub fn poa_data(&self) -> &PoAData {
+        &self.poa_data
+    }
+
+    /// Index of aggregator `owner` in current setup.
+    pub fn aggregator_index(&self, owner: usize) -> Option<usize> {
+        self.setup().find_aggregator(
+            &self.fixture.owner_scripts[owner]
+                .calc_script_hash()
+                .as_bytes(),
+        )
+    }
+
+    /// Aggregator `owner` issues a subblock at exactly `subtime`, providing
+    /// its owner cell. When the subblock is not valid, the transaction holds the PoA
+    /// data a careless aggregator would write: it joins the running round,
+    /// or starts a new one after the round has ended. An aggregator no
+    /// longer in the setup claims the slot of the next aggregator.
+    pub fn subblock(&mut self, owner: usize, subtime: u64) -> Step<'_> {
+        let last = self.poa_data.clone();
+        let aggregators = self.setup().identities.len();
+        let aggregator_index =
+            self.aggregator_index(owner)
+                .unwrap_or((last.aggregator_index as usize + 1) % aggregators) as u16;
+        let round_end = last.round_initial_subtime
+            + self.setup().round_duration(last.aggregator_index as usize);
+        // The library moves a subblock preceding the last one up to its
+        // subtime, while the step insists on `subtime`
+        let poa_data = next_poa_data(self.setup(), &last, aggregator_index, subtime)
+            .ok()
+            .filter(|poa_data| poa_data.subblock_subtime == subtime)
+            .unwrap_or_else(|| {
+                if subtime < round_end {
+                    PoAData {
+                        subblock_subtime: subtime,
+                        subblock_index: last.subblock_index + 1,
+                        aggregator_index,
+                        ..last.clone()
+                    }
+                } else {
+                    PoAData {
+                        round_initial_subtime: subtime,
+                        subblock_subtime: subtime,
+                        subblock_index: 0,
+                        aggregator_index,
+                        ..last.clone()
+                    }
+                }
+            });
+        let since = subtime_to_since(self.setup(), subtime).expect("since");
+        let owner_cell = self.fixture.owner_cell(owner);
+        let tx = TransactionBuilder::default()
+            .input(self.fixture.poa_cell.input(since))
+            .input(self.fixture.poa_data_cell.input(0))
+            .input(owner_cell.input(0))
+            .output(self.fixture.poa_cell.output.clone())
+            .output_data(self.fixture.poa_cell.data.pack())
+            .output(self.fixture.poa_data_cell.output.clone())
+            .output_data(serialize_poa_data(&poa_data).pack())
+            .cell_dep(self.fixture.poa_setup_cell.code_dep())
+            .cell_deps(self.fixture.cell_deps.clone())
+            .build();
+        Step {
+            description: format!("aggregator {} issues a subblock at {}", owner, subtime),
+            scenario: self,
+            tx,
+            setup: None,
+            poa_data: Some((1, poa_data)),
+        }
+    }
+
+    /// Aggregator `owner` issues one subblock at each of `subtimes`, all of
+    /// which must pass.
+    pub fn subblocks<I: IntoIterator<Item = u64>>(&mut self, owner: usize, subtimes: I) {
+        for subtime in subtimes {
+            self.subblock(owner, subtime).passes();
+        }
+    }
+
+    /// Updates the setup with approvals from `approvers`. When the aggregator
+    /// list or the kind of round intervals changes, the PoA data cell is
+    /// reset via `migrate_poa_data`.
+    pub fn update_setup(&mut self, new_setup: PoASetup, approvers: &[usize]) -> Step<'_> {
+        let mut builder = TransactionBuilder::default()
+            .input(self.fixture.poa_cell.input(0))
+            .input(self.fixture.poa_setup_cell.input(0))
+            .output(self.fixture.poa_cell.output.clone())
+            .output_data(self.fixture.poa_cell.data.pack())
+            .output(self.fixture.poa_setup_cell.output.clone())
+            .output_data(
+                serialize_poa_setup(&new_setup)
+                    .expect("serialize poa setup")
+                    .pack(),
+            );
+        let poa_data = if identities_changed(self.setup(), &new_setup)
+            || self.setup().round_interval_uses_seconds != new_setup.round_interval_uses_seconds
+        {
+            let poa_data = migrate_poa_data(self.setup(), &new_setup, &self.poa_data);
+            builder = builder
+                .input(self.fixture.poa_data_cell.input(0))
+                .output(self.fixture.poa_data_cell.output.clone())
+                .output_data(serialize_poa_data(&poa_data).pack());
+            Some((2, poa_data))
+        } else {
+            None
+        };
+        for owner in approvers {
+            builder = builder.input(self.fixture.owner_cell(*owner).input(0));
+        }
+        let tx = builder.cell_deps(self.fixture.cell_deps.clone()).build();
+        Step {
+            description: format!("setup update approved by aggregators {:?}", approvers),
+            scenario: self,
+            tx,
+            setup: Some((1, new_setup)),
+            poa_data,
+        }
+    }
+
+    /// Removes aggregator `owner` from the setup, lowering the aggregator
+    /// change threshold if there are not enough aggregators left.
+    pub fn remove_aggregator(&mut self, owner: usize, approvers: &[usize]) -> Step<'_> {
+        let mut new_setup = self.setup().clone();
+        let index = self.aggregator_index(owner).expect("aggregator in setup");
+        new_setup.identities.remove(index);
+        new_setup.aggregator_change_threshold = new_setup
+            .aggregator_change_threshold
+            .min(new_setup.identities.len() as u8);
+        self.update_setup(new_setup, approvers)
+    }
+}
+
+/// A transaction built by a scenario, which must be resolved by stating
+/// whether the PoA lock accepts it.
+#[must_use = "a step must state whether it passes or fails"]
+pub struct Step<'a> {
+    scenario: &'a mut Scenario,
+    description: String,
+    tx: TransactionView,
+    // New setup and PoA data, with the indices of their outputs
+    setup: Option<(usize, PoASetup)>,
+    poa_data: Option<(usize, PoAData)>,
+}
+
+impl<'a> Step<'a> {
+    /// The PoA lock accepts the transaction, whose outputs replace the live
+    /// PoA cells.
+    pub fn passes(self) {
+        let scenario = self.scenario;
+        scenario.steps += 1;
+        let tx = scenario.fixture.context.complete_tx(self.tx);
+        if let Err(err) = scenario.fixture.context.verify_tx(&tx, MAX_CYCLES) {
+            panic!(
+                "step {} ({}) should pass, but fails: {:?}",
+                scenario.steps, self.description, err
+            );
+        }
+        scenario.fixture.poa_cell = scenario.fixture.commit_output(&tx, 0);
+        if let Some((index, setup)) = self.setup {
+            scenario.fixture.poa_setup_cell = scenario.fixture.commit_output(&tx, index);
+            scenario.fixture.setup = setup;
+        }
+        if let Some((index, poa_data)) = self.poa_data {
+            scenario.fixture.poa_data_cell = scenario.fixture.commit_output(&tx, index);
+            scenario.poa_data = poa_data;
+        }
+    }
+
+    /// The PoA lock rejects the transaction with `error`, the live PoA cells
+    /// are left untouched.
+    pub fn fails_with(self, error: PoaError) {
+        let scenario = self.scenario;
+        scenario.steps += 1;
+        let tx = scenario.fixture.context.complete_tx(self.tx);
+        match scenario.fixture.context.verify_tx(&tx, MAX_CYCLES) {
+            Ok(_) => panic!(
+                "step {} ({}) should fail with {:?}, but passes",
+                scenario.steps, self.description, error
+            ),
+            Err(err) => assert_eq!(
+                script_error_code(err).and_then(PoaError::from_code),
+                Some(error),
+                "step {} ({})",
+                scenario.steps,
+                self.description
+            ),
+        }
+    }
+}
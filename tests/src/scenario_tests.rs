@@ -0,0 +1,57 @@
+use super::scenario::Scenario;
+use clerkb::PoaError;
+
+#[test]
+fn test_scenario_handoff_and_removal() {
+    let mut scenario = Scenario::new(3);
+
+    // Aggregator 1 issues 3 subblocks in its round
+    scenario.subblocks(1, 1000..=1002);
+    assert_eq!(scenario.poa_data().subblock_index, 2);
+    scenario
+        .subblock(2, 1050)
+        .fails_with(PoaError::AggregatorChanged);
+    // Aggregator 2 is due before aggregator 0
+    scenario
+        .subblock(0, 1100)
+        .fails_with(PoaError::RoundNotStarted);
+    scenario.subblock(2, 1100).passes();
+    scenario.subblock(2, 1120).passes();
+
+    // Governance removes aggregator 1
+    scenario
+        .remove_aggregator(1, &[0])
+        .fails_with(PoaError::NotEnoughApprovals);
+    scenario.remove_aggregator(1, &[0, 2]).passes();
+    assert_eq!(scenario.setup().identities.len(), 2);
+    assert_eq!(scenario.aggregator_index(1), None);
+    // The round stays with aggregator 2, starting over at its last subblock
+    assert_eq!(scenario.poa_data().aggregator_index, 1);
+    assert_eq!(scenario.poa_data().round_initial_subtime, 1120);
+
+    scenario
+        .subblock(1, 1300)
+        .fails_with(PoaError::OwnerNotFound);
+    scenario.subblock(0, 1300).passes();
+    scenario.subblocks(2, 1400..=1403);
+    assert_eq!(scenario.poa_data().aggregator_index, 1);
+}
+
+#[test]
+fn test_scenario_round_limits() {
+    let mut scenario = Scenario::with_setup(2, |setup| setup.subblocks_per_round = 2);
+
+    scenario.subblocks(1, vec![500, 520]);
+    scenario
+        .subblock(1, 540)
+        .fails_with(PoaError::InvalidSubblockIndex);
+    // A new round of the same aggregator waits for a full rotation
+    scenario
+        .subblock(1, 600)
+        .fails_with(PoaError::RoundNotStarted);
+    scenario.subblock(0, 590).passes();
+    scenario
+        .subblock(0, 585)
+        .fails_with(PoaError::SubtimeDecreased);
+    scenario.subblock(1, 680).passes();
+}
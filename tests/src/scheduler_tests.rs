@@ -0,0 +1,273 @@
+use super::*;
+use clerkb::{
+    handoff_duration, record_missed_rounds, skipped_rotations, steps, Error, PoAData,
+    RoundScheduler, State, POA_SETUP_V1,
+};
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+fn round_scheduler(fixture: &PoAFixture, aggregator_index: usize) -> RoundScheduler {
+    RoundScheduler::new(
+        fixture.owner_scripts[aggregator_index]
+            .calc_script_hash()
+            .as_bytes(),
+    )
+}
+
+// Checks if the PoA lock accepts a subblock issued by the specified
+// aggregator, at the specified timestamp.
+fn verify_subblock(
+    fixture: &mut PoAFixture,
+    aggregator_index: usize,
+    last_data: &PoAData,
+    next_data: &PoAData,
+    since: u64,
+) -> bool {
+    let tx = fixture.subblock_tx(
+        fixture.setup_data(),
+        last_data,
+        next_data,
+        0x4000000000000000u64 | since,
+        aggregator_index,
+    );
+    let tx = fixture.context.complete_tx(tx);
+    fixture.context.verify_tx(&tx, MAX_CYCLES).is_ok()
+}
+
+// Checks if the PoA lock accepts a new round started by the specified
+// aggregator at the specified timestamp.
+fn verify_new_round(
+    fixture: &mut PoAFixture,
+    aggregator_index: usize,
+    last_data: &PoAData,
+    since: u64,
+) -> bool {
+    let next_data = poa_data(since, since, 0, aggregator_index as u16);
+    verify_subblock(fixture, aggregator_index, last_data, &next_data, since)
+}
+
+#[test]
+fn test_steps() {
+    assert_eq!(steps(1, 0, 2), Ok(1));
+    assert_eq!(steps(0, 1, 2), Ok(1));
+    assert_eq!(steps(0, 0, 2), Ok(2));
+    assert_eq!(steps(0, 2, 3), Ok(1));
+    assert_eq!(steps(1, 2, 3), Ok(2));
+    assert_eq!(steps(0, 0, 1), Ok(1));
+    assert_eq!(steps(254, 0, 255), Ok(254));
+    assert_eq!(steps(0, 0, 0), Err(Error::InvalidAggregatorNumber(0)));
+}
+
+#[test]
+fn test_record_missed_rounds() {
+    assert_eq!(record_missed_rounds(&[0, 0, 0], 0, 0, 0), Ok(vec![0, 1, 1]));
+    assert_eq!(record_missed_rounds(&[0, 0, 0], 1, 0, 0), Ok(vec![0, 0, 0]));
+    assert_eq!(
+        record_missed_rounds(&[5, u32::max_value(), 7], 2, 0, 0),
+        Ok(vec![5, u32::max_value(), 7])
+    );
+    // Skipped rotations count for all aggregators
+    assert_eq!(record_missed_rounds(&[0, 0, 0], 2, 0, 2), Ok(vec![2, 3, 2]));
+    assert_eq!(
+        record_missed_rounds(&[5, u32::max_value() - 1, 7], 2, 0, 1),
+        Ok(vec![6, u32::max_value(), 8])
+    );
+    assert_eq!(
+        record_missed_rounds(&[0, 0, 0], 0, 0, u64::max_value()),
+        Ok(vec![u32::max_value(); 3])
+    );
+    assert_eq!(
+        record_missed_rounds(&[], 0, 0, 0),
+        Err(Error::InvalidAggregatorNumber(0))
+    );
+    assert_eq!(
+        record_missed_rounds(&[0; 256], 0, 0, 0),
+        Err(Error::InvalidAggregatorNumber(256))
+    );
+}
+
+#[test]
+fn test_skipped_rotations() {
+    let mut fixture = PoAFixture::builder(3).build();
+    let setup = &mut fixture.setup;
+    assert_eq!(setup.rotation_duration(), 270);
+    assert_eq!(skipped_rotations(setup, 1090, 1000), 0);
+    assert_eq!(skipped_rotations(setup, 1090, 1359), 0);
+    assert_eq!(skipped_rotations(setup, 1090, 1360), 1);
+    assert_eq!(skipped_rotations(setup, 1090, 1900), 3);
+
+    setup.version = POA_SETUP_V1;
+    setup.weights = vec![3, 1, 2];
+    assert_eq!(setup.rotation_duration(), 540);
+    assert_eq!(skipped_rotations(setup, 1090, 1630), 1);
+
+    setup.round_intervals = 0;
+    assert_eq!(skipped_rotations(setup, 1090, 1900), 0);
+}
+
+#[test]
+fn test_handoff_duration() {
+    let mut fixture = PoAFixture::builder(3).build();
+    let setup = &mut fixture.setup;
+    assert_eq!(handoff_duration(setup, 1, 0), Ok(90));
+    assert_eq!(handoff_duration(setup, 0, 0), Ok(270));
+
+    setup.version = POA_SETUP_V1;
+    setup.weights = vec![3, 1, 2];
+    assert_eq!(handoff_duration(setup, 1, 0), Ok(270));
+    assert_eq!(handoff_duration(setup, 2, 0), Ok(360));
+    assert_eq!(handoff_duration(setup, 0, 0), Ok(540));
+    // Wraps around past the last aggregator
+    assert_eq!(handoff_duration(setup, 0, 2), Ok(180));
+    assert_eq!(handoff_duration(setup, 1, 2), Ok(450));
+
+    setup.identities.clear();
+    setup.weights.clear();
+    assert_eq!(
+        handoff_duration(setup, 0, 0),
+        Err(Error::InvalidAggregatorNumber(0))
+    );
+}
+
+#[test]
+fn test_scheduler_next_aggregator() {
+    let mut fixture = PoAFixture::builder(2).build();
+    let setup = fixture.setup.clone();
+    let tip = poa_data(1000, 1000, 0, 0);
+    let mut scheduler = round_scheduler(&fixture, 1);
+    assert_eq!(scheduler.aggregator_index(&setup), Ok(1));
+    assert_eq!(scheduler.next_round_start(&tip, &setup), Ok(1090));
+
+    assert_eq!(scheduler.wait_time(1089, &tip, &setup), Ok(1));
+    assert_eq!(
+        scheduler.should_issue_new_block(1089, &tip, &setup),
+        Ok(State::No)
+    );
+    assert!(!verify_new_round(&mut fixture, 1, &tip, 1089));
+
+    assert_eq!(scheduler.wait_time(1090, &tip, &setup), Ok(0));
+    assert_eq!(
+        scheduler.should_issue_new_block(1090, &tip, &setup),
+        Ok(State::Yes)
+    );
+    assert_eq!(scheduler.round_start_subtime(), Some(1090));
+    assert!(verify_new_round(&mut fixture, 1, &tip, 1090));
+}
+
+#[test]
+fn test_scheduler_same_aggregator_waits_full_rotation() {
+    let mut fixture = PoAFixture::builder(2).build();
+    let setup = fixture.setup.clone();
+    let tip = poa_data(1000, 1000, 0, 0);
+    let mut scheduler = round_scheduler(&fixture, 0);
+    assert_eq!(scheduler.next_round_start(&tip, &setup), Ok(1180));
+    assert_eq!(
+        scheduler.should_issue_new_block(1179, &tip, &setup),
+        Ok(State::No)
+    );
+    assert!(!verify_new_round(&mut fixture, 0, &tip, 1179));
+    assert_eq!(
+        scheduler.should_issue_new_block(1180, &tip, &setup),
+        Ok(State::Yes)
+    );
+    assert!(verify_new_round(&mut fixture, 0, &tip, 1180));
+}
+
+#[test]
+fn test_scheduler_in_round() {
+    let mut fixture = PoAFixture::builder(2).subblocks_per_round(3).build();
+    let setup = fixture.setup.clone();
+    let tip = poa_data(1000, 1000, 0, 0);
+    let mut scheduler = round_scheduler(&fixture, 1);
+    assert_eq!(
+        scheduler.should_issue_new_block(1090, &tip, &setup),
+        Ok(State::Yes)
+    );
+    assert!(verify_new_round(&mut fixture, 1, &tip, 1090));
+
+    let tip = poa_data(1090, 1090, 0, 1);
+    assert_eq!(
+        scheduler.should_issue_new_block(1100, &tip, &setup),
+        Ok(State::YesIfFull)
+    );
+    let next = poa_data(1090, 1100, 1, 1);
+    assert!(verify_subblock(&mut fixture, 1, &tip, &next, 1100));
+
+    // Round expires, aggregator 1 now needs to wait for aggregator 0.
+    let tip = next;
+    assert_eq!(
+        scheduler.should_issue_new_block(1180, &tip, &setup),
+        Ok(State::No)
+    );
+    assert_eq!(scheduler.round_start_subtime(), None);
+    let next = poa_data(1090, 1180, 2, 1);
+    assert!(!verify_subblock(&mut fixture, 1, &tip, &next, 1180));
+    assert!(!verify_new_round(&mut fixture, 1, &tip, 1180));
+    assert!(verify_new_round(&mut fixture, 0, &tip, 1180));
+}
+
+#[test]
+fn test_scheduler_cancel_issue_block() {
+    let fixture = PoAFixture::builder(2).subblocks_per_round(3).build();
+    let tip = poa_data(1000, 1000, 0, 0);
+    let mut scheduler = round_scheduler(&fixture, 1);
+    assert_eq!(
+        scheduler.should_issue_new_block(1090, &tip, &fixture.setup),
+        Ok(State::Yes)
+    );
+    scheduler.cancel_issue_block();
+    assert_eq!(scheduler.round_start_subtime(), None);
+    assert_eq!(
+        scheduler.should_issue_new_block(1091, &tip, &fixture.setup),
+        Ok(State::Yes)
+    );
+    assert_eq!(scheduler.round_start_subtime(), Some(1091));
+}
+
+#[test]
+fn test_scheduler_unknown_identity() {
+    let fixture = PoAFixture::builder(2).build();
+    let tip = PoAData::default();
+    let mut scheduler = RoundScheduler::new(random_32bytes());
+    assert_eq!(
+        scheduler.should_issue_new_block(1000, &tip, &fixture.setup),
+        Err(clerkb::Error::IdentityNotFound)
+    );
+}
+
+#[test]
+fn test_scheduler_agrees_with_poa_lock() {
+    let mut fixture = PoAFixture::builder(3).round_intervals(50).build();
+    let setup = fixture.setup.clone();
+    let tip = poa_data(1000, 1000, 0, 2);
+    for aggregator_index in 0..3 {
+        let next_round_start = round_scheduler(&fixture, aggregator_index)
+            .next_round_start(&tip, &setup)
+            .expect("next round start");
+        for since in &[
+            1000,
+            1049,
+            1050,
+            1099,
+            1100,
+            1149,
+            1150,
+            1151,
+            next_round_start - 1,
+            next_round_start,
+        ] {
+            let mut scheduler = round_scheduler(&fixture, aggregator_index);
+            let state = scheduler
+                .should_issue_new_block(*since, &tip, &setup)
+                .expect("schedule");
+            let accepted = verify_new_round(&mut fixture, aggregator_index, &tip, *since);
+            assert_eq!(
+                state == State::Yes,
+                accepted,
+                "aggregator {} at {}",
+                aggregator_index,
+                since
+            );
+        }
+    }
+}
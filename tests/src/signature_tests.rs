@@ -0,0 +1,223 @@
+use super::*;
+use ckb_tool::ckb_crypto::secp::Generator;
+use ckb_tool::ckb_types::{bytes::Bytes, packed::*, prelude::*};
+use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    parse_poa_setup, serialize_poa_setup, sign_poa_tx, Error, PoASetup, PoaError, SignatureLibrary,
+    SIGNATURE_SIZE,
+};
+use std::collections::HashMap;
+
+#[test]
+fn test_signature_setup_roundtrip() {
+    let setup = PoASetup {
+        identity_size: 20,
+        identities: vec![Bytes::from(vec![1u8; 20]), Bytes::from(vec![2u8; 20])],
+        aggregator_change_threshold: 2,
+        round_intervals: 10,
+        subblocks_per_round: 3,
+        signature_library: Some(SignatureLibrary {
+            code_hash: [7u8; 32],
+            hash_type: 1,
+        }),
+        ..Default::default()
+    };
+    let data = serialize_poa_setup(&setup).expect("serialize poa setup");
+    assert_eq!(data.len(), 12 + 2 * 20 + 33);
+    assert_eq!(data[0], 2);
+    assert_eq!(parse_poa_setup(&data), Ok(setup.clone()));
+
+    // Signature library is required after identities
+    assert_eq!(
+        parse_poa_setup(&data[..data.len() - 1]),
+        Err(Error::SetupLengthMismatch {
+            expected: data.len(),
+            actual: data.len() - 1,
+        })
+    );
+
+    let mut invalid_setup = setup;
+    invalid_setup.identity_size = 21;
+    invalid_setup.identities = vec![Bytes::from(vec![1u8; 21])];
+    invalid_setup.aggregator_change_threshold = 1;
+    assert_eq!(
+        serialize_poa_setup(&invalid_setup),
+        Err(Error::InvalidIdentitySize(21))
+    );
+}
+
+#[test]
+fn test_signature_subblock() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let tx = fixture.unsigned_subblock_tx(0, 1020);
+    let tx = sign_poa_tx(&tx, 0, &[fixture.keys[0].clone()]).expect("sign tx");
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "signature_subblock",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_signature_subblock_next_aggregator() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let tx = fixture.unsigned_subblock_tx(1, 1090);
+    let tx = sign_poa_tx(&tx, 0, &[fixture.keys[1].clone()]).expect("sign tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_signature_subblock_wrong_key() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let tx = fixture.unsigned_subblock_tx(0, 1020);
+    let tx = sign_poa_tx(&tx, 0, &[fixture.keys[1].clone()]).expect("sign tx");
+    assert_eq!(
+        fixture.verify_error(tx.clone()),
+        Some(PoaError::OwnerNotFound)
+    );
+
+    let tx = sign_poa_tx(&tx, 0, &[Generator::random_privkey()]).expect("sign tx");
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::OwnerNotFound));
+}
+
+#[test]
+fn test_signature_subblock_replayed_signature() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let signed_tx = fixture.unsigned_subblock_tx(0, 1020);
+    let signed_tx = sign_poa_tx(&signed_tx, 0, &[fixture.keys[0].clone()]).expect("sign tx");
+    fixture.verify(signed_tx.clone());
+
+    // The same signature is not valid for another transaction
+    let tx = fixture.unsigned_subblock_tx(0, 1030);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(signed_tx.witnesses().into_iter().collect())
+        .build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::OwnerNotFound));
+}
+
+#[test]
+fn test_signature_subblock_missing_witness() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let tx = fixture.unsigned_subblock_tx(0, 1020);
+    assert_eq!(
+        fixture.verify_error(tx.clone()),
+        Some(PoaError::InvalidWitness)
+    );
+
+    // Witness without signatures in lock field
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![WitnessArgs::default().as_bytes().pack()])
+        .build();
+    assert_eq!(
+        fixture.verify_error(tx.clone()),
+        Some(PoaError::InvalidWitness)
+    );
+
+    // Lock field that is not a multiple of the signature size
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![WitnessArgs::new_builder()
+            .lock(Some(Bytes::from(vec![0u8; SIGNATURE_SIZE - 1])).pack())
+            .build()
+            .as_bytes()
+            .pack()])
+        .build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidWitness));
+}
+
+#[test]
+fn test_signature_subblock_invalid_signature() {
+    let mut fixture = PoAFixture::builder(2)
+        .aggregator_change_threshold(1)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let tx = fixture.unsigned_subblock_tx(0, 1020);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![WitnessArgs::new_builder()
+            .lock(Some(Bytes::from(vec![0xffu8; SIGNATURE_SIZE])).pack())
+            .build()
+            .as_bytes()
+            .pack()])
+        .build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidSignature));
+}
+
+#[test]
+fn test_signature_setup_update() {
+    let mut fixture = PoAFixture::builder(3)
+        .aggregator_change_threshold(2)
+        .signatures()
+        .poa_data(poa_data(1000, 1010, 0, 0))
+        .build();
+    let mut new_setup = fixture.setup.clone();
+    new_setup.round_intervals = 47;
+    let builder = fixture.setup_update_builder(new_setup, &[0]);
+    assert_eq!(
+        builder.build().err(),
+        Some(Error::NotEnoughApprovals {
+            required: 2,
+            missing: vec![
+                fixture.setup.identities[1].clone(),
+                fixture.setup.identities[2].clone(),
+            ],
+        })
+    );
+
+    let builder = builder.signer(fixture.setup.identities[2].clone());
+    assert_eq!(
+        builder.missing_approvals(),
+        Ok(vec![fixture.setup.identities[1].clone()])
+    );
+    let tx = fixture
+        .context
+        .complete_tx(builder.build().expect("build tx"));
+
+    let signed_tx =
+        sign_poa_tx(&tx, 0, &[fixture.keys[0].clone(), fixture.keys[2].clone()]).expect("sign tx");
+    fixture.verify(signed_tx);
+
+    // Duplicate signatures from the same aggregator count once
+    let signed_tx =
+        sign_poa_tx(&tx, 0, &[fixture.keys[0].clone(), fixture.keys[0].clone()]).expect("sign tx");
+    assert_eq!(
+        fixture.verify_error(signed_tx),
+        Some(PoaError::NotEnoughApprovals)
+    );
+}
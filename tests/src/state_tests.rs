@@ -1,18 +1,26 @@
 use super::*;
 use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
-use ckb_tool::ckb_types::{bytes::Bytes, core::TransactionBuilder, packed::*, prelude::*};
+use ckb_tool::ckb_types::{
+    bytes::{Bytes, BytesMut},
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
 use ckb_x64_simulator::RunningSetup;
 use std::collections::HashMap;
 
 const MAX_CYCLES: u64 = 10_000_000;
+// Error codes returned by `c/state.c`.
+const ERROR_TRANSACTION: i8 = -1;
+const ERROR_OWNER_NOT_FOUND: i8 = -2;
 
 #[test]
 fn test_state_normal_unlock() {
     // deploy contract
     let mut context = Context::default();
     let state_bin: Bytes = Loader::default().load_binary("state.strip");
-    let state_out_point = context.deploy_cell(state_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let state_out_point = deploy_cell(&mut context, state_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let target_lock_script = context
@@ -35,7 +43,8 @@ fn test_state_normal_unlock() {
         .expect("build script");
 
     // prepare cells
-    let target_input_out_point = context.create_cell(
+    let target_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(target_lock_script.clone())
@@ -45,7 +54,8 @@ fn test_state_normal_unlock() {
     let target_input = CellInput::new_builder()
         .previous_output(target_input_out_point)
         .build();
-    let state_input_out_point = context.create_cell(
+    let state_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(state_lock_script.clone())
@@ -101,8 +111,8 @@ fn test_state_update_failure() {
     // deploy contract
     let mut context = Context::default();
     let state_bin: Bytes = Loader::default().load_binary("state.strip");
-    let state_out_point = context.deploy_cell(state_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let state_out_point = deploy_cell(&mut context, state_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let target_lock_script = context
@@ -122,7 +132,8 @@ fn test_state_update_failure() {
         .expect("build script");
 
     // prepare cells
-    let target_input_out_point = context.create_cell(
+    let target_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(target_lock_script.clone())
@@ -132,7 +143,8 @@ fn test_state_update_failure() {
     let target_input = CellInput::new_builder()
         .previous_output(target_input_out_point)
         .build();
-    let state_input_out_point = context.create_cell(
+    let state_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(state_lock_script.clone())
@@ -160,9 +172,10 @@ fn test_state_update_failure() {
     let tx = context.complete_tx(tx);
 
     // run
-    context
+    let err = context
         .verify_tx(&tx, MAX_CYCLES)
         .expect_err("fail verification");
+    assert_eq!(script_error_code(err), Some(ERROR_OWNER_NOT_FOUND));
 
     // dump raw test tx files
     let setup = RunningSetup {
@@ -177,7 +190,7 @@ fn test_state_update_failure() {
         &tx,
         &context,
         &setup,
-        1,
+        ERROR_OWNER_NOT_FOUND,
         true,
     );
 }
@@ -187,8 +200,8 @@ fn test_state_invalid_args_failure() {
     // deploy contract
     let mut context = Context::default();
     let state_bin: Bytes = Loader::default().load_binary("state.strip");
-    let state_out_point = context.deploy_cell(state_bin);
-    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let state_out_point = deploy_cell(&mut context, state_bin);
+    let always_success_out_point = deploy_cell(&mut context, ALWAYS_SUCCESS.clone());
 
     // prepare scripts
     let target_lock_script = context
@@ -214,7 +227,8 @@ fn test_state_invalid_args_failure() {
         .expect("build script");
 
     // prepare cells
-    let target_input_out_point = context.create_cell(
+    let target_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(target_lock_script.clone())
@@ -224,7 +238,8 @@ fn test_state_invalid_args_failure() {
     let target_input = CellInput::new_builder()
         .previous_output(target_input_out_point)
         .build();
-    let state_input_out_point = context.create_cell(
+    let state_input_out_point = create_cell(
+        &mut context,
         CellOutput::new_builder()
             .capacity(500u64.pack())
             .lock(state_lock_script.clone())
@@ -252,9 +267,10 @@ fn test_state_invalid_args_failure() {
     let tx = context.complete_tx(tx);
 
     // run
-    context
+    let err = context
         .verify_tx(&tx, MAX_CYCLES)
         .expect_err("fail verification");
+    assert_eq!(script_error_code(err), Some(ERROR_TRANSACTION));
 
     // dump raw test tx files
     let setup = RunningSetup {
@@ -269,7 +285,181 @@ fn test_state_invalid_args_failure() {
         &tx,
         &context,
         &setup,
-        -1,
+        ERROR_TRANSACTION,
         true,
     );
 }
+
+// Builds a transaction unlocking a state cell with `state_lock_args`, using
+// input cells with the given owner locks.
+fn build_state_tx(
+    context: &mut Context,
+    state_lock_args: Bytes,
+    owner_locks: &[Script],
+) -> TransactionView {
+    let state_bin: Bytes = Loader::default().load_binary("state.strip");
+    let state_out_point = deploy_cell(context, state_bin);
+    let always_success_out_point = deploy_cell(context, ALWAYS_SUCCESS.clone());
+    let state_lock_script = context
+        .build_script(&state_out_point, state_lock_args)
+        .expect("build script");
+    let output_lock_script = context
+        .build_script(&always_success_out_point, random_32bytes())
+        .expect("build script");
+
+    let state_input_out_point = create_cell(
+        context,
+        CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(state_lock_script)
+            .build(),
+        Bytes::new(),
+    );
+    let owner_inputs: Vec<CellInput> = owner_locks
+        .iter()
+        .map(|lock| {
+            let out_point = create_cell(
+                context,
+                CellOutput::new_builder()
+                    .capacity(500u64.pack())
+                    .lock(lock.clone())
+                    .build(),
+                Bytes::new(),
+            );
+            CellInput::new_builder().previous_output(out_point).build()
+        })
+        .collect();
+
+    let tx = TransactionBuilder::default()
+        .input(
+            CellInput::new_builder()
+                .previous_output(state_input_out_point)
+                .build(),
+        )
+        .inputs(owner_inputs)
+        .output(
+            CellOutput::new_builder()
+                .capacity(1000u64.pack())
+                .lock(output_lock_script)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(CellDep::new_builder().out_point(state_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point)
+                .build(),
+        )
+        .build();
+    context.complete_tx(tx)
+}
+
+fn owner_locks(context: &mut Context, count: usize) -> Vec<Script> {
+    let always_success_out_point = deploy_cell(context, ALWAYS_SUCCESS.clone());
+    (0..count)
+        .map(|_| {
+            context
+                .build_script(&always_success_out_point, random_32bytes())
+                .expect("build script")
+        })
+        .collect()
+}
+
+fn owners_args(locks: &[Script]) -> Bytes {
+    let mut buffer = BytesMut::new();
+    for lock in locks {
+        buffer.extend_from_slice(lock.calc_script_hash().as_slice());
+    }
+    buffer.freeze()
+}
+
+#[test]
+fn test_state_multiple_owners_unlock() {
+    let mut context = Context::default();
+    let owners = owner_locks(&mut context, 3);
+    let args = owners_args(&owners);
+
+    // Any of the owners can unlock the cell
+    for owner in &owners {
+        let tx = build_state_tx(&mut context, args.clone(), &[owner.clone()]);
+        let cycles = context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect("pass verification");
+        println!("consume cycles: {}", cycles);
+    }
+
+    let tx = build_state_tx(&mut context, args, &owners[2..]);
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "state_multiple_owners_unlock",
+        "state_sim",
+        &tx,
+        &context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_state_multiple_owners_missing() {
+    let mut context = Context::default();
+    let owners = owner_locks(&mut context, 3);
+    let others = owner_locks(&mut context, 2);
+    let tx = build_state_tx(&mut context, owners_args(&owners), &others);
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_eq!(script_error_code(err), Some(ERROR_OWNER_NOT_FOUND));
+
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "state_multiple_owners_missing",
+        "state_sim",
+        &tx,
+        &context,
+        &setup,
+        ERROR_OWNER_NOT_FOUND,
+        true,
+    );
+}
+
+#[test]
+fn test_state_many_owners_unlock() {
+    let mut context = Context::default();
+    let owners = owner_locks(&mut context, 64);
+    let tx = build_state_tx(&mut context, owners_args(&owners), &owners[63..]);
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_state_invalid_owners_args() {
+    let mut context = Context::default();
+    let owners = owner_locks(&mut context, 2);
+
+    // Args must be a multiple of 32 bytes
+    let args = owners_args(&owners).slice(0..48);
+    let tx = build_state_tx(&mut context, args, &owners);
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_eq!(script_error_code(err), Some(ERROR_TRANSACTION));
+
+    let tx = build_state_tx(&mut context, Bytes::new(), &owners);
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_eq!(script_error_code(err), Some(ERROR_TRANSACTION));
+}
@@ -0,0 +1,635 @@
+use super::*;
+use ckb_tool::ckb_types::{bytes::Bytes, core::TransactionView, packed::*, prelude::*};
+use ckb_x64_simulator::RunningSetup;
+use clerkb::{
+    current_subtime, missed_rounds, offline_aggregators, serialize_poa_data, subtime_to_since,
+    Error, PoAData, PoaError, RoundScheduler, State, SubblockTxBuilder, POA_SETUP_V1,
+    SINCE_TIMESTAMP_FLAG,
+};
+use std::collections::HashMap;
+
+#[test]
+fn test_subblock_builder_new_round() {
+    let mut fixture = PoAFixture::builder(2).build();
+    fixture.set_poa_data(&poa_data(1000, 1000, 0, 0));
+    let builder = fixture.subblock_builder(1);
+    let next_data = poa_data(1100, 1100, 0, 1);
+    assert_eq!(builder.next_poa_data(1100), Ok(next_data.clone()));
+    let tx = builder.build(1100).expect("build tx");
+    let since: u64 = tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, 0x400000000000044c);
+    assert_eq!(
+        tx.outputs_data().get(1).unwrap().raw_data(),
+        serialize_poa_data(&next_data)
+    );
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "subblock_builder_new_round",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_subblock_builder_same_round() {
+    let mut fixture = PoAFixture::builder(2).subblocks_per_round(3).build();
+    fixture.set_poa_data(&poa_data(1000, 1023, 1, 1));
+    let builder = fixture.subblock_builder(1);
+    let next_data = poa_data(1000, 1024, 2, 1);
+    assert_eq!(builder.next_poa_data(1024), Ok(next_data.clone()));
+    let tx = builder.build(1024).expect("build tx");
+    let since: u64 = tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, 0x4000000000000400);
+    assert_eq!(
+        tx.outputs_data().get(1).unwrap().raw_data(),
+        serialize_poa_data(&next_data)
+    );
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_builder_same_round_keeps_subtime() {
+    let mut fixture = PoAFixture::builder(2).subblocks_per_round(3).build();
+    fixture.set_poa_data(&poa_data(1000, 1023, 1, 1));
+    let builder = fixture.subblock_builder(1);
+    let tx = builder.build(1020).expect("build tx");
+    let since: u64 = tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, 0x40000000000003ff);
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_builder_full_round() {
+    let mut fixture = PoAFixture::builder(2).subblocks_per_round(2).build();
+    let data = poa_data(1000, 1010, 1, 1);
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(1);
+    assert_eq!(
+        builder.build(1050).err(),
+        Some(Error::RoundNotStarted(1180))
+    );
+
+    let builder = fixture.subblock_builder(0);
+    assert_eq!(
+        builder.build(1050).err(),
+        Some(Error::RoundNotStarted(1090))
+    );
+    let tx = builder.build(1090).expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_builder_custom_outputs() {
+    let mut fixture = PoAFixture::builder(1).build();
+    let poa_output = fixture.poa_cell.output.clone();
+    let extra_output = CellOutput::new_builder()
+        .capacity(500u64.pack())
+        .lock(fixture.owner_scripts[0].clone())
+        .build();
+    fixture.set_poa_data(&poa_data(1000, 1000, 0, 0));
+    let builder = fixture
+        .subblock_builder(0)
+        .poa_output(poa_output, Bytes::from_static(b"new"))
+        .output(extra_output, Bytes::from_static(b"change"));
+    let tx = builder.build(1090).expect("build tx");
+    assert_eq!(tx.outputs().len(), 3);
+    assert_eq!(
+        tx.outputs_data().get(0).unwrap().raw_data(),
+        Bytes::from_static(b"new")
+    );
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_builder_errors() {
+    let mut fixture = PoAFixture::builder(2).build();
+    let poa_data_cell = fixture.poa_data_cell.clone();
+    let builder = SubblockTxBuilder::new(
+        fixture.poa_cell.clone(),
+        poa_data_cell.clone(),
+        fixture.poa_setup_cell.clone(),
+        fixture.owner_scripts[1].clone(),
+    );
+    assert_eq!(builder.build(1100).err(), Some(Error::OwnerCellMissing));
+
+    let owner_cell = fixture.owner_cell(0);
+    let builder = builder.input(owner_cell);
+    assert_eq!(builder.build(1100).err(), Some(Error::OwnerCellMissing));
+
+    let stranger_script = fixture.owner_scripts[0]
+        .clone()
+        .as_builder()
+        .args(random_32bytes().pack())
+        .build();
+    let builder = SubblockTxBuilder::new(
+        fixture.poa_cell.clone(),
+        poa_data_cell.clone(),
+        fixture.poa_setup_cell.clone(),
+        stranger_script,
+    );
+    assert_eq!(builder.build(1100).err(), Some(Error::IdentityNotFound));
+
+    let builder = SubblockTxBuilder::new(
+        fixture.poa_cell.clone(),
+        poa_data_cell,
+        fixture.poa_cell.clone(),
+        fixture.owner_scripts[1].clone(),
+    );
+    assert_eq!(builder.build(1100).err(), Some(Error::PoACellMismatch));
+}
+
+#[test]
+fn test_subblock_builder_paused() {
+    let mut fixture = PoAFixture::builder(2).build();
+    let mut setup = fixture.setup.clone();
+    setup.paused = true;
+    fixture.set_setup(setup);
+    let builder = fixture.subblock_builder(1);
+    assert_eq!(builder.build(1100).err(), Some(Error::Paused));
+}
+
+// Replaces the since value of the PoA cell input.
+fn rewrite_since(tx: &TransactionView, since: u64) -> TransactionView {
+    let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    inputs[0] = inputs[0].clone().as_builder().since(since.pack()).build();
+    tx.as_advanced_builder().set_inputs(inputs).build()
+}
+
+#[test]
+fn test_subtime_to_since() {
+    let fixture = PoAFixture::builder(1).build();
+    assert_eq!(
+        subtime_to_since(&fixture.setup, 1100),
+        Ok(0x400000000000044c)
+    );
+    let fixture = PoAFixture::builder(1)
+        .round_interval_uses_seconds(false)
+        .round_intervals(10)
+        .build();
+    assert_eq!(subtime_to_since(&fixture.setup, 1100), Ok(1100));
+    assert_eq!(
+        subtime_to_since(&fixture.setup, 1 << 56),
+        Err(Error::InvalidSubtime(1 << 56))
+    );
+    assert_eq!(current_subtime(&fixture.setup, 1_600_000_000_000, 120), 120);
+}
+
+#[test]
+fn test_subblock_builder_block_number_same_round() {
+    let mut fixture = PoAFixture::builder(2)
+        .round_interval_uses_seconds(false)
+        .round_intervals(10)
+        .subblocks_per_round(3)
+        .build();
+    fixture.set_poa_data(&poa_data(100, 101, 1, 1));
+    let builder = fixture.subblock_builder(1);
+    let next_data = poa_data(100, 105, 2, 1);
+    assert_eq!(builder.next_poa_data(105), Ok(next_data.clone()));
+    let tx = builder.build(105).expect("build tx");
+    let since: u64 = tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, 105);
+    assert_eq!(
+        tx.outputs_data().get(1).unwrap().raw_data(),
+        serialize_poa_data(&next_data)
+    );
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "subblock_block_number_same_round",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+}
+
+#[test]
+fn test_subblock_builder_block_number_handoff() {
+    let mut fixture = PoAFixture::builder(2)
+        .round_interval_uses_seconds(false)
+        .round_intervals(10)
+        .subblocks_per_round(3)
+        .build();
+    let setup = fixture.setup.clone();
+    let data = poa_data(100, 102, 2, 0);
+    let mut scheduler = RoundScheduler::new(fixture.owner_scripts[1].calc_script_hash().as_bytes());
+    let subtime = current_subtime(&setup, 1_600_000_000_000, 109);
+    assert_eq!(
+        scheduler.should_issue_new_block(subtime, &data, &setup),
+        Ok(State::No)
+    );
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(1);
+    assert_eq!(
+        builder.build(subtime).err(),
+        Some(Error::RoundNotStarted(110))
+    );
+
+    let subtime = current_subtime(&setup, 1_600_000_000_000, 110);
+    assert_eq!(
+        scheduler.should_issue_new_block(subtime, &data, &setup),
+        Ok(State::Yes)
+    );
+    let tx = builder.build(subtime).expect("build tx");
+    let since: u64 = tx.inputs().get(0).unwrap().since().unpack();
+    assert_eq!(since, 110);
+    assert_eq!(
+        tx.outputs_data().get(1).unwrap().raw_data(),
+        serialize_poa_data(&poa_data(110, 110, 0, 1))
+    );
+    fixture.verify(tx);
+
+    // Aggregator 0 waits for a full rotation before its next round.
+    let builder = fixture.subblock_builder(0);
+    assert_eq!(builder.build(119).err(), Some(Error::RoundNotStarted(120)));
+    let tx = builder.build(120).expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_block_number_since_failure() {
+    let mut fixture = PoAFixture::builder(2)
+        .round_interval_uses_seconds(false)
+        .round_intervals(10)
+        .build();
+    fixture.set_poa_data(&poa_data(100, 100, 0, 0));
+    let builder = fixture.subblock_builder(1);
+    let tx = builder.build(110).expect("build tx");
+    let tx = rewrite_since(&tx, SINCE_TIMESTAMP_FLAG | 110);
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidSince));
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "subblock_block_number_since_failure",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        PoaError::InvalidSince.code(),
+        true,
+    );
+}
+
+#[test]
+fn test_subblock_timestamp_since_failure() {
+    let mut fixture = PoAFixture::builder(2).build();
+    fixture.set_poa_data(&poa_data(1000, 1000, 0, 0));
+    let builder = fixture.subblock_builder(1);
+    let tx = builder.build(1100).expect("build tx");
+    let tx = rewrite_since(&tx, 1100);
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidSince));
+}
+
+// Replaces the data of the output PoA data cell.
+fn rewrite_poa_data(tx: &TransactionView, data: &PoAData) -> TransactionView {
+    let mut outputs_data: Vec<_> = tx.outputs_data().into_iter().collect();
+    outputs_data[1] = serialize_poa_data(data).pack();
+    tx.as_advanced_builder()
+        .set_outputs_data(outputs_data)
+        .build()
+}
+
+#[test]
+fn test_subblock_builder_missed_rounds() {
+    let mut fixture = PoAFixture::builder(4).subblocks_per_round(2).build();
+    let genesis_data = PoAData {
+        round_initial_subtime: 1000,
+        subblock_subtime: 1000,
+        subblock_index: 0,
+        aggregator_index: 0,
+        missed_rounds: vec![0; 4],
+    };
+
+    // Aggregators 1 and 2 are offline, aggregator 3 takes over after 3 rounds
+    fixture.set_poa_data(&genesis_data);
+    let builder = fixture.subblock_builder(3);
+    assert_eq!(
+        builder.build(1269).err(),
+        Some(Error::RoundNotStarted(1270))
+    );
+    let data = builder.next_poa_data(1270).expect("next poa data");
+    assert_eq!(data.missed_rounds, vec![0, 1, 1, 0]);
+    let tx = builder.build(1270).expect("build tx");
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "subblock_builder_missed_rounds",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        0,
+        true,
+    );
+
+    // Rotation wraps around: aggregators 0 and 1 miss their rounds before
+    // aggregator 2 is back
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(2);
+    let data = builder.next_poa_data(1540).expect("next poa data");
+    assert_eq!(data.missed_rounds, vec![1, 2, 1, 0]);
+    let tx = builder.build(1540).expect("build tx");
+    fixture.verify(tx);
+
+    // Missed rounds stay the same within a round
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(2);
+    let data = builder.next_poa_data(1550).expect("next poa data");
+    assert_eq!(data.subblock_index, 1);
+    assert_eq!(data.missed_rounds, vec![1, 2, 1, 0]);
+    let tx = builder.build(1550).expect("build tx");
+    fixture.verify(tx);
+
+    // An aggregator following itself makes all others miss a round
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(2);
+    let data = builder.next_poa_data(1900).expect("next poa data");
+    assert_eq!(data.missed_rounds, vec![2, 3, 1, 1]);
+    let tx = builder.build(1900).expect("build tx");
+    fixture.verify(tx);
+
+    let identities: Vec<Bytes> = fixture.setup.identities.clone();
+    assert_eq!(
+        missed_rounds(&fixture.setup, &data),
+        Ok(identities.iter().cloned().zip(vec![2, 3, 1, 1]).collect())
+    );
+    assert_eq!(
+        offline_aggregators(&fixture.setup, &data, 1),
+        Ok(vec![identities[0].clone(), identities[1].clone()])
+    );
+
+    // A gap longer than a full rotation makes all aggregators miss a round
+    // for each rotation skipped on top
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(3);
+    let next_data = builder.next_poa_data(2349).expect("next poa data");
+    assert_eq!(next_data.missed_rounds, vec![2, 3, 1, 1]);
+    let next_data = builder.next_poa_data(2350).expect("next poa data");
+    assert_eq!(next_data.missed_rounds, vec![3, 4, 2, 2]);
+    let tx = builder.build(2350).expect("build tx");
+    fixture.verify(tx);
+
+    let mut untracked_data = genesis_data;
+    untracked_data.missed_rounds = Vec::new();
+    assert_eq!(
+        missed_rounds(&fixture.setup, &untracked_data),
+        Err(Error::InvalidMissedRounds {
+            expected: 4,
+            actual: 0,
+        })
+    );
+}
+
+#[test]
+fn test_subblock_missed_rounds_failure() {
+    let mut fixture = PoAFixture::builder(4).subblocks_per_round(2).build();
+    let last_data = PoAData {
+        round_initial_subtime: 1000,
+        subblock_subtime: 1000,
+        subblock_index: 0,
+        aggregator_index: 0,
+        missed_rounds: vec![5, 0, 0, 0],
+    };
+
+    // Skipped aggregators must be accounted for
+    fixture.set_poa_data(&last_data);
+    let tx = fixture.subblock_builder(3).build(1270).expect("build tx");
+    let mut next_data = fixture
+        .subblock_builder(3)
+        .next_poa_data(1270)
+        .expect("next poa data");
+    next_data.missed_rounds = vec![5, 1, 0, 0];
+    let tx = rewrite_poa_data(&tx, &next_data);
+    assert_eq!(
+        fixture.verify_error(tx.clone()),
+        Some(PoaError::InvalidMissedRounds)
+    );
+
+    // dump raw test tx files
+    let setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    let tx = fixture.context.complete_tx(tx);
+    write_native_setup(
+        "subblock_missed_rounds_failure",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &setup,
+        PoaError::InvalidMissedRounds.code(),
+        true,
+    );
+
+    // Counters of aggregators that are not skipped cannot change
+    next_data.missed_rounds = vec![0, 1, 1, 0];
+    let tx = fixture.subblock_builder(3).build(1270).expect("build tx");
+    let tx = rewrite_poa_data(&tx, &next_data);
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::InvalidMissedRounds)
+    );
+
+    // Skipped rotations must be accounted for as well
+    let tx = fixture.subblock_builder(3).build(1630).expect("build tx");
+    let mut rotation_data = fixture
+        .subblock_builder(3)
+        .next_poa_data(1630)
+        .expect("next poa data");
+    assert_eq!(rotation_data.missed_rounds, vec![6, 2, 2, 1]);
+    fixture.verify(tx.clone());
+    rotation_data.missed_rounds = vec![5, 1, 1, 0];
+    let tx = rewrite_poa_data(&tx, &rotation_data);
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::InvalidMissedRounds)
+    );
+
+    // Missed rounds cannot change within a round
+    let tx = fixture.subblock_builder(0).build(1010).expect("build tx");
+    let mut next_data = fixture
+        .subblock_builder(0)
+        .next_poa_data(1010)
+        .expect("next poa data");
+    next_data.missed_rounds = vec![0, 0, 0, 0];
+    let tx = rewrite_poa_data(&tx, &next_data);
+    assert_eq!(
+        fixture.verify_error(tx),
+        Some(PoaError::InvalidMissedRounds)
+    );
+
+    // Tracking cannot be dropped
+    let tx = fixture.subblock_builder(3).build(1270).expect("build tx");
+    next_data.missed_rounds = Vec::new();
+    let tx = rewrite_poa_data(&tx, &next_data);
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidPoAData));
+
+    // Missed rounds must be tracked for all aggregators
+    let mut partial_data = last_data.clone();
+    partial_data.missed_rounds = vec![0; 3];
+    fixture.set_poa_data(&partial_data);
+    assert_eq!(
+        fixture.subblock_builder(3).build(1270).err(),
+        Some(Error::InvalidMissedRounds {
+            expected: 4,
+            actual: 3,
+        })
+    );
+    fixture.set_poa_data(&last_data);
+    let tx = fixture.subblock_builder(3).build(1270).expect("build tx");
+    let mut tx_inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    let partial_cell = fixture.poa_data_cell_with(serialize_poa_data(&partial_data));
+    tx_inputs[1] = partial_cell.input(0);
+    let tx = tx.as_advanced_builder().set_inputs(tx_inputs).build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidPoAData));
+}
+
+#[test]
+fn test_subblock_builder_weighted_rounds() {
+    let mut fixture = PoAFixture::builder(3).build();
+    let mut setup = fixture.setup.clone();
+    setup.version = POA_SETUP_V1;
+    setup.weights = vec![3, 1, 2];
+    fixture.set_setup(setup);
+    let data = poa_data(1000, 1000, 0, 0);
+
+    // Aggregator 0 has weight 3: its round lasts 270 and allows 3 subblocks
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(0);
+    let data = builder.next_poa_data(1200).expect("next poa data");
+    assert_eq!(data.round_initial_subtime, 1000);
+    assert_eq!(data.subblock_index, 1);
+    let tx = builder.build(1200).expect("build tx");
+    let tx = fixture.verify(tx);
+
+    // dump raw test tx files
+    let running_setup = RunningSetup {
+        is_lock_script: true,
+        is_output: false,
+        script_index: 0,
+        native_binaries: HashMap::default(),
+    };
+    write_native_setup(
+        "subblock_builder_weighted_rounds",
+        "poa_sim",
+        &tx,
+        &fixture.context,
+        &running_setup,
+        0,
+        true,
+    );
+
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(0);
+    let data = builder.next_poa_data(1269).expect("next poa data");
+    assert_eq!(data.subblock_index, 2);
+    let tx = builder.build(1269).expect("build tx");
+    fixture.verify(tx);
+
+    // Round is full, aggregator 0 waits for the whole weighted rotation
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(0);
+    assert_eq!(
+        builder.build(1269).err(),
+        Some(Error::RoundNotStarted(1540))
+    );
+    let builder = fixture.subblock_builder(1);
+    assert_eq!(
+        builder.build(1269).err(),
+        Some(Error::RoundNotStarted(1270))
+    );
+
+    // Aggregator 1 is skipped, aggregator 2 waits for rounds of 0 and 1
+    let builder = fixture.subblock_builder(2);
+    assert_eq!(
+        builder.build(1300).err(),
+        Some(Error::RoundNotStarted(1360))
+    );
+    let data = builder.next_poa_data(1360).expect("next poa data");
+    assert_eq!(data.aggregator_index, 2);
+    let tx = builder.build(1360).expect("build tx");
+    fixture.verify(tx);
+
+    // Handoff wraps around past the last aggregator: aggregator 1 waits for
+    // rounds of 2 and 0
+    fixture.set_poa_data(&data);
+    let builder = fixture.subblock_builder(1);
+    assert_eq!(
+        builder.build(1809).err(),
+        Some(Error::RoundNotStarted(1810))
+    );
+    let tx = builder.build(1810).expect("build tx");
+    fixture.verify(tx);
+}
+
+#[test]
+fn test_subblock_weighted_round_failure() {
+    let mut fixture = PoAFixture::builder(3).build();
+    let mut setup = fixture.setup.clone();
+    setup.version = POA_SETUP_V1;
+    setup.weights = vec![3, 1, 2];
+    fixture.set_setup(setup);
+    let data = poa_data(1000, 1000, 0, 0);
+
+    // Aggregator 2 cannot start its round before the weighted rounds of
+    // aggregators 0 and 1 have passed
+    fixture.set_poa_data(&data);
+    let tx = fixture.subblock_builder(2).build(1360).expect("build tx");
+    let mut next_data = fixture
+        .subblock_builder(2)
+        .next_poa_data(1360)
+        .expect("next poa data");
+    next_data.round_initial_subtime = 1280;
+    next_data.subblock_subtime = 1280;
+    let tx = rewrite_since(
+        &rewrite_poa_data(&tx, &next_data),
+        SINCE_TIMESTAMP_FLAG | 1280,
+    );
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::RoundNotStarted));
+
+    // Setup cells with invalid weights are rejected
+    let tx = fixture.subblock_builder(1).build(1270).expect("build tx");
+    let mut setup_data = fixture.poa_setup_cell.data.to_vec();
+    *setup_data.last_mut().unwrap() = 0;
+    let invalid_setup_cell = fixture.cell(fixture.poa_setup_cell.output.clone(), setup_data.into());
+    let mut cell_deps: Vec<CellDep> = tx.cell_deps().into_iter().collect();
+    cell_deps[0] = invalid_setup_cell.code_dep();
+    let tx = tx.as_advanced_builder().set_cell_deps(cell_deps).build();
+    assert_eq!(fixture.verify_error(tx), Some(PoaError::InvalidWeights));
+}